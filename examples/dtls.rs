@@ -0,0 +1,82 @@
+use udp_netmsg::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::{thread, time};
+
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::x509::{X509, X509NameBuilder};
+
+#[derive(Serialize, Deserialize, Debug)]
+struct UpdatePos {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub text: String
+}
+
+//Real deployments would load their certificates from files instead of creating them on startup
+fn self_signed(name: &str) -> (Vec<u8>, Vec<u8>) {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+    cert.set_subject_name(&subject).unwrap();
+    cert.set_issuer_name(&subject).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+    (cert.build().to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+}
+
+fn main() {
+    let (server_cert, server_key) = self_signed("server");
+    let (client_cert, client_key) = self_signed("client");
+
+    //Each side only accepts the certificate of the other
+    let server = Builder::init()
+        .socket(String::from("127.0.0.1:12002"))
+        .dtls(DtlsConfig {
+            certificate: server_cert.clone(),
+            private_key: server_key,
+            trusted: client_cert.clone(),
+            handshake_timeout: time::Duration::from_secs(5),
+            max_handshakes: 16
+        })
+        .start::<JSON>()
+        .unwrap();
+    let client = Builder::init()
+        .socket(String::from("127.0.0.1:12003"))
+        .dtls(DtlsConfig {
+            certificate: client_cert,
+            private_key: client_key,
+            trusted: server_cert,
+            handshake_timeout: time::Duration::from_secs(5),
+            max_handshakes: 16
+        })
+        .start::<JSON>()
+        .unwrap();
+
+    let move_entity = UpdatePos {x: 16f32, y: 17f32, z: 20f32, text: String::from("Hello! I Moved")};
+
+    //The datagram is held until the handshake completes, then sent encrypted
+    match client.send(move_entity, String::from("127.0.0.1:12002")) {
+        Ok(_) => println!("datagram sent!"),
+        Err(e) => println!("datagram failed to send because: {}", e)
+    }
+
+    thread::sleep(time::Duration::from_millis(500));
+
+    let (from_addr, update_pos_message) = server.get::<UpdatePos>().unwrap();
+
+    println!("Message Received from {}!: {:?}", from_addr, update_pos_message);
+}
@@ -8,6 +8,7 @@ struct Message {
     message: String,
 }
 
+#[allow(clippy::single_match)]
 fn main() 
 {
     let manager = Builder::init()
@@ -1,6 +1,5 @@
 use udp_netmsg::prelude::*;
 use serde::{Serialize, Deserialize};
-use std::sync::Arc;
 
 #[derive(Serialize, Deserialize)]
 struct Message {
@@ -8,14 +7,14 @@ struct Message {
     message: String,
 }
 
+#[allow(clippy::single_match)]
 fn main() 
 {
     let manager = Builder::init()
         .socket(String::from("0.0.0.0:40062"))
         //.use_ids(false)
-        .start::<JSON>().unwrap();
+        .start_shared::<JSON>().unwrap();
 
-    let manager = Arc::from(manager);
     let man = manager.clone();
 
     std::thread::spawn(move || {
@@ -0,0 +1,48 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Source of the current time for the time-dependent features of a UdpManager, such as 
+/// message_ttl and the send circuit breaker (see Builder::clock).
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Clock reading the system's monotonic time. Used unless another clock is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        return Instant::now();
+    }
+}
+
+/// Clock that only moves when advanced, so tests can skip ahead instead of sleeping.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<Instant>
+}
+
+impl MockClock {
+    /// Creates a clock stopped at the current instant.
+    pub fn new() -> MockClock {
+        return MockClock { now: Mutex::from(Instant::now()) };
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        return MockClock::new();
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        return *self.now.lock().unwrap_or_else(PoisonError::into_inner);
+    }
+}
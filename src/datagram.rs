@@ -0,0 +1,9 @@
+/// Trait from earlier versions of the crate, where every datagram supplied its own header.
+/// 
+/// Types implementing it keep using their header as the id with UdpManager::send_with_header and
+/// UdpManager::get_with_header, instead of the id generated from the type name, so they can talk to
+/// programs built against those versions.
+pub trait Datagram {
+    /// Id sent ahead of the payload
+    fn header()->u32;
+}
@@ -0,0 +1,318 @@
+use std::collections::{hash_map, HashMap, VecDeque};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use openssl::error::ErrorStack;
+use openssl::ex_data::Index;
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::{PKey, Private};
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use openssl::ssl::{ErrorCode, Ssl, SslContext, SslMethod, SslOptions, SslRef, SslStream, SslVerifyMode};
+use openssl::x509::X509;
+use openssl::x509::store::X509StoreBuilder;
+
+use crate::transport::Transport;
+
+/// Largest DTLS record sent, kept below common path MTUs so records are not fragmented.
+const MTU: u32 = 1200;
+
+/// Largest datagram received from a peer, including the DTLS record overhead.
+const MAX_RECORD_LEN: usize = 65536;
+
+/// Certificates used to secure datagrams with DTLS (see Builder::dtls).
+///
+/// Both sides of every session authenticate each other: a peer is only accepted if it presents a
+/// certificate signed by one of the trusted certificates, so each manager needs a certificate of
+/// its own. A self-signed certificate can be trusted directly.
+#[derive(Clone)]
+pub struct DtlsConfig {
+    /// PEM encoded certificate presented to peers, followed by any intermediate certificates
+    pub certificate: Vec<u8>,
+    /// PEM encoded private key of the certificate
+    pub private_key: Vec<u8>,
+    /// PEM encoded certificates trusted to sign the certificates of peers
+    pub trusted: Vec<u8>,
+    /// Longest time a handshake may take before it is abandoned, along with the datagrams waiting for it
+    pub handshake_timeout: Duration,
+    /// Most handshakes started by peers that may be in progress at once. Datagrams from further
+    /// unknown peers are dropped until one of them completes or is abandoned.
+    pub max_handshakes: usize
+}
+
+/// Transport encrypting and authenticating every datagram with a DTLS session per peer.
+///
+/// The first datagram sent to a peer starts a handshake as the client, while the first datagram
+/// received from an unknown peer starts one as the server. The server answers the first hello of a
+/// peer with a cookie (HelloVerifyRequest) that the peer must echo back, so only peers able to
+/// receive datagrams at their address get further than that. Datagrams sent during the handshake are
+/// held until it completes, so the receiving thread of the manager must be running for sends to
+/// reach the peer. Datagrams that only carry handshake records are not handed to the manager;
+/// recv_from returns WouldBlock for them instead.
+///
+/// Empty datagrams carry no data worth protecting and are sent and received unencrypted, as the
+/// manager sends them to its own socket to wake its receiving thread.
+pub struct DtlsTransport {
+    udp: Arc<UdpSocket>,
+    context: SslContext,
+    handshake_timeout: Duration,
+    max_handshakes: usize,
+    peer_index: Index<Ssl, SocketAddr>,
+    sessions: Mutex<HashMap<SocketAddr, Session>>,
+    record: Mutex<Vec<u8>>
+}
+
+/// DTLS session with a single peer
+struct Session {
+    stream: SslStream<Channel>,
+    established: bool,
+    pending: Vec<Vec<u8>>,
+    started: Instant
+}
+
+/// Carries the records of a session: received records are queued by the transport, while written
+/// records are sent straight to the peer.
+struct Channel {
+    udp: Arc<UdpSocket>,
+    peer: SocketAddr,
+    incoming: VecDeque<Vec<u8>>
+}
+
+impl Read for Channel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let record = self.incoming.pop_front().ok_or_else(|| io::Error::from(ErrorKind::WouldBlock))?;
+        let len = record.len().min(buf.len());
+        buf[..len].copy_from_slice(&record[..len]);
+        return Ok(len);
+    }
+}
+
+impl Write for Channel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return self.udp.send_to(buf, self.peer);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+fn config_error(e: ErrorStack) -> io::Error {
+    return io::Error::new(ErrorKind::InvalidInput, e);
+}
+
+/// Cookie proving that a peer received a datagram sent to its address
+fn cookie(secret: &PKey<Private>, ssl: &SslRef, peer_index: Index<Ssl, SocketAddr>) -> Result<Vec<u8>, ErrorStack> {
+    let mut signer = Signer::new(MessageDigest::sha256(), secret)?;
+    if let Some(peer) = ssl.ex_data(peer_index) {
+        signer.update(peer.to_string().as_bytes())?;
+    }
+    return signer.sign_to_vec();
+}
+
+fn session_error(peer: SocketAddr, e: openssl::ssl::Error) -> io::Error {
+    return io::Error::new(ErrorKind::InvalidData, format!("DTLS session with {} failed: {}", peer, e));
+}
+
+impl DtlsTransport {
+    /// Wraps a bound socket, which must not be used directly afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind InvalidInput if a certificate or the private key cannot be parsed,
+    /// or the private key does not match the certificate, and an error of kind Other if no secret
+    /// could be generated for the cookies.
+    pub fn new(udp: UdpSocket, config: &DtlsConfig) -> Result<DtlsTransport, io::Error> {
+        let mut chain = X509::stack_from_pem(&config.certificate).map_err(config_error)?.into_iter();
+        let certificate = chain.next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "No certificate to present to peers"))?;
+
+        let mut trusted = X509StoreBuilder::new().map_err(config_error)?;
+        for cert in X509::stack_from_pem(&config.trusted).map_err(config_error)? {
+            trusted.add_cert(cert).map_err(config_error)?;
+        }
+
+        let mut context = SslContext::builder(SslMethod::dtls()).map_err(config_error)?;
+        context.set_certificate(&certificate).map_err(config_error)?;
+        for cert in chain {
+            context.add_extra_chain_cert(cert).map_err(config_error)?;
+        }
+        let private_key = PKey::private_key_from_pem(&config.private_key).map_err(config_error)?;
+        context.set_private_key(&private_key).map_err(config_error)?;
+        context.check_private_key().map_err(config_error)?;
+        context.set_cert_store(trusted.build());
+        context.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        context.set_options(SslOptions::NO_QUERY_MTU | SslOptions::COOKIE_EXCHANGE);
+
+        let mut secret = [0; 32];
+        rand_bytes(&mut secret).map_err(io::Error::other)?;
+        let secret = PKey::hmac(&secret).map_err(config_error)?;
+        let peer_index = Ssl::new_ex_index().map_err(config_error)?;
+        let verify_secret = secret.clone();
+        context.set_cookie_generate_cb(move |ssl, buf| {
+            let cookie = cookie(&secret, ssl, peer_index)?;
+            buf[..cookie.len()].copy_from_slice(&cookie);
+            return Ok(cookie.len());
+        });
+        context.set_cookie_verify_cb(move |ssl, received| {
+            return match cookie(&verify_secret, ssl, peer_index) {
+                Ok(cookie) => cookie.len() == received.len() && memcmp::eq(&cookie, received),
+                Err(_) => false
+            };
+        });
+
+        return Ok(DtlsTransport {
+            udp: Arc::new(udp),
+            context: context.build(),
+            handshake_timeout: config.handshake_timeout,
+            max_handshakes: config.max_handshakes,
+            peer_index,
+            sessions: Mutex::from(HashMap::new()),
+            record: Mutex::from(vec![0; MAX_RECORD_LEN])
+        });
+    }
+
+    fn session(&self, peer: SocketAddr, client: bool) -> Result<Session, io::Error> {
+        let mut ssl = Ssl::new(&self.context).map_err(config_error)?;
+        ssl.set_mtu(MTU).map_err(config_error)?;
+        ssl.set_ex_data(self.peer_index, peer);
+        match client {
+            true => ssl.set_connect_state(),
+            false => ssl.set_accept_state()
+        }
+
+        let channel = Channel { udp: self.udp.clone(), peer, incoming: VecDeque::new() };
+        return Ok(Session {
+            stream: SslStream::new(ssl, channel).map_err(config_error)?,
+            established: false,
+            pending: vec![],
+            started: Instant::now()
+        });
+    }
+
+    /// Abandons the handshakes that took longer than handshake_timeout
+    fn expire(&self, sessions: &mut HashMap<SocketAddr, Session>) {
+        sessions.retain(|_, session| session.established || session.started.elapsed() < self.handshake_timeout);
+    }
+}
+
+impl Session {
+    /// Continues the handshake with the records received so far, sending the datagrams held for
+    /// it once it completes.
+    fn handshake(&mut self, peer: SocketAddr) -> Result<(), io::Error> {
+        if self.established {
+            return Ok(());
+        }
+
+        match self.stream.do_handshake() {
+            Ok(()) => self.established = true,
+            Err(ref e) if e.code() == ErrorCode::WANT_READ => return Ok(()),
+            Err(e) => return Err(session_error(peer, e))
+        }
+
+        for datagram in std::mem::take(&mut self.pending) {
+            self.stream.ssl_write(&datagram).map_err(|e| session_error(peer, e))?;
+        }
+        return Ok(());
+    }
+}
+
+impl Transport for DtlsTransport {
+    /// # Panics
+    ///
+    /// This will panic if the lock becomes poisioned.
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, io::Error> {
+        if buf.is_empty() {
+            return self.udp.send_to(buf, addr);
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions);
+
+        let session = match sessions.entry(addr) {
+            hash_map::Entry::Occupied(session) => session.into_mut(),
+            hash_map::Entry::Vacant(vacant) => vacant.insert(self.session(addr, true)?)
+        };
+
+        if session.established {
+            return session.stream.ssl_write(buf).map_err(|e| session_error(addr, e));
+        }
+
+        session.pending.push(buf.to_vec());
+        if let Err(e) = session.handshake(addr) {
+            sessions.remove(&addr);
+            return Err(e);
+        }
+        return Ok(buf.len());
+    }
+
+    /// # Panics
+    ///
+    /// This will panic if the lock becomes poisioned.
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), io::Error> {
+        let mut record = self.record.lock().unwrap();
+        let (len, addr) = match self.udp.recv_from(&mut record) {
+            Ok(received) => received,
+            Err(e) => {
+                //Lets OpenSSL retransmit handshake records once its timer expires
+                let mut sessions = self.sessions.lock().unwrap();
+                self.expire(&mut sessions);
+                sessions.retain(|peer, session| session.handshake(*peer).is_ok());
+                return Err(e);
+            }
+        };
+        if len == 0 {
+            return Ok((0, addr));
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire(&mut sessions);
+
+        if !sessions.contains_key(&addr) {
+            let handshakes = sessions.values().filter(|session| !session.established && session.stream.ssl().is_server()).count();
+            if handshakes >= self.max_handshakes {
+                return Err(io::Error::from(ErrorKind::WouldBlock));
+            }
+        }
+
+        let session = match sessions.entry(addr) {
+            hash_map::Entry::Occupied(session) => session.into_mut(),
+            hash_map::Entry::Vacant(vacant) => vacant.insert(self.session(addr, false)?)
+        };
+        session.stream.get_mut().incoming.push_back(record[..len].to_vec());
+        drop(record);
+
+        if let Err(e) = session.handshake(addr) {
+            sessions.remove(&addr);
+            return Err(e);
+        }
+        if !session.established {
+            return Err(io::Error::from(ErrorKind::WouldBlock));
+        }
+
+        return match session.stream.ssl_read(buf) {
+            Ok(len) => Ok((len, addr)),
+            Err(ref e) if e.code() == ErrorCode::WANT_READ => Err(io::Error::from(ErrorKind::WouldBlock)),
+            Err(e) => {
+                //The peer closed the session or sent a record that failed authentication
+                sessions.remove(&addr);
+                Err(session_error(addr, e))
+            }
+        };
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        return self.udp.local_addr();
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), io::Error> {
+        return self.udp.set_read_timeout(timeout);
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), io::Error> {
+        return self.udp.set_nonblocking(nonblocking);
+    }
+}
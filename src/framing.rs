@@ -0,0 +1,136 @@
+use std::io::{self, Read, Write, ErrorKind};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// Byte order used by the numeric and string helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little
+}
+
+/// Number that can be written and read in either byte order.
+pub trait Num: Sized {
+    fn write_to<W: Write + ?Sized>(self, wtr: &mut W, endian: Endian) -> io::Result<()>;
+    fn read_from<R: Read + ?Sized>(rdr: &mut R, endian: Endian) -> io::Result<Self>;
+}
+
+impl Num for u8 {
+    fn write_to<W: Write + ?Sized>(self, wtr: &mut W, _: Endian) -> io::Result<()> {
+        return wtr.write_all(&[self]);
+    }
+
+    fn read_from<R: Read + ?Sized>(rdr: &mut R, _: Endian) -> io::Result<u8> {
+        let mut buf = [0u8; 1];
+        rdr.read_exact(&mut buf)?;
+        return Ok(buf[0]);
+    }
+}
+
+impl Num for i8 {
+    fn write_to<W: Write + ?Sized>(self, wtr: &mut W, endian: Endian) -> io::Result<()> {
+        return (self as u8).write_to(wtr, endian);
+    }
+
+    fn read_from<R: Read + ?Sized>(rdr: &mut R, endian: Endian) -> io::Result<i8> {
+        return u8::read_from(rdr, endian).map(|n| n as i8);
+    }
+}
+
+macro_rules! impl_num {
+    ($($ty:ty, $write:ident, $read:ident;)*) => {
+        $(
+            impl Num for $ty {
+                fn write_to<W: Write + ?Sized>(self, wtr: &mut W, endian: Endian) -> io::Result<()> {
+                    return match endian {
+                        Endian::Big => wtr.$write::<BigEndian>(self),
+                        Endian::Little => wtr.$write::<LittleEndian>(self)
+                    };
+                }
+
+                fn read_from<R: Read + ?Sized>(rdr: &mut R, endian: Endian) -> io::Result<$ty> {
+                    return match endian {
+                        Endian::Big => rdr.$read::<BigEndian>(),
+                        Endian::Little => rdr.$read::<LittleEndian>()
+                    };
+                }
+            }
+        )*
+    };
+}
+
+impl_num! {
+    u16, write_u16, read_u16;
+    u32, write_u32, read_u32;
+    u64, write_u64, read_u64;
+    i16, write_i16, read_i16;
+    i32, write_i32, read_i32;
+    i64, write_i64, read_i64;
+    f32, write_f32, read_f32;
+    f64, write_f64, read_f64;
+}
+
+/// Writes numbers in a chosen byte order, for building datagrams by hand (use_ids(false)).
+///
+/// ```rust
+/// use udp_netmsg::framing::{Endian, WriteNum, ReadNum};
+///
+/// let mut buf = Vec::new();
+/// buf.write_num(7u32, Endian::Little).unwrap();
+/// assert_eq!(buf, vec![7, 0, 0, 0]);
+/// assert_eq!(buf.as_slice().read_num::<u32>(Endian::Little).unwrap(), 7);
+/// ```
+pub trait WriteNum: Write {
+    fn write_num<N: Num>(&mut self, n: N, endian: Endian) -> io::Result<()> {
+        return n.write_to(self, endian);
+    }
+}
+
+impl<W: Write + ?Sized> WriteNum for W {}
+
+/// Reads numbers written with WriteNum.
+///
+/// # Errors
+///
+/// Returns an error of kind UnexpectedEof if the reader ends before the number does.
+pub trait ReadNum: Read {
+    fn read_num<N: Num>(&mut self, endian: Endian) -> io::Result<N> {
+        return N::read_from(self, endian);
+    }
+}
+
+impl<R: Read + ?Sized> ReadNum for R {}
+
+/// Writes strings prefixed by their length in bytes as a u32 in the chosen byte order.
+pub trait WriteString: Write {
+    /// # Errors
+    ///
+    /// Returns an error of kind InvalidInput if the string is longer than u32::MAX bytes.
+    fn write_string(&mut self, s: &str, endian: Endian) -> io::Result<()> {
+        if s.len() > u32::MAX as usize {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "string is longer than u32::MAX bytes"));
+        }
+        self.write_num(s.len() as u32, endian)?;
+        return self.write_all(s.as_bytes());
+    }
+}
+
+impl<W: Write + ?Sized> WriteString for W {}
+
+/// Reads strings written with WriteString.
+pub trait ReadString: Read {
+    /// # Errors
+    ///
+    /// Returns an error of kind UnexpectedEof if the reader ends before the string does, or of
+    /// kind InvalidData if the string is not valid UTF-8.
+    fn read_string(&mut self, endian: Endian) -> io::Result<String> {
+        let len: u32 = self.read_num(endian)?;
+        let mut buf = Vec::new();
+        self.take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len as usize {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "string ends past the end of the buffer"));
+        }
+        return String::from_utf8(buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e));
+    }
+}
+
+impl<R: Read + ?Sized> ReadString for R {}
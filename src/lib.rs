@@ -20,10 +20,10 @@
 //! 
 //! [here]: https://github.com/Javagedes/udp_netmsg/blob/master/src/serdes.rs
 //! 
-//! Convenience Implementations:
-//! - JSON
-//! - Bincode
-//! - YAML
+//! Convenience Implementations, each behind the cargo feature of the same name:
+//! - JSON (json, enabled by default)
+//! - Bincode (bincode)
+//! - YAML (yaml)
 //! 
 //! ## Example
 //! 
@@ -39,6 +39,7 @@
 //!     pub z: f32
 //! }
 //! 
+//! # #[cfg(feature = "json")]
 //! fn main() {
 //!     let mut net_msg = Builder::init().start::<JSON>().unwrap(); 
 //!     let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
@@ -48,6 +49,8 @@
 //! 
 //!     net_msg.get::<UpdatePos>().unwrap();
 //! }
+//! # #[cfg(not(feature = "json"))]
+//! # fn main() {}
 //! ```
 
 ///Traits used for implementing SerDes formats and operations
@@ -56,6 +59,33 @@ pub mod serdes;
 ///UDP manager and associated methods
 pub mod manager;
 
+///Time sources for the time-dependent features of the UDP manager
+pub mod clock;
+
+///Transports carrying datagrams for the UDP manager
+pub mod transport;
+
+///Compatibility with header-based datagrams of earlier versions
+pub mod datagram;
+
+///Datagram types declaring their own id
+pub mod msg_id;
+
+///Helpers for writing and reading numbers and strings in datagrams built by hand
+pub mod framing;
+
+///DTLS encryption of datagrams
+#[cfg(feature = "dtls")]
+pub mod dtls;
+
+///Network simulation for testing
+#[cfg(feature = "netsim")]
+pub mod netsim;
+
+///Helpers for testing code built on the UDP manager
+#[cfg(feature = "testing")]
+pub mod testing;
+
 #[doc(hidden)]
 pub mod prelude;
 
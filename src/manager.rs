@@ -1,26 +1,258 @@
 use std::any::TypeId;
-use std::collections::{hash_map, HashMap, VecDeque};
+use std::collections::{hash_map, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 use std::hash::{Hash, Hasher};
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Read, Write};
 use std::marker::PhantomData;
 use std::net::{UdpSocket, ToSocketAddrs, SocketAddr};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use serde::{de, ser};
 use byteorder::{ByteOrder, BigEndian, WriteBytesExt};
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
 
 use crate::util::ThreadSafe;
-use crate::serdes::SerDesType;
+use crate::datagram::Datagram;
+use crate::msg_id::MsgId;
+use crate::transport::{Closed, Transport};
+use crate::clock::{Clock, SystemClock};
+#[cfg(feature = "netsim")]
+use crate::netsim::SimConfig;
+#[cfg(feature = "dtls")]
+use crate::dtls::{DtlsConfig, DtlsTransport};
+#[cfg(feature = "netsim")]
+use std::collections::BinaryHeap;
+use crate::serdes::{self, SerDesType, DynSerDes, Dynamic, BorrowSerDes, Borrowable};
+
+/// Ids reserved for the crate's internal control messages. They cannot be assigned with set_id
+/// and are never produced by automatic id generation.
+pub const RESERVED_IDS: std::ops::RangeInclusive<u64> = 0..=255;
+
+/// Bit set on every automatically generated id.
+const AUTO_ID_BIT: u64 = 1 << 63;
+
+/// Id of datagrams sent with send_reliable. Their payload is the sequence number and id of the 
+/// wrapped message, followed by its payload.
+const RELIABLE_ID: u64 = 2;
+
+/// Id of the acknowledgements replied to reliable datagrams, echoing their sequence number.
+const ACK_ID: u64 = 3;
+
+/// Id of datagrams sent with send_with_origin. Their payload is the id of the wrapped message and
+/// the address it originates from, followed by its payload.
+const ORIGIN_ID: u64 = 4;
+
+/// Most acknowledgements kept for send_reliable to collect. The oldest are discarded first.
+const MAX_PENDING_ACKS: usize = 64;
+
+/// Longest time dropping a manager waits for queued outgoing datagrams to be sent.
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Size of the length prefix written after the id of every datagram (see Builder::length_prefix).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthPrefix 
+{
+    None,
+    U16,
+    U32
+}
+
+/// Determines which addresses a host name given to the Builder struct resolves to (see Builder::resolve).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveMode 
+{
+    /// Only the first resolved address is tried
+    First,
+    /// Only IPv4 addresses are tried, in the order they resolved
+    Ipv4Only,
+    /// Only IPv6 addresses are tried, in the order they resolved
+    Ipv6Only,
+    /// Every resolved address is tried in order until one can be bound
+    All
+}
+
+/// Metadata fields carried by every datagram between its header and payload (see Builder::envelope).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EnvelopeSpec 
+{
+    /// A big endian u32, such as the simulation tick the datagram was sent on
+    pub tick: bool,
+    /// A single byte, such as the channel of a multiplayer session
+    pub channel: bool
+}
+
+/// Metadata sent with send_env and provided by get_env. Fields not enabled by the EnvelopeSpec 
+/// are not sent and are received as 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EnvelopeMeta 
+{
+    pub tick: u32,
+    pub channel: u8
+}
+
+impl EnvelopeSpec {
+
+    /// Number of bytes the envelope occupies in every datagram
+    fn len(&self) -> usize 
+    {
+        return 4 * self.tick as usize + self.channel as usize;
+    }
+
+    fn write(&self, meta: EnvelopeMeta, wtr: &mut Vec<u8>) 
+    {
+        if self.tick {
+            wtr.extend_from_slice(&meta.tick.to_be_bytes());
+        }
+        if self.channel {
+            wtr.push(meta.channel);
+        }
+    }
+
+    /// Strips the envelope from the front of the buffer. Returns None if the buffer is too short.
+    fn read(&self, buffer: &mut Vec<u8>) -> Option<EnvelopeMeta> 
+    {
+        if buffer.len() < self.len() {
+            return None;
+        }
+
+        let envelope: Vec<u8> = buffer.drain(..self.len()).collect();
+        let mut meta = EnvelopeMeta::default();
+        if self.tick {
+            meta.tick = BigEndian::read_u32(&envelope[..4]);
+        }
+        if self.channel {
+            meta.channel = envelope[envelope.len() - 1];
+        }
+        return Some(meta);
+    }
+}
+
+/// Describes why starting a manager failed, such as the address that could not be bound.
+/// 
+/// Builder::start keeps returning a std::io::Error of the original kind, carrying a BuilderError 
+/// that can be reached with get_ref and downcast_ref.
+#[derive(Debug)]
+pub enum BuilderError 
+{
+    /// The address could not be resolved, or resolved to no address allowed by the ResolveMode
+    Resolve { addr: String, source: std::io::Error },
+    /// The socket could not be bound to the address
+    Bind { addr: String, source: std::io::Error },
+    /// A socket option (non_blocking, read_timeout, dscp, ...) could not be applied to the socket 
+    /// bound to the address
+    Configure { addr: String, source: std::io::Error },
+    /// The buffer_len cannot hold the headers of a datagram (id, length prefix, compression flag, envelope and checksum)
+    BufferTooSmall { buffer_len: usize, required: usize }
+}
+
+impl BuilderError 
+{
+    fn resolve(addr: &str, source: std::io::Error)->std::io::Error 
+    {
+        return std::io::Error::new(source.kind(), BuilderError::Resolve { addr: String::from(addr), source });
+    }
+
+    fn bind(addr: &str, source: std::io::Error)->std::io::Error 
+    {
+        return std::io::Error::new(source.kind(), BuilderError::Bind { addr: String::from(addr), source });
+    }
+
+    fn configure(addr: &str, source: std::io::Error)->std::io::Error 
+    {
+        return std::io::Error::new(source.kind(), BuilderError::Configure { addr: String::from(addr), source });
+    }
+
+    fn buffer_too_small(buffer_len: usize, required: usize)->std::io::Error 
+    {
+        return std::io::Error::new(ErrorKind::InvalidInput, BuilderError::BufferTooSmall { buffer_len, required });
+    }
+}
+
+impl std::fmt::Display for BuilderError 
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuilderError::Resolve { addr, source } => write!(f, "failed to resolve {}: {}", addr, source),
+            BuilderError::Bind { addr, source } => write!(f, "failed to bind {}: {}", addr, source),
+            BuilderError::Configure { addr, source } => write!(f, "failed to configure the socket bound to {}: {}", addr, source),
+            BuilderError::BufferTooSmall { buffer_len, required } => 
+                write!(f, "buffer_len of {} bytes cannot hold the {} byte datagram header", buffer_len, required)
+        }
+    }
+}
+
+impl std::error::Error for BuilderError 
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuilderError::Resolve { source, .. } | BuilderError::Bind { source, .. } | 
+            BuilderError::Configure { source, .. } => Some(source),
+            BuilderError::BufferTooSmall { .. } => None
+        }
+    }
+}
 
 /// Helper struct for configuring the UDP Manager.
 pub struct Builder 
 {
     buffer_len: usize,
     socket: String,
+    sockets: Vec<String>,
+    send_socket: Option<String>,
+    transport: Option<Box<dyn Transport>>,
     non_blocking: bool,
     read_timeout: Option<std::time::Duration>,
+    shutdown_timeout: Option<Duration>,
+    detach_on_drop: bool,
+    idle_sleep: Duration,
     use_ids: bool,
+    id_hash_seed: Option<(u64, u64)>,
+    dscp: Option<u8>,
+    preserve_global_order: bool,
+    queue_capacity: usize,
+    max_queue_len: Option<usize>,
+    max_queues: Option<usize>,
+    per_sender_queues: bool,
+    block_send_when_full: bool,
+    capture_dead_letters: bool,
+    get_retains_on_error: bool,
+    message_ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    sweep_interval: Option<Duration>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    bind_device: Option<String>,
+    recv_ttl: bool,
+    reuse_port: bool,
+    resolve: ResolveMode,
+    send_buffer_hint: usize,
+    on_recv_error: Option<Arc<RecvErrorFn>>,
+    on_raw: Option<Arc<RawFn>>,
+    on_unknown: Option<Arc<UnknownFn>>,
+    on_version_mismatch: Option<Arc<VersionMismatchFn>>,
+    async_send: bool,
+    manual_poll: bool,
+    batch_recv: usize,
+    auto_ack: bool,
+    compression_flag: bool,
+    coalesce: Option<(usize, Duration)>,
+    checksum: bool,
+    circuit_breaker: Option<(u32, Duration)>,
+    send_retries: (u32, Duration),
+    ignore_send_errors: bool,
+    length_prefix: LengthPrefix,
+    envelope: EnvelopeSpec,
+    protocol_version: Option<u8>,
+    #[cfg(feature = "json")]
+    tag_in_body: Option<String>,
+    #[cfg(feature = "netsim")]
+    simulate: Option<SimConfig>,
+    #[cfg(feature = "dtls")]
+    dtls: Option<DtlsConfig>,
 }
 
 impl Builder 
@@ -31,16 +263,108 @@ impl Builder
     { 
         let buffer_len = 100;
         let socket = String::from("0.0.0.0:39507");
+        let sockets = vec![];
+        let send_socket = None;
+        let transport = None;
         let read_timeout = None;
         let non_blocking = true;
+        let shutdown_timeout = None;
+        let detach_on_drop = false;
+        let idle_sleep = Duration::from_millis(1);
         let use_ids = true;
+        let id_hash_seed = None;
+        let dscp = None;
+        let preserve_global_order = false;
+        let queue_capacity = 0;
+        let max_queue_len = None;
+        let max_queues = None;
+        let per_sender_queues = false;
+        let block_send_when_full = false;
+        let capture_dead_letters = false;
+        let get_retains_on_error = false;
+        let message_ttl = None;
+        let clock = Arc::new(SystemClock);
+        let sweep_interval = None;
+        let recv_buffer_size = None;
+        let send_buffer_size = None;
+        let bind_device = None;
+        let recv_ttl = false;
+        let reuse_port = false;
+        let resolve = ResolveMode::All;
+        let send_buffer_hint = 0;
+        let on_recv_error = None;
+        let on_raw = None;
+        let on_unknown = None;
+        let on_version_mismatch = None;
+        let async_send = false;
+        let manual_poll = false;
+        let batch_recv = 1;
+        let auto_ack = false;
+        let compression_flag = false;
+        let coalesce = None;
+        let checksum = false;
+        let circuit_breaker = None;
+        let send_retries = (0, Duration::ZERO);
+        let ignore_send_errors = false;
+        let length_prefix = LengthPrefix::None;
+        let envelope = EnvelopeSpec::default();
+        let protocol_version = None;
 
         return Builder {
             buffer_len,
             socket,
+            sockets,
+            send_socket,
+            transport,
             read_timeout,
             non_blocking,
-            use_ids
+            shutdown_timeout,
+            detach_on_drop,
+            idle_sleep,
+            use_ids,
+            id_hash_seed,
+            dscp,
+            preserve_global_order,
+            queue_capacity,
+            max_queue_len,
+            max_queues,
+            per_sender_queues,
+            block_send_when_full,
+            capture_dead_letters,
+            get_retains_on_error,
+            message_ttl,
+            clock,
+            sweep_interval,
+            recv_buffer_size,
+            send_buffer_size,
+            bind_device,
+            recv_ttl,
+            reuse_port,
+            resolve,
+            send_buffer_hint,
+            on_recv_error,
+            on_raw,
+            on_unknown,
+            on_version_mismatch,
+            async_send,
+            manual_poll,
+            batch_recv,
+            auto_ack,
+            compression_flag,
+            coalesce,
+            checksum,
+            circuit_breaker,
+            send_retries,
+            ignore_send_errors,
+            length_prefix,
+            envelope,
+            protocol_version,
+            #[cfg(feature = "json")]
+            tag_in_body: None,
+            #[cfg(feature = "netsim")]
+            simulate: None,
+            #[cfg(feature = "dtls")]
+            dtls: None
         }
     }
 
@@ -50,10 +374,12 @@ impl Builder
     /// the faster the execution. This is because less time is spent reallocating 
     /// memory when the buffer size needs to be increased. To large of a buffer
     /// is also bad as you 1. waste space & 2. waste time allocating unecessary space.
+    /// Starting the manager fails if the buffer cannot hold the datagram header, such as the 8 byte id.
     /// 
     /// **Default value:** 100 bytes
     /// 
     
+    #[allow(clippy::empty_line_after_doc_comments)]
     pub fn buffer_len(mut self, len: usize) -> Builder 
     {
         self.buffer_len = len;
@@ -73,12 +399,28 @@ impl Builder
     /// **Default value:** True
     /// 
     
+    #[allow(clippy::empty_line_after_doc_comments)]
     pub fn use_ids(mut self, use_ids: bool) -> Builder 
     {
         self.use_ids = use_ids;
         return self;
     }
 
+    /// Seeds the hash used to generate header ids from type names.
+    /// 
+    /// Generated ids only depend on the type name and the seed, so they are reproducible across runs
+    /// of the same Rust version. Only managers using the same seed generate the same ids, which 
+    /// keeps applications with different seeds from accepting each other's datagrams. Ids set with 
+    /// set_id or set_id_by_name are not affected.
+    /// 
+    /// **Default value:** None (the unseeded hash)
+    /// 
+    pub fn id_hash_seed(mut self, seed: (u64, u64)) -> Builder 
+    {
+        self.id_hash_seed = Some(seed);
+        return self;
+    }
+
     /// Used to determine how long the system should wait before returning from the try_recv method.
     /// A longer timeout value results in less cpu resources used, but a slower response from the 
     /// method get method as they both need mutable access to the same resource.
@@ -88,6 +430,7 @@ impl Builder
     /// **Default value:** None
     /// 
     
+    #[allow(clippy::empty_line_after_doc_comments, clippy::partialeq_to_none)]
     pub fn read_timeout(mut self, read_timeout: Option<std::time::Duration>) -> Builder 
     {   
         if read_timeout != None {self.non_blocking = false;}
@@ -103,6 +446,7 @@ impl Builder
     /// **Default value:** False
     /// 
 
+    #[allow(clippy::empty_line_after_doc_comments, clippy::bool_comparison)]
     pub fn non_blocking(mut self, non_blocking: bool) -> Builder 
     {
         if non_blocking == true {
@@ -112,355 +456,4446 @@ impl Builder
         return self
     }
 
-    /// Sets the listening port to receive datagrams on.
+    /// Sets how long the background thread sleeps when a non-blocking socket has nothing to read.
     /// 
-    /// **Default value:** 39507
+    /// Without a pause, a non_blocking listener retries as fast as possible and keeps a whole core
+    /// busy while idle. Datagrams arriving during the pause wait at most this long to be received.
+    /// A duration of zero disables the pause. Has no effect on blocking sockets.
     /// 
-
-    pub fn socket(mut self, socket: String)-> Builder 
+    /// **Default value:** 1 millisecond
+    /// 
+    pub fn idle_sleep(mut self, sleep: Duration) -> Builder 
     {
-        self.socket = socket;
+        self.idle_sleep = sleep;
         return self;
     }
 
-    /// Creates and starts the UDP Manager
+    /// Sets the longest time stopping the manager waits for each background thread to finish.
     /// 
-    /// Uses the configurations set with the builder struct to initialize and start the UDP Manager.
-    /// Specifies the SerDes format for data. Spins up the background thread that continiously checks 
-    /// for datagrams
+    /// When the manager is dropped or shut down, a blocking listener is woken with an empty datagram
+    /// sent to its own socket, so it normally stops at once. A thread still running after the 
+    /// timeout, such as one stuck in a custom transport, is detached instead of joined.
     /// 
-    /// # Errors
+    /// **Default value:** None (waits for every thread to finish)
     /// 
-    /// Errors if configurations to the underlying UDP Socket fail or if it was unable to create the 
-    /// new thread at the OS level.
-    pub fn start<T>(self)->Result<UdpManager<T>, std::io::Error> 
-        where T: SerDesType
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Builder 
     {
-        let len = self.buffer_len;
-        let mut manager = UdpManager::<T>::init(self)?;
-        
-        manager.start(len)?;
-
-        return Ok(manager);
-    }
-}
-
-/// Sends and receives datagrams conveniently. Runs a background thread to continuously check for datagrams
-/// without interrupting other functionality.
-pub struct UdpManager<T>
-    where T: SerDesType
-{
-
-    udp: Arc<UdpSocket>,
-
-    msg_map: Arc<MsgStorage>,
-    
-    resource_type: PhantomData<T>,
-
-    stop: ThreadSafe<bool>,
-
-    thread: Option<thread::JoinHandle<()>>,
-
-    use_ids: bool
-}
-
-/// Allows the background thread to safely shutdown when the struct loses scope or program performs a shutdown.
-impl<T> Drop for UdpManager<T> 
-    where T: SerDesType
-{
-    fn drop(&mut self) {
-        self.stop();
+        self.shutdown_timeout = Some(timeout);
+        return self;
     }
-}
 
-impl <T>UdpManager<T> 
-    where T: SerDesType
-{
-    /// initializer for the class that is only callable by the builder. Uses configured values
-    /// from the builder helper to set the manager. 
+    /// Sets whether dropping the manager leaves its background threads to finish on their own 
+    /// instead of joining them.
     /// 
-    /// # Errors
+    /// Useful for managers kept for the whole program, such as ones stored in a static or leaked,
+    /// where joining during teardown can deadlock. The threads are still told to stop, but may 
+    /// outlive the manager briefly, keeping the sockets open until they exit. Queued async_send 
+    /// datagrams are flushed as usual, and shutdown always joins.
     /// 
-    /// Initialization will fail if it is unable to set the nonblocking or read timeout values to 
-    /// the underlying udp socket.
-    fn init<K>(builder: Builder)->Result<UdpManager<K>, std::io::Error> 
-        where K: SerDesType
+    /// **Default value:** false
+    /// 
+    pub fn detach_on_drop(mut self, detach: bool) -> Builder 
     {
-        let socket        = builder.socket;
-        let read_timeout  = builder.read_timeout;
-        let non_blocking  = builder.non_blocking;
-        let use_ids       = builder.use_ids;
-        let resource_type = PhantomData;
-
-        let udp: UdpSocket = UdpSocket::bind(socket)?;
-        let udp = Arc::from(udp);
-        
-        udp.set_nonblocking(non_blocking)?;
-        udp.set_read_timeout(read_timeout)?;
-
-        let msg_map = Arc::from(MsgStorage::new());
-
-        Ok(UdpManager {
-            udp,
-            stop: ThreadSafe::from(false),
-            thread: None,
-            resource_type,
-            msg_map,
-            use_ids
-        })
+        self.detach_on_drop = detach;
+        return self;
     }
 
-    /// Spawns the background thread for receiving datagrams. Only callable by builder.
+    /// Sets the listening port to receive datagrams on.
+    /// 
+    /// **Default value:** 39507
     /// 
-    /// # Errors
-    ///  
-    /// Fails if unable to create a new thread at the OS level.
-    fn start(&mut self, buffer_len: usize)->Result<(), std::io::Error> 
-    {
-        let udp = self.udp.clone();
-        let msg_map = self.msg_map.clone();
-        let stop = self.stop.clone();
-        let use_ids = self.use_ids.clone();
-
-        let thread = thread::Builder::new()
-            .name(String::from("thread_udp_listener"))
-            .spawn( move || {
-                while *stop.lock().unwrap() == false {
-                    Self::try_recv(udp.clone(), msg_map.clone(), buffer_len, use_ids.clone());
-            }})?;
 
-        self.thread = Some(thread);
-        return Ok(())
+    #[allow(clippy::empty_line_after_doc_comments)]
+    pub fn socket(mut self, socket: String)-> Builder 
+    {
+        self.socket = socket;
+        return self;
     }
 
-    /// Safely closes the background thread. Automatically called when struct is dropped.
-    fn stop(&mut self)
+    /// Sets several listening ports to receive datagrams on.
+    /// 
+    /// Each address is bound and gets its own background thread, all feeding the same storage, so 
+    /// a single manager can serve several ports. The first address replaces the one set with socket
+    /// and is used for sending unless send_socket is set. An empty list leaves the socket setting in place.
+    /// 
+    /// **Default value:** empty
+    /// 
+    pub fn sockets(mut self, addrs: Vec<String>)-> Builder 
     {
-        *self.stop.lock().unwrap() = true;
-        self.thread.take().map(thread::JoinHandle::join);
+        self.sockets = addrs;
+        return self;
     }
 
-    /// Attempts to receive a datagram from the underyling socket. 
+    /// Sets a separate port to send datagrams from, so outgoing and incoming ports can differ.
     /// 
-    /// Attempts to receive a datagram from the underlying socket and remove it from the queue.
-    /// If no datagram is available, it will either return, or sit and wait depending on if the 
-    /// the value of non_blocking, set with the Builder struct.
+    /// The listening sockets then only receive. Peers see the send socket as the source of every 
+    /// datagram, and NAT devices create their mappings for it, so replies addressed to the source
+    /// of a datagram arrive on the send socket and are never read. Peers must be told to reply to
+    /// the listening port instead, which may not be reachable from behind NAT.
     /// 
-    /// # Errors
+    /// **Default value:** None (the listening socket also sends)
     /// 
-    /// Errors when the there is an issue receiving data from the underyling socket. 
-    /// Does not return an error, prints the error to the command line.
+    pub fn send_socket(mut self, socket: String)-> Builder 
+    {
+        self.send_socket = Some(socket);
+        return self;
+    }
+
+    /// Sets the transport carrying datagrams instead of binding a UdpSocket.
     /// 
-    /// # Panics
+    /// The socket, sockets and send_socket settings are ignored, and so are options that only 
+    /// apply to a UdpSocket (dscp, buffer sizes, bind_device). An InMemoryTransport lets managers
+    /// talk to each other deterministically in tests, without the network.
     /// 
-    /// This will panic if the lock becomes poisioned.
-    fn try_recv(udp: Arc<UdpSocket>, msg_map: Arc<MsgStorage>, buffer_len: usize, use_ids: bool) 
+    /// **Default value:** None (a UdpSocket is bound)
+    /// 
+    pub fn transport(mut self, transport: Box<dyn Transport>)-> Builder 
     {
-        let mut buffer: Vec<u8> = vec![0; buffer_len];
-
-        let (num_bytes, addr) =  match udp.recv_from(&mut buffer) {
-            Ok(n) => n ,
-            Err(e)=> {
-                if e.kind() == ErrorKind::WouldBlock {} //Unix response when non_blocking is true
-                else if e.kind() == ErrorKind::TimedOut {}//Windows Response when non_blocking is true
-                else {println!("{}",e);} //Prints this to screen instead of crashing for one fail read
-
-                return; } //Break out of function if we received no bytes
-        };
-
-        buffer.truncate(num_bytes);
-        
-        if use_ids {
-            let id: Vec<_> = buffer.drain(..8).collect();
-            let id = BigEndian::read_u64(&id);
-            msg_map.add_msg(id, addr, buffer);
-        }
-        else {
-            msg_map.add_msg(1, addr, buffer);
-        }   
+        self.transport = Some(transport);
+        return self;
     }
 
-    /// Provides the oldest datagram of the specified type, if one exists. 
+    /// Sets the DSCP (Differentiated Services Code Point) value used to mark all outgoing datagrams.
     /// 
-    /// Attempts to retrieve the serialized object from the underlying storage depending
-    /// on the requested data type. The serialized object is removed (if one exists) 
-    /// from the underyling storage regardless of deserialization success.
-    /// The deserialized object is returned to the user, if deserialization is successful
+    /// The value is written to the upper six bits of the IP_TOS byte of the underlying socket so
+    /// routers can prioritize latency-sensitive traffic. Whether the marking has any effect depends
+    /// entirely on the network; many routers ignore or rewrite it. Values above 63 are rejected
+    /// when the manager is started.
     /// 
-    /// # Errors
+    /// **Default value:** None (the OS default is left untouched)
     /// 
-    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    pub fn dscp(mut self, value: u8) -> Builder 
+    {
+        self.dscp = Some(value);
+        return self;
+    }
+
+    /// Records the global arrival order of datagrams across all types.
     /// 
-    /// # Panics
+    /// When enabled, every stored datagram is tagged with a monotonically increasing receive
+    /// sequence number. This allows get_next_any to return the globally-oldest datagram regardless
+    /// of its type, which supports a single dispatch loop that handles messages strictly in the 
+    /// order they arrived.
     /// 
-    /// This will panic if the lock becomes poisioned.
-    pub fn get<J>(&self)->Result<(SocketAddr, J), std::io::Error>
-        where J: de::DeserializeOwned + 'static
+    /// **Default value:** False
+    /// 
+    pub fn preserve_global_order(mut self, preserve_global_order: bool) -> Builder 
     {
-        return self.msg_map.get_obj::<T,J>(self.use_ids);
+        self.preserve_global_order = preserve_global_order;
+        return self;
     }
 
-    /// Provides all datagrams of the specified type, if any exist.
+    /// Sets the initial capacity of the queue created for each datagram type.
     /// 
-    /// Attempts to retrieve all serialized objects from the underlying storage depending
-    /// on the requested data type. If storage for the object exists, it will attempt to 
-    /// deserialize any datagrams that exist. If deserialization fails, the datagram is lost.
-    /// It will return an empty vector as long as the underlying storage existed. If use_ids is 
-    /// set to false, this will return only the datagrams that were able to be converted. All 
-    /// others are removed.
+    /// A queue is created the first time a datagram of a given type is received. Starting it 
+    /// with a larger capacity reduces reallocations for types that are expected to arrive in bursts.
+    /// This is only a hint; behavior is otherwise unchanged.
     /// 
-    /// # Errors
+    /// **Default value:** 0
     /// 
-    /// Returns an error when the underlying storage for that data type does not exist (different 
-    /// than being empty) or the data could not be deserialized
+    pub fn queue_capacity(mut self, cap: usize) -> Builder 
+    {
+        self.queue_capacity = cap;
+        return self;
+    }
+
+    /// Sets the most datagrams kept in the queue of each datagram type.
     /// 
-    /// # Panics
+    /// When a datagram arrives at a full queue, the oldest datagram of that type is dropped to 
+    /// make room, so memory stays bounded for types that arrive faster than they are consumed.
     /// 
-    /// This will panic if the lock becomes poisioned.
-    pub fn get_all<J>(&self)->Result<Vec<(std::net::SocketAddr, J)>, std::io::Error>
-        where J: de::DeserializeOwned + 'static
+    /// **Default value:** None (queues are unbounded)
+    /// 
+    pub fn max_queue_len(mut self, len: usize) -> Builder 
     {
-        return self.msg_map.get_obj_all::<T,J>(self.use_ids);
+        self.max_queue_len = Some(len);
+        return self;
     }
 
-    /// Provides the oldest datagram of the specified type, if one exists, without
-    /// removing it from the underlying storage.
+    /// Sets the most distinct datagram ids that are given a queue.
     /// 
-    /// Attempts to retrieve the serialized object from the underlying storage depending
-    /// on the requested data type. If a serialized object is available, a copy is taken and
-    /// an attempt to deserialize the data is made. If successful, the deserialized object is 
-    /// returned to the user.
+    /// Every new id creates a queue, so a flood of datagrams with random ids could otherwise grow
+    /// memory without limit. Once this many ids have a queue, datagrams with any other id are 
+    /// dropped and counted as too_many_queues in the drop stats. Queues stay allocated once 
+    /// created, even after being emptied.
     /// 
-    /// # Errors
+    /// **Default value:** None (the number of queues is unbounded)
     /// 
-    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    pub fn max_queues(mut self, count: usize) -> Builder 
+    {
+        self.max_queues = Some(count);
+        return self;
+    }
+
+    /// Determines if the datagrams of each type are queued separately for every sender.
     /// 
-    /// # Panics
+    /// By default the datagrams of a type share one queue, so get_from and peek_from pass over the
+    /// datagrams other senders queued ahead. With a queue per sender they reach a sender's oldest 
+    /// datagram directly, and each sender's datagrams are still provided strictly in the order 
+    /// they arrived. Methods that take no sender, such as get, provide the oldest datagram across 
+    /// all senders, which takes time proportional to the number of senders with datagrams queued.
+    /// max_queue_len still bounds the datagrams of a type across all of its senders.
     /// 
-    /// This will panic if the lock becomes poisioned.
-    pub fn peek<J>(&self)->Result<(SocketAddr, J), std::io::Error>
-        where J: de::DeserializeOwned + 'static
+    /// **Default value:** false
+    /// 
+    pub fn per_sender_queues(mut self, per_sender_queues: bool) -> Builder 
     {
-        return self.msg_map.peek::<T,J>(self.use_ids);
+        self.per_sender_queues = per_sender_queues;
+        return self;
     }
 
-    /// Removes the oldest datagram of the specified type, if one exists, without providing
-    /// it to the user.
+    /// Determines if sending to this manager's own address blocks while the receiving queue is full.
     /// 
-    /// if use_ids is set false, it will remove the oldest datagram and the specified type is ignored.
+    /// Provides crude flow control for in-process pipelines: a send to one of the manager's local 
+    /// addresses waits until the queue for the datagram's type holds fewer than max_queue_len 
+    /// datagrams, instead of making the receiver drop the oldest one. Another thread must consume 
+    /// the datagrams, or the send never returns. Sends to any other address are unaffected, and so
+    /// is everything when max_queue_len is not set.
     /// 
-    /// # Errors
+    /// **Default value:** false
     /// 
-    /// Returns error when the underlying storage does not exist.
+    pub fn block_send_when_full(mut self, block: bool) -> Builder 
+    {
+        self.block_send_when_full = block;
+        return self;
+    }
+
+    /// Determines if datagrams that fail to deserialize in get or get_all are kept as dead letters.
     /// 
-    /// # Panics
+    /// Without it, such datagrams are removed and lost. With it, they are moved to a separate list
+    /// available through UdpManager::dead_letters, giving a way to recover datagrams read as the 
+    /// wrong type, which is easy when use_ids is false.
     /// 
-    /// This will panic if the lock becomes poisioned.
-    pub fn remove_front<J>(&self)->Result<(), std::io::Error>
-        where J: de::DeserializeOwned + 'static
+    /// **Default value:** false
+    /// 
+    pub fn capture_dead_letters(mut self, capture: bool) -> Builder 
     {
-        return self.msg_map.remove_front::<T, J>(self.use_ids);
+        self.capture_dead_letters = capture;
+        return self;
     }
 
-    /// Removes all datagram of the specified type, if one exists, without providing
-    /// it to the user.
+    /// Determines if datagrams that fail to deserialize in get or get_all stay queued.
     /// 
-    /// if use_ids is set false, it will remove all datagram and the specified type is ignored.
+    /// The error is returned by get without removing the datagram, so it can be retrieved again 
+    /// as another type. This makes get safe to retry when use_ids is false, without combining 
+    /// peek and remove. get_all returns the datagrams that deserialize and leaves the rest at the 
+    /// front of the queue in their original order. Takes precedence over capture_dead_letters.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn get_retains_on_error(mut self, retain: bool) -> Builder 
+    {
+        self.get_retains_on_error = retain;
+        return self;
+    }
+
+    /// Sets how long a received datagram stays valid.
+    /// 
+    /// Datagrams older than the ttl, measured from the moment the background thread received them,
+    /// are removed instead of being returned by get, peek, get_all and the other read methods. This
+    /// is useful for real-time data where an old update is worse than none.
+    /// 
+    /// **Default value:** None (datagrams never expire)
+    /// 
+    pub fn message_ttl(mut self, ttl: Duration) -> Builder 
+    {
+        self.message_ttl = Some(ttl);
+        return self;
+    }
+
+    /// Sets the source of time used for message_ttl, the time datagrams were received, and the 
+    /// send_circuit_breaker cooldown.
+    /// 
+    /// A MockClock lets tests advance time instantly instead of sleeping. Timeouts that actually 
+    /// wait, such as read_timeout or get_or_default, always use the system's time.
+    /// 
+    /// **Default value:** SystemClock
+    /// 
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Builder 
+    {
+        self.clock = clock;
+        return self;
+    }
+
+    /// Sets how often a background thread removes expired datagrams from every queue.
+    /// 
+    /// Without a sweep, datagrams older than message_ttl are only removed when their type is read, 
+    /// so memory is never reclaimed for types that arrive but are rarely consumed. Has no effect 
+    /// unless message_ttl is also set.
+    /// 
+    /// **Default value:** None (no sweeping)
+    /// 
+    pub fn sweep_interval(mut self, interval: Duration) -> Builder 
+    {
+        self.sweep_interval = Some(interval);
+        return self;
+    }
+
+    /// Sets the size of the kernel receive buffer (SO_RCVBUF) of the underlying socket.
+    /// 
+    /// A larger buffer reduces packet loss on bursty, high-rate workloads. The OS may round or
+    /// cap the requested size (Linux doubles it and caps it at net.core.rmem_max).
+    /// 
+    /// **Default value:** None (the OS default is left untouched)
+    /// 
+    pub fn recv_buffer_size(mut self, bytes: usize) -> Builder 
+    {
+        self.recv_buffer_size = Some(bytes);
+        return self;
+    }
+
+    /// Sets the size of the kernel send buffer (SO_SNDBUF) of the underlying socket.
+    /// 
+    /// The OS may round or cap the requested size (Linux doubles it and caps it at net.core.wmem_max).
+    /// 
+    /// **Default value:** None (the OS default is left untouched)
+    /// 
+    pub fn send_buffer_size(mut self, bytes: usize) -> Builder 
+    {
+        self.send_buffer_size = Some(bytes);
+        return self;
+    }
+
+    /// Binds the socket to a specific network interface (SO_BINDTODEVICE), such as "eth0".
+    /// 
+    /// Only datagrams arriving on the named interface are received, which is useful on multi-NIC
+    /// hosts. This is only supported on Linux and typically requires elevated privileges 
+    /// (CAP_NET_RAW). Starting the manager fails on other platforms or if the bind is refused.
+    /// 
+    /// **Default value:** None (all interfaces)
+    /// 
+    pub fn bind_device(mut self, iface: String) -> Builder 
+    {
+        self.bind_device = Some(iface);
+        return self;
+    }
+
+    /// Determines if the TTL (or IPv6 hop limit) of each received datagram is captured, to be read
+    /// with UdpManager::get_with_ttl.
+    /// 
+    /// Useful for routing diagnostics and simple trust heuristics, such as only accepting datagrams
+    /// that crossed no router. Only supported on Linux; elsewhere, and with a custom transport, the
+    /// TTL of every datagram is None. Datagrams are then received one at a time, so batch_recv has 
+    /// no effect.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn recv_ttl(mut self, recv_ttl: bool) -> Builder 
+    {
+        self.recv_ttl = recv_ttl;
+        return self;
+    }
+
+    /// Determines if SO_REUSEPORT is set before binding the sockets.
+    /// 
+    /// Lets several managers, usually in separate processes, bind the same port so a server can be
+    /// scaled across cores. On Linux, the kernel hashes each flow (source and destination address 
+    /// and port) to one of the sockets, so every datagram from a given peer reaches the same manager.
+    /// Other Unix platforms accept the option with their own semantics, and starting the manager 
+    /// fails on Windows.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn reuse_port(mut self, reuse_port: bool) -> Builder 
+    {
+        self.reuse_port = reuse_port;
+        return self;
+    }
+
+    /// Determines how the addresses given to socket, sockets and send_socket are resolved.
+    /// 
+    /// A host name such as localhost may resolve to both IPv4 and IPv6 addresses, in an order 
+    /// chosen by the system. Restricting the family avoids binding to an unexpected one. Failing 
+    /// to resolve an address, or resolving it to no address allowed by the mode, makes start fail
+    /// with BuilderError::Resolve. Resolution may block on DNS.
+    /// 
+    /// **Default value:** ResolveMode::All
+    /// 
+    pub fn resolve(mut self, mode: ResolveMode) -> Builder 
+    {
+        self.resolve = mode;
+        return self;
+    }
+
+    /// Sets the expected size, in bytes, of serialized payloads.
+    /// 
+    /// The buffer used to build each outgoing datagram is preallocated with room for the id plus
+    /// this many bytes, avoiding reallocations when payload sizes are known in advance. This is
+    /// only a hint; behavior is otherwise unchanged.
+    /// 
+    /// **Default value:** 0
+    /// 
+    pub fn send_buffer_hint(mut self, bytes: usize) -> Builder 
+    {
+        self.send_buffer_hint = bytes;
+        return self;
+    }
+
+    /// Sets a callback invoked on the background thread whenever receiving from the socket fails or 
+    /// a malformed datagram, such as one too short to carry an id, is dropped.
+    /// 
+    /// Without a callback, unexpected errors are printed to the command line. Errors caused by an 
+    /// unreachable peer (ConnectionRefused, ConnectionReset, HostUnreachable, NetworkUnreachable) are
+    /// never printed: on a connected socket, an ICMP error from a previous send is reported by the
+    /// next receive, and the background thread simply keeps receiving. All errors are also 
+    /// available through last_error. The callback should return quickly as it blocks receiving.
+    /// 
+    /// **Default value:** None
+    /// 
+    pub fn on_recv_error<F>(mut self, f: F) -> Builder 
+        where F: Fn(&std::io::Error) + Send + Sync + 'static
+    {
+        self.on_recv_error = Some(Arc::new(f));
+        return self;
+    }
+
+    /// Sets a callback invoked on the background thread with the id, source and payload of every 
+    /// received datagram, before it is stored or deserialized.
+    /// 
+    /// The datagram continues into the underlying storage once the callback returns. This is useful 
+    /// for logging and protocol inspection. The callback should return quickly as it blocks receiving.
+    /// 
+    /// **Default value:** None
+    /// 
+    pub fn on_raw<F>(mut self, f: F) -> Builder 
+        where F: Fn(u64, SocketAddr, &[u8]) + Send + Sync + 'static
+    {
+        self.on_raw = Some(Arc::new(move |id, addr, payload: &[u8]| {
+            f(id, addr, payload);
+            return false;
+        }));
+        return self;
+    }
+
+    /// Sets a callback that may consume received datagrams before they are stored.
+    /// 
+    /// Behaves like on_raw, but when the callback returns true the datagram is considered handled
+    /// and is not added to the underlying storage. This allows custom dispatch of some ids while 
+    /// the rest are retrieved as usual. Replaces any callback set with on_raw.
+    /// 
+    /// **Default value:** None
+    /// 
+    pub fn on_raw_consume<F>(mut self, f: F) -> Builder 
+        where F: Fn(u64, SocketAddr, &[u8]) -> bool + Send + Sync + 'static
+    {
+        self.on_raw = Some(Arc::new(f));
+        return self;
+    }
+
+    /// Sets a callback receiving datagrams whose id belongs to no known type, instead of storing 
+    /// them under an id nothing will retrieve.
+    /// 
+    /// An id is known once the manager has used it: a type passed to send, get, get_id, set_id or 
+    /// subscribe, or a name passed to get_id_by_name or set_id_by_name. Call get_id for every 
+    /// expected type before traffic arrives so none of them is mistaken for unknown. Useful for
+    /// gateways logging or forwarding foreign traffic. Has no effect when use_ids is false. The 
+    /// callback should return quickly as it blocks receiving.
+    /// 
+    /// **Default value:** None (unknown datagrams are stored like any other)
+    /// 
+    pub fn on_unknown<F>(mut self, f: F) -> Builder 
+        where F: Fn(u64, SocketAddr, Vec<u8>) + Send + Sync + 'static
+    {
+        self.on_unknown = Some(Arc::new(f));
+        return self;
+    }
+
+    /// Sets a callback receiving datagrams dropped for carrying another protocol_version, along 
+    /// with their source address.
+    /// 
+    /// The datagram is provided exactly as it arrived, starting with its version byte, so it can 
+    /// be logged or decoded by code that understands that version. Such datagrams are still 
+    /// counted in the drop stats, but are not reported to on_recv_error. With checksum enabled, 
+    /// datagrams failing the checksum are dropped for it instead, whatever their first byte. The 
+    /// callback should return quickly as it blocks receiving.
+    /// 
+    /// **Default value:** None (mismatched datagrams are reported to on_recv_error)
+    /// 
+    pub fn on_version_mismatch<F>(mut self, f: F) -> Builder 
+        where F: Fn(SocketAddr, Vec<u8>) + Send + Sync + 'static
+    {
+        self.on_version_mismatch = Some(Arc::new(f));
+        return self;
+    }
+
+    /// Determines if datagrams are sent from a dedicated background thread.
+    /// 
+    /// When enabled, send methods only serialize the datagram and queue it; the actual system call
+    /// happens on a separate thread, so errors from the socket are reported through last_error 
+    /// rather than returned. Dropping the manager flushes the queue, waiting at most 500 
+    /// milliseconds, so datagrams sent right before the program exits still reach the socket.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn async_send(mut self, async_send: bool) -> Builder 
+    {
+        self.async_send = async_send;
+        return self;
+    }
+
+    /// Determines if start skips spawning the background threads that receive datagrams.
+    /// 
+    /// For applications that want full control over receiving from a single thread, such as 
+    /// embedded or single-threaded programs. Datagrams are then only received when the application
+    /// calls UdpManager::poll_once, or recv_into to bypass the underlying storage.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn manual_poll(mut self, manual_poll: bool) -> Builder 
+    {
+        self.manual_poll = manual_poll;
+        return self;
+    }
+
+    /// Sets the number of datagrams the listener may receive with a single system call.
+    /// 
+    /// On Linux, sockets are read with recvmmsg, which returns every datagram already waiting up 
+    /// to count. This greatly reduces the number of system calls at high packet rates. Each 
+    /// datagram still gets a buffer of buffer_len bytes. Other platforms and transports keep 
+    /// receiving one datagram at a time. A count of 0 is treated as 1.
+    /// 
+    /// **Default value:** 1
+    /// 
+    pub fn batch_recv(mut self, count: usize) -> Builder 
+    {
+        self.batch_recv = count.max(1);
+        return self;
+    }
+
+    /// Determines if datagrams sent with UdpManager::send_reliable are acknowledged.
+    /// 
+    /// The acknowledgement is sent back to the sender by the background thread as soon as the 
+    /// datagram is received, from the socket it arrived on. Reliable datagrams are delivered 
+    /// regardless of this setting, but the sender keeps retransmitting until it gives up when 
+    /// nothing acknowledges them.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn auto_ack(mut self, auto_ack: bool) -> Builder 
+    {
+        self.auto_ack = auto_ack;
+        return self;
+    }
+
+    /// Determines if a compression flag byte follows the id of every datagram.
+    /// 
+    /// When enabled, send_compressed DEFLATEs the payload and sets the flag, while every other send
+    /// method leaves it cleared. Received datagrams are inflated before being stored when the flag is
+    /// set. This lets large messages be compressed on demand while small ones stay cheap. Both 
+    /// peers must agree on this setting as it changes the layout of every datagram.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn compression_flag(mut self, compression_flag: bool) -> Builder 
+    {
+        self.compression_flag = compression_flag;
+        return self;
+    }
+
+    /// Coalesces small outgoing datagrams to the same destination into a single datagram.
+    /// 
+    /// Sent messages are appended to a pending buffer per destination, each prefixed with its 
+    /// length, and the buffer is sent once adding another message would exceed max_bytes or once
+    /// the oldest message has waited max_delay. Received datagrams are split back into individual
+    /// messages. This trades a little latency for far fewer packets. Both peers must enable it, and 
+    /// the buffer_len of the receiver must be at least max_bytes. Pending messages are sent when 
+    /// the manager is dropped.
+    /// 
+    /// **Default value:** None (every message is its own datagram)
+    /// 
+    pub fn coalesce(mut self, max_bytes: usize, max_delay: Duration) -> Builder 
+    {
+        self.coalesce = Some((max_bytes, max_delay));
+        return self;
+    }
+
+    /// Determines if a CRC32 of every datagram is appended and verified.
+    /// 
+    /// The checksum covers the id and payload and catches corruption that slips past the weak 
+    /// checksum of UDP, for example across tunnels. Received datagrams that fail verification are 
+    /// dropped and reported like other malformed datagrams. It does not protect against forgery. 
+    /// Both peers must agree on this setting as it changes the layout of every datagram.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn checksum(mut self, checksum: bool) -> Builder 
+    {
+        self.checksum = checksum;
+        return self;
+    }
+
+    /// Stops sending to a destination for a while after repeated send failures.
+    /// 
+    /// After threshold consecutive failed sends to the same address, every send to it fails 
+    /// immediately with BrokenPipe for the cooldown instead of attempting the system call. Once the
+    /// cooldown has passed, the next send is attempted again: success closes the breaker, while a 
+    /// failure opens it for another cooldown. The state of a peer can be cleared with reset_peer.
+    /// 
+    /// **Default value:** None (every send is attempted)
+    /// 
+    pub fn send_circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Builder 
+    {
+        self.circuit_breaker = Some((threshold, cooldown));
+        return self;
+    }
+
+    /// Retries sends that fail with a transient error.
+    /// 
+    /// A send failing with WouldBlock (the send buffer is full) or Interrupted is attempted again up
+    /// to max more times, sleeping between attempts. The sleep starts at backoff and doubles after 
+    /// every attempt. Any other error is returned immediately. This also applies to sends made by
+    /// the async_send and coalesce threads.
+    /// 
+    /// **Default value:** 0 retries
+    /// 
+    pub fn send_retries(mut self, max: u32, backoff: Duration) -> Builder 
+    {
+        self.send_retries = (max, backoff);
+        return self;
+    }
+
+    /// Makes sends succeed even when the socket fails to send the datagram, for best-effort 
+    /// traffic such as telemetry.
+    /// 
+    /// Only the failure of the socket itself is ignored, after any send_retries. Serialization 
+    /// errors, unresolvable addresses and refusals by the send circuit breaker are still returned.
+    /// Every failed send is counted by dropped_sends either way.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn ignore_send_errors(mut self, ignore: bool) -> Builder 
+    {
+        self.ignore_send_errors = ignore;
+        return self;
+    }
+
+    /// Sets the size of a big endian length prefix written right after the id of every datagram.
+    /// 
+    /// The prefix holds the number of bytes that follow it, excluding the checksum, for bridges 
+    /// that expect length-prefixed payloads. Received datagrams whose declared length does not 
+    /// match the received bytes are dropped and reported, which catches truncation. Both peers must
+    /// agree on this setting as it changes the layout of every datagram.
+    /// 
+    /// **Default value:** LengthPrefix::None
+    /// 
+    pub fn length_prefix(mut self, length_prefix: LengthPrefix) -> Builder 
+    {
+        self.length_prefix = length_prefix;
+        return self;
+    }
+
+    /// Sets a protocol version byte written at the very start of every datagram, before the id.
+    /// 
+    /// Received datagrams carrying any other version are dropped and counted, which keeps peers 
+    /// running an incompatible protocol from being misread while a change is rolled out. See 
+    /// on_version_mismatch to handle them instead. Both peers must agree on whether a version is
+    /// carried as it changes the layout of every datagram.
+    /// 
+    /// **Default value:** None (no version byte)
+    /// 
+    pub fn protocol_version(mut self, version: u8) -> Builder 
+    {
+        self.protocol_version = Some(version);
+        return self;
+    }
+
+    /// Sets the metadata fields, such as a tick number or channel, carried by every datagram 
+    /// right before its payload.
+    /// 
+    /// The metadata travels separately from the message types so it does not need to be added to
+    /// each of them. It is set with UdpManager::send_env and read with get_env; other sends write
+    /// zeroes and other gets ignore it. Received datagrams too short to hold the envelope are 
+    /// dropped and reported. Both peers must agree on this setting as it changes the layout of 
+    /// every datagram.
+    /// 
+    /// **Default value:** no fields
+    /// 
+    pub fn envelope(mut self, envelope: EnvelopeSpec) -> Builder 
+    {
+        self.envelope = envelope;
+        return self;
+    }
+
+    /// Carries the type of each datagram as a field of its JSON body instead of a binary id.
+    /// 
+    /// Send adds the name of the datagram's type under the field, and the background thread routes
+    /// received datagrams by reading it, which allows talking to JSON APIs that describe themselves
+    /// this way. The manager must be started with the JSON format, datagrams must serialize to JSON
+    /// objects, and the generated ids must not be overridden with set_id or set_id_by_name. Reliable
+    /// sends and sends with an origin are unsupported, and use_ids is ignored. Only available with
+    /// the json feature.
+    /// 
+    /// **Default value:** None (types are carried as binary ids, if use_ids is true)
+    /// 
+    #[cfg(feature = "json")]
+    pub fn tag_in_body(mut self, field: String) -> Builder 
+    {
+        self.tag_in_body = Some(field);
+        return self;
+    }
+
+    /// Simulates a lossy, slow network on the receive path, for testing.
+    /// 
+    /// Each received datagram is dropped with the configured probability, and the rest are only 
+    /// added to the underlying storage after the configured delay, plus or minus a random jitter.
+    /// Jitter can reorder datagrams, as on a real network. Delayed datagrams are released by one 
+    /// extra background thread. Only available with the netsim feature.
+    /// 
+    /// **Default value:** None
+    /// 
+    #[cfg(feature = "netsim")]
+    pub fn simulate(mut self, config: SimConfig) -> Builder 
+    {
+        self.simulate = Some(config);
+        return self;
+    }
+
+    /// Secures every datagram with DTLS, encrypting and authenticating it with a session per peer.
+    /// 
+    /// Each bound socket is wrapped in a DtlsTransport. Both sides of a session must use DTLS, and
+    /// each must present a certificate signed by a certificate the other trusts (see DtlsConfig).
+    /// The handshake completes through the receiving thread, so manual_poll managers must keep 
+    /// polling while it runs, and send_socket cannot be combined with DTLS as handshake replies 
+    /// would not reach a receiving socket. Ignored when a transport is set. Only available with 
+    /// the dtls feature.
+    /// 
+    /// **Default value:** None
+    /// 
+    #[cfg(feature = "dtls")]
+    pub fn dtls(mut self, config: DtlsConfig) -> Builder 
+    {
+        self.dtls = Some(config);
+        return self;
+    }
+
+    /// Creates and starts the UDP Manager
+    /// 
+    /// Uses the configurations set with the builder struct to initialize and start the UDP Manager.
+    /// Specifies the SerDes format for data. Spins up the background thread that continiously checks 
+    /// for datagrams
+    /// 
+    /// # Errors
+    /// 
+    /// Errors if configurations to the underlying UDP Socket fail or if it was unable to create the 
+    /// new thread at the OS level. Bind and configuration failures carry a BuilderError naming 
+    /// the address.
+    pub fn start<T>(self)->Result<UdpManager<T>, std::io::Error> 
+        where T: SerDesType
+    {
+        let len = self.buffer_len;
+        let async_send = self.async_send;
+        let manual_poll = self.manual_poll;
+        let mut manager = UdpManager::<T>::init(self)?;
+        
+        manager.start(len, async_send, manual_poll)?;
+
+        return Ok(manager);
+    }
+
+    /// Creates and starts the UDP Manager, ready to be shared between threads.
+    /// 
+    /// Behaves exactly like start, but wraps the manager in an Arc. Clones of the returned 
+    /// SharedUdpManager may send and receive concurrently from any number of threads.
+    /// 
+    /// # Errors
+    /// 
+    /// Errors if configurations to the underlying UDP Socket fail or if it was unable to create the 
+    /// new thread at the OS level.
+    pub fn start_shared<T>(self)->Result<SharedUdpManager<T>, std::io::Error> 
+        where T: SerDesType
+    {
+        return Ok(Arc::new(self.start::<T>()?));
+    }
+
+    /// Creates and starts the UDP Manager with a SerDes format chosen at runtime.
+    /// 
+    /// Behaves exactly like start, but the format is a value rather than a type parameter. This
+    /// allows the format to be picked from a configuration file or command line at startup. 
+    /// Dispatching through the trait object is slightly slower than a statically chosen format.
+    /// 
+    /// # Errors
+    /// 
+    /// Errors if configurations to the underlying UDP Socket fail or if it was unable to create the 
+    /// new thread at the OS level.
+    pub fn start_dyn(self, format: Box<dyn DynSerDes>)->Result<UdpManager<Dynamic>, std::io::Error> 
+    {
+        let len = self.buffer_len;
+        let async_send = self.async_send;
+        let manual_poll = self.manual_poll;
+        let mut manager = UdpManager::<Dynamic>::init(self)?;
+        manager.format = Some(Arc::from(format));
+
+        manager.start(len, async_send, manual_poll)?;
+
+        return Ok(manager);
+    }
+}
+
+/// Sends and receives datagrams conveniently. Runs a background thread to continuously check for datagrams
+/// without interrupting other functionality.
+/// 
+/// Every method takes &self, so a single manager can be shared between threads with an Arc and
+/// used to send and receive concurrently without any additional locking.
+pub struct UdpManager<T>
+    where T: SerDesType
+{
+
+    udp: Arc<dyn Transport>,
+
+    sockets: Vec<Arc<dyn Transport>>,
+
+    msg_map: Arc<MsgStorage>,
+    
+    resource_type: PhantomData<T>,
+
+    stop: ThreadSafe<bool>,
+
+    threads: Vec<thread::JoinHandle<()>>,
+
+    listeners: Vec<Listener>,
+
+    idle: Vec<Arc<IdlePasses>>,
+
+    socket_mode: Mutex<(bool, Option<Duration>)>,
+
+    recv_one_buffer: Mutex<Vec<u8>>,
+
+    shutdown_timeout: Option<Duration>,
+
+    detach_on_drop: bool,
+
+    last_error: ThreadSafe<Option<(ErrorKind, String)>>,
+
+    on_recv_error: Option<Arc<RecvErrorFn>>,
+
+    on_raw: Option<Arc<RawFn>>,
+
+    on_unknown: Option<Arc<UnknownFn>>,
+
+    on_version_mismatch: Option<Arc<VersionMismatchFn>>,
+
+    send_buffer_hint: usize,
+
+    format: Option<Arc<dyn DynSerDes>>,
+
+    outgoing: Option<mpsc::Sender<Outgoing>>,
+
+    sender: Option<thread::JoinHandle<()>>,
+
+    flush_deadline: ThreadSafe<Option<Instant>>,
+
+    sweep_interval: Option<Duration>,
+
+    coalescer: Option<Arc<Coalescer>>,
+
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+
+    send_retries: SendRetries,
+
+    ignore_send_errors: bool,
+
+    dropped_sends: Arc<AtomicU64>,
+
+    block_send_when_full: bool,
+
+    batch_recv: usize,
+
+    recv_ttl: bool,
+
+    idle_sleep: Duration,
+
+    auto_ack: bool,
+
+    framing: Framing,
+
+    #[cfg(feature = "json")]
+    tag_in_body: Option<Arc<str>>,
+
+    #[cfg(feature = "netsim")]
+    simulate: Option<SimConfig>,
+
+    #[cfg(feature = "netsim")]
+    delayed: Arc<DelayQueue>,
+
+    use_ids: bool
+}
+
+/// A UDP Manager shared between threads, as created by Builder::start_shared.
+/// 
+/// All state of the manager sits behind an Arc, Mutex or atomic, so the manager is Send and Sync
+/// for every provided format and needs no extra locking. The background threads are stopped when 
+/// the last clone is dropped.
+pub type SharedUdpManager<T> = Arc<UdpManager<T>>;
+
+/// Allows the background thread to safely shutdown when the struct loses scope or program performs a shutdown.
+impl<T> Drop for UdpManager<T> 
+    where T: SerDesType
+{
+    fn drop(&mut self) {
+        self.flush();
+        if self.detach_on_drop {
+            self.detach();
+        }
+        else {
+            self.stop();
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for UdpManager<T> 
+    where T: SerDesType
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let listening = !self.threads.is_empty() && self.threads.iter().all(|thread| !thread.is_finished());
+
+        return f.debug_struct("UdpManager")
+            .field("local_addrs", &self.local_addrs().unwrap_or_default())
+            .field("format", &self.format_name())
+            .field("use_ids", &self.use_ids)
+            .field("listening", &listening)
+            .field("pending", &self.msg_map.pending())
+            .finish();
+    }
+}
+
+impl <T>UdpManager<T> 
+    where T: SerDesType
+{
+    /// initializer for the class that is only callable by the builder. Uses configured values
+    /// from the builder helper to set the manager. 
+    /// 
+    /// # Errors
+    /// 
+    /// Initialization will fail if it is unable to set the nonblocking, read timeout, dscp or buffer 
+    /// size values to the underlying udp socket.
+    fn init<K>(builder: Builder)->Result<UdpManager<K>, std::io::Error> 
+        where K: SerDesType
+    {
+        let mut sockets   = builder.sockets;
+        let read_timeout  = builder.read_timeout;
+        let non_blocking  = builder.non_blocking;
+        let use_ids       = builder.use_ids;
+        let send_buffer_hint = builder.send_buffer_hint;
+        let compression_flag = builder.compression_flag;
+        let on_recv_error = builder.on_recv_error;
+        let on_raw = builder.on_raw;
+        let on_unknown = builder.on_unknown;
+        let on_version_mismatch = builder.on_version_mismatch;
+        let dscp          = builder.dscp;
+        let preserve_global_order = builder.preserve_global_order;
+        let queue_capacity = builder.queue_capacity;
+        let max_queue_len = builder.max_queue_len;
+        let message_ttl = builder.message_ttl;
+        let clock = builder.clock;
+        let id_hash_seed = builder.id_hash_seed;
+        let sweep_interval = builder.sweep_interval.filter(|_| message_ttl.is_some());
+        let recv_buffer_size = builder.recv_buffer_size;
+        let send_buffer_size = builder.send_buffer_size;
+        let bind_device = builder.bind_device;
+        let recv_ttl = builder.recv_ttl;
+        let reuse_port = builder.reuse_port;
+        let resolve = builder.resolve;
+        let resource_type = PhantomData;
+
+        #[cfg(feature = "json")]
+        let tag_in_body: Option<Arc<str>> = builder.tag_in_body.map(Arc::from);
+        #[cfg(feature = "json")]
+        let tagged = tag_in_body.is_some();
+        #[cfg(not(feature = "json"))]
+        let tagged = false;
+
+        //A type tag replaces the binary id on the wire, but datagrams are still stored by id
+        let framing = Framing {
+            use_ids: use_ids && !tagged,
+            compression_flag,
+            checksum: builder.checksum,
+            length_prefix: builder.length_prefix,
+            version: builder.protocol_version,
+            envelope: builder.envelope
+        };
+        if builder.buffer_len < framing.header_len() {
+            return Err(BuilderError::buffer_too_small(builder.buffer_len, framing.header_len()));
+        }
+        let use_ids = use_ids || tagged;
+
+        if sockets.is_empty() {
+            sockets.push(builder.socket);
+        }
+
+        let configure = |udp: &UdpSocket| -> Result<(), std::io::Error> {
+            udp.set_nonblocking(non_blocking)?;
+            udp.set_read_timeout(read_timeout)?;
+
+            if let Some(dscp) = dscp {
+                if dscp > 63 {
+                    return Err(std::io::Error::new(ErrorKind::InvalidInput, "DSCP value must be between 0 and 63"));
+                }
+                SockRef::from(udp).set_tos((dscp as u32) << 2)?;
+            }
+
+            if let Some(bytes) = recv_buffer_size {
+                SockRef::from(udp).set_recv_buffer_size(bytes)?;
+            }
+
+            if let Some(bytes) = send_buffer_size {
+                SockRef::from(udp).set_send_buffer_size(bytes)?;
+            }
+
+            if let Some(iface) = &bind_device {
+                Self::bind_device(udp, iface)?;
+            }
+
+            if recv_ttl {
+                crate::transport::enable_recv_ttl(udp)?;
+            }
+            return Ok(());
+        };
+
+        #[cfg(feature = "dtls")]
+        let dtls = builder.dtls;
+        #[cfg(feature = "dtls")]
+        if dtls.is_some() && builder.send_socket.is_some() {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "DTLS cannot be combined with a send_socket"));
+        }
+        let secure = |udp: UdpSocket| -> Result<Arc<dyn Transport>, std::io::Error> {
+            #[cfg(feature = "dtls")]
+            if let Some(config) = &dtls {
+                return Ok(Arc::new(DtlsTransport::new(udp, config)?));
+            }
+            return Ok(Arc::new(udp));
+        };
+
+        let mut bound: Vec<Arc<dyn Transport>> = Vec::with_capacity(sockets.len());
+        let udp: Arc<dyn Transport> = match builder.transport {
+            Some(transport) => {
+                let addr = transport.local_addr().map(|addr| addr.to_string()).unwrap_or_default();
+                transport.set_nonblocking(non_blocking).map_err(|e| BuilderError::configure(&addr, e))?;
+                transport.set_read_timeout(read_timeout).map_err(|e| BuilderError::configure(&addr, e))?;
+                bound.push(Arc::from(transport));
+                bound[0].clone()
+            },
+            None => {
+                for socket in sockets {
+                    let addrs = Self::resolve(&socket, resolve)?;
+                    let udp: UdpSocket = Self::bind(&addrs, reuse_port).map_err(|e| BuilderError::bind(&socket, e))?;
+                    configure(&udp).map_err(|e| BuilderError::configure(&socket, e))?;
+                    bound.push(secure(udp).map_err(|e| BuilderError::configure(&socket, e))?);
+                }
+
+                match builder.send_socket {
+                    Some(socket) => {
+                        let addrs = Self::resolve(&socket, resolve)?;
+                        let udp: UdpSocket = Self::bind(&addrs, reuse_port).map_err(|e| BuilderError::bind(&socket, e))?;
+                        configure(&udp).map_err(|e| BuilderError::configure(&socket, e))?;
+                        Arc::new(udp)
+                    },
+                    None => bound[0].clone()
+                }
+            }
+        };
+
+        let mut msg_map = MsgStorage::new(preserve_global_order, queue_capacity, max_queue_len, message_ttl, clock.clone(), id_hash_seed, builder.capture_dead_letters);
+        msg_map.retain_on_error = builder.get_retains_on_error;
+        msg_map.max_queues = builder.max_queues;
+        msg_map.per_sender_queues = builder.per_sender_queues;
+        let msg_map = Arc::from(msg_map);
+
+        Ok(UdpManager {
+            udp,
+            sockets: bound,
+            stop: ThreadSafe::from(false),
+            threads: vec![],
+            listeners: vec![],
+            idle: vec![],
+            socket_mode: Mutex::from((non_blocking, read_timeout)),
+            recv_one_buffer: Mutex::from(vec![0; builder.buffer_len]),
+            shutdown_timeout: builder.shutdown_timeout,
+            detach_on_drop: builder.detach_on_drop,
+            last_error: ThreadSafe::from(None),
+            on_recv_error,
+            on_raw,
+            on_unknown,
+            on_version_mismatch,
+            send_buffer_hint,
+            format: None,
+            outgoing: None,
+            sender: None,
+            flush_deadline: ThreadSafe::from(None),
+            sweep_interval,
+            coalescer: builder.coalesce.map(|(max_bytes, max_delay)| Arc::from(Coalescer::new(max_bytes, max_delay, msg_map.clone()))),
+            framing,
+            circuit_breaker: builder.circuit_breaker.map(|(threshold, cooldown)| Arc::from(CircuitBreaker::new(threshold, cooldown, clock.clone(), msg_map.clone()))),
+            send_retries: SendRetries { max: builder.send_retries.0, backoff: builder.send_retries.1 },
+            ignore_send_errors: builder.ignore_send_errors,
+            dropped_sends: Arc::new(AtomicU64::new(0)),
+            block_send_when_full: builder.block_send_when_full && max_queue_len.is_some(),
+            batch_recv: if recv_ttl { 1 } else { builder.batch_recv },
+            recv_ttl,
+            idle_sleep: builder.idle_sleep,
+            auto_ack: builder.auto_ack,
+            #[cfg(feature = "json")]
+            tag_in_body,
+            #[cfg(feature = "netsim")]
+            simulate: builder.simulate,
+            #[cfg(feature = "netsim")]
+            delayed: Arc::new(DelayQueue::new(msg_map.clone())),
+            resource_type,
+            msg_map,
+            use_ids
+        })
+    }
+
+    /// Resolves the address into the ones allowed by the mode. Fails with BuilderError::Resolve 
+    /// if none are left.
+    fn resolve(addr: &str, mode: ResolveMode)->Result<Vec<SocketAddr>, std::io::Error> 
+    {
+        let resolved = addr.to_socket_addrs().map_err(|e| BuilderError::resolve(addr, e))?;
+        let addrs: Vec<SocketAddr> = match mode {
+            ResolveMode::First => resolved.take(1).collect(),
+            ResolveMode::Ipv4Only => resolved.filter(SocketAddr::is_ipv4).collect(),
+            ResolveMode::Ipv6Only => resolved.filter(SocketAddr::is_ipv6).collect(),
+            ResolveMode::All => resolved.collect()
+        };
+
+        if addrs.is_empty() {
+            return Err(BuilderError::resolve(addr, 
+                std::io::Error::new(ErrorKind::AddrNotAvailable, format!("no address allowed by ResolveMode::{:?}", mode))));
+        }
+        return Ok(addrs);
+    }
+
+    /// Binds a socket to the first of the addresses that can be bound, setting SO_REUSEPORT first 
+    /// if requested.
+    fn bind(addrs: &[SocketAddr], reuse_port: bool)->Result<UdpSocket, std::io::Error> 
+    {
+        if !reuse_port {
+            return UdpSocket::bind(addrs);
+        }
+
+        let mut last_error = std::io::Error::new(ErrorKind::InvalidInput, "No addresses to bind to");
+        for addr in addrs {
+            let socket = Socket::new(Domain::for_address(*addr), Type::DGRAM, Some(Protocol::UDP))?;
+            Self::set_reuse_port(&socket)?;
+            match socket.bind(&(*addr).into()) {
+                Ok(()) => return Ok(socket.into()),
+                Err(e) => last_error = e
+            }
+        }
+        return Err(last_error);
+    }
+
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    fn set_reuse_port(socket: &Socket)->Result<(), std::io::Error> 
+    {
+        return socket.set_reuse_port(true);
+    }
+
+    #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+    fn set_reuse_port(_socket: &Socket)->Result<(), std::io::Error> 
+    {
+        return Err(std::io::Error::new(ErrorKind::Unsupported, "SO_REUSEPORT is not supported on this platform"));
+    }
+
+    /// Binds the socket to the named network interface. Only supported on Linux.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(udp: &UdpSocket, iface: &str)->Result<(), std::io::Error> 
+    {
+        return SockRef::from(udp).bind_device(Some(iface.as_bytes()));
+    }
+
+    /// Binds the socket to the named network interface. Only supported on Linux.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn bind_device(_udp: &UdpSocket, _iface: &str)->Result<(), std::io::Error> 
+    {
+        return Err(std::io::Error::new(ErrorKind::Unsupported, "Binding to a device is only supported on Linux"));
+    }
+
+    /// Spawns a background thread for receiving datagrams on each socket, the one for sending 
+    /// datagrams if async_send is enabled, and the one removing expired datagrams if sweep_interval
+    /// is set. Only callable by builder.
+    /// 
+    /// # Errors
+    ///  
+    /// Fails if unable to create a new thread at the OS level.
+    fn start(&mut self, buffer_len: usize, async_send: bool, manual_poll: bool)->Result<(), std::io::Error> 
+    {
+        if async_send {
+            let (outgoing, queued) = mpsc::channel::<Outgoing>();
+            let udp = self.udp.clone();
+            let last_error = self.last_error.clone();
+            let flush_deadline = self.flush_deadline.clone();
+            let circuit_breaker = self.circuit_breaker.clone();
+            let send_retries = self.send_retries;
+            let dropped_sends = self.dropped_sends.clone();
+            let msg_map = self.msg_map.clone();
+
+            let sender = thread::Builder::new()
+                .name(String::from("thread_udp_sender"))
+                .spawn( move || {
+                    for (wtr, dest_addr, expires) in queued {
+                        if msg_map.lock(flush_deadline.mutex()).is_some_and(|deadline| Instant::now() > deadline) {
+                            break;
+                        }
+                        if expires.is_some_and(|expires| Instant::now() > expires) {
+                            continue;
+                        }
+                        let result = send_retries.send_to(&*udp, &wtr, dest_addr);
+                        if let Some(circuit_breaker) = &circuit_breaker {
+                            circuit_breaker.record(dest_addr, result.is_ok());
+                        }
+                        if let Err(e) = result {
+                            dropped_sends.fetch_add(1, Ordering::Relaxed);
+                            *msg_map.lock(last_error.mutex()) = Some((e.kind(), e.to_string()));
+                        }
+                }})?;
+
+            self.outgoing = Some(outgoing);
+            self.sender = Some(sender);
+        }
+
+        for udp in &self.sockets {
+            let stop = self.stop.clone();
+            let idle = Arc::new(IdlePasses::default());
+            let listener = Listener {
+                udp: udp.clone(),
+                stop: stop.clone(),
+                idle: idle.clone(),
+                msg_map: self.msg_map.clone(),
+                last_error: self.last_error.clone(),
+                on_recv_error: self.on_recv_error.clone(),
+                on_raw: self.on_raw.clone(),
+                on_unknown: self.on_unknown.clone(),
+                on_version_mismatch: self.on_version_mismatch.clone(),
+                buffers: Mutex::from(vec![vec![0; buffer_len]; self.batch_recv]),
+                batch_recv: self.batch_recv,
+                recv_ttl: self.recv_ttl,
+                idle_sleep: self.idle_sleep,
+                auto_ack: self.auto_ack,
+                coalesce: self.coalescer.is_some(),
+                framing: self.framing,
+                #[cfg(feature = "json")]
+                tag_in_body: self.tag_in_body.clone(),
+                #[cfg(feature = "netsim")]
+                simulate: self.simulate,
+                #[cfg(feature = "netsim")]
+                delayed: self.delayed.clone()
+            };
+
+            if manual_poll {
+                self.listeners.push(listener);
+                continue;
+            }
+            self.idle.push(idle);
+
+            let thread = thread::Builder::new()
+                .name(String::from("thread_udp_listener"))
+                .spawn( move || {
+                    while !*listener.msg_map.lock(stop.mutex()) {
+                        listener.try_recv();
+                }})?;
+
+            self.threads.push(thread);
+        }
+
+        if let Some(coalescer) = &self.coalescer {
+            let stop = self.stop.clone();
+            let coalescer = coalescer.clone();
+            let udp = self.udp.clone();
+            let last_error = self.last_error.clone();
+            let send_retries = self.send_retries;
+            let dropped_sends = self.dropped_sends.clone();
+            let msg_map = self.msg_map.clone();
+
+            let thread = thread::Builder::new()
+                .name(String::from("thread_udp_coalescer"))
+                .spawn( move || {
+                    while !*msg_map.lock(stop.mutex()) {
+                        thread::sleep(coalescer.max_delay.min(Duration::from_millis(5)));
+                        for (wtr, dest_addr) in coalescer.take_due(false) {
+                            if let Err(e) = send_retries.send_to(&*udp, &wtr, dest_addr) {
+                                dropped_sends.fetch_add(1, Ordering::Relaxed);
+                                *msg_map.lock(last_error.mutex()) = Some((e.kind(), e.to_string()));
+                            }
+                        }
+                }})?;
+
+            self.threads.push(thread);
+        }
+
+        #[cfg(feature = "netsim")]
+        if self.simulate.is_some() {
+            let stop = self.stop.clone();
+            let delayed = self.delayed.clone();
+            let msg_map = self.msg_map.clone();
+
+            let thread = thread::Builder::new()
+                .name(String::from("thread_udp_netsim"))
+                .spawn( move || {
+                    //Waits in short steps so stopping the manager is not delayed by a long delay
+                    while !*msg_map.lock(stop.mutex()) {
+                        for (id, msg) in delayed.take_due(Duration::from_millis(5)) {
+                            msg_map.add_msg(id, msg);
+                        }
+                }})?;
+
+            self.threads.push(thread);
+        }
+
+        if let Some(interval) = self.sweep_interval {
+            let stop = self.stop.clone();
+            let msg_map = self.msg_map.clone();
+
+            let thread = thread::Builder::new()
+                .name(String::from("thread_udp_sweeper"))
+                .spawn( move || {
+                    //Sleeps in short steps so stopping the manager is not delayed by a long interval
+                    let mut last_sweep = Instant::now();
+                    while !*msg_map.lock(stop.mutex()) {
+                        thread::sleep(interval.min(Duration::from_millis(50)));
+                        if last_sweep.elapsed() >= interval {
+                            msg_map.sweep();
+                            last_sweep = Instant::now();
+                        }
+                }})?;
+
+            self.threads.push(thread);
+        }
+        return Ok(())
+    }
+
+
+    /// Sends every datagram still queued by async_send, waiting at most FLUSH_TIMEOUT, and joins 
+    /// the sending thread.
+    fn flush(&mut self)
+    {
+        if let Some(coalescer) = self.coalescer.clone() {
+            for (wtr, dest_addr) in coalescer.take_due(true) {
+                let _ = self.transmit_now(wtr, dest_addr, None);
+            }
+        }
+
+        *self.msg_map.lock(self.flush_deadline.mutex()) = Some(Instant::now() + FLUSH_TIMEOUT);
+        self.outgoing.take();
+        self.sender.take().map(thread::JoinHandle::join);
+    }
+
+    /// Safely closes the background threads. Automatically called when struct is dropped.
+    /// Returns false if one of them panicked.
+    fn stop(&mut self) -> bool
+    {
+        *self.msg_map.lock(self.stop.mutex()) = true;
+        if !self.threads.is_empty() && !self.msg_map.lock(&self.socket_mode).0 {
+            self.wake_listeners();
+        }
+
+        let deadline = self.shutdown_timeout.map(|timeout| Instant::now() + timeout);
+        let mut clean = true;
+        for thread in self.threads.drain(..) {
+            if let Some(deadline) = deadline {
+                while !thread.is_finished() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                //Dropping the handle detaches a thread that is still running
+                if !thread.is_finished() {
+                    continue;
+                }
+            }
+            clean &= thread.join().is_ok();
+        }
+        return clean;
+    }
+
+    /// Tells the background threads to stop without waiting for them, dropping their handles so
+    /// they are detached.
+    fn detach(&mut self)
+    {
+        *self.msg_map.lock(self.stop.mutex()) = true;
+        if !self.threads.is_empty() && !self.msg_map.lock(&self.socket_mode).0 {
+            self.wake_listeners();
+        }
+        self.threads.clear();
+    }
+
+    /// Sends an empty datagram to every socket from itself, so listeners blocked in recv_from 
+    /// return and notice the stop.
+    fn wake_listeners(&self)
+    {
+        for udp in &self.sockets {
+            if let Ok(mut addr) = udp.local_addr() {
+                if addr.ip().is_unspecified() {
+                    match addr {
+                        SocketAddr::V4(_) => addr.set_ip(std::net::Ipv4Addr::LOCALHOST.into()),
+                        SocketAddr::V6(_) => addr.set_ip(std::net::Ipv6Addr::LOCALHOST.into())
+                    }
+                }
+                let _ = udp.send_to(&[], addr);
+            }
+        }
+    }
+
+    /// Stops the background threads and closes the sockets, so the OS frees their ports immediately
+    /// rather than when the manager is dropped.
+    /// 
+    /// Datagrams still queued by async_send or coalesce are sent first, like when the manager is 
+    /// dropped. Datagrams already received stay available, but every later send or receive fails
+    /// with NotConnected. A transport set with Builder::transport is dropped as well.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if one of the background threads panicked. The sockets are closed regardless.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn shutdown(&mut self)->Result<(), std::io::Error> 
+    {
+        self.flush();
+        let clean = self.stop();
+
+        let closed: Arc<dyn Transport> = Arc::new(Closed);
+        self.listeners.clear();
+        self.udp = closed.clone();
+        self.sockets = vec![closed; self.sockets.len()];
+
+        if !clean {
+            return Err(std::io::Error::other("A background thread panicked"));
+        }
+        return Ok(());
+    }
+
+    /// Converts the manager into one using another SerDes format, keeping its sockets and queues.
+    /// 
+    /// Received datagrams are stored as raw bytes, so nothing queued is lost, although datagrams 
+    /// received before the switch still need the old format to be deserialized (see 
+    /// get_next_any). The background threads only share format-agnostic state, so they are handed
+    /// over to the new manager and keep running, and no datagram is missed during the switch. 
+    /// Converting into Dynamic is not supported as it has no format to use; start one with 
+    /// Builder::start_dyn instead.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn into_format<U>(mut self)->UdpManager<U> 
+        where U: SerDesType
+    {
+        //Everything the old manager would stop or flush when dropped is moved out of it
+        return UdpManager {
+            udp: self.udp.clone(),
+            sockets: self.sockets.clone(),
+            msg_map: self.msg_map.clone(),
+            resource_type: PhantomData,
+            stop: std::mem::replace(&mut self.stop, ThreadSafe::from(false)),
+            threads: std::mem::take(&mut self.threads),
+            listeners: std::mem::take(&mut self.listeners),
+            idle: self.idle.clone(),
+            socket_mode: Mutex::from(*self.msg_map.lock(&self.socket_mode)),
+            recv_one_buffer: Mutex::from(std::mem::take(&mut *self.msg_map.lock(&self.recv_one_buffer))),
+            shutdown_timeout: self.shutdown_timeout,
+            detach_on_drop: self.detach_on_drop,
+            last_error: self.last_error.clone(),
+            on_recv_error: self.on_recv_error.clone(),
+            on_raw: self.on_raw.clone(),
+            on_unknown: self.on_unknown.clone(),
+            on_version_mismatch: self.on_version_mismatch.clone(),
+            send_buffer_hint: self.send_buffer_hint,
+            format: None,
+            outgoing: self.outgoing.take(),
+            sender: self.sender.take(),
+            flush_deadline: std::mem::replace(&mut self.flush_deadline, ThreadSafe::from(None)),
+            sweep_interval: self.sweep_interval,
+            coalescer: self.coalescer.take(),
+            circuit_breaker: self.circuit_breaker.clone(),
+            send_retries: self.send_retries,
+            ignore_send_errors: self.ignore_send_errors,
+            dropped_sends: self.dropped_sends.clone(),
+            block_send_when_full: self.block_send_when_full,
+            batch_recv: self.batch_recv,
+            recv_ttl: self.recv_ttl,
+            idle_sleep: self.idle_sleep,
+            auto_ack: self.auto_ack,
+            framing: self.framing,
+            #[cfg(feature = "json")]
+            tag_in_body: self.tag_in_body.clone(),
+            #[cfg(feature = "netsim")]
+            simulate: self.simulate,
+            #[cfg(feature = "netsim")]
+            delayed: self.delayed.clone(),
+            use_ids: self.use_ids
+        };
+    }
+
+    /// Provides the socket used for sending, for options the Builder struct does not cover.
+    /// 
+    /// Options such as set_ttl or join_multicast_v6 can be applied directly. Unless send_socket is
+    /// set, this is also the first socket the background thread receives on, so changes apply to
+    /// receiving as well. Prefer UdpManager::set_nonblocking and set_read_timeout over the methods 
+    /// of the socket, as the manager restores its own mode after recv_one. Returns None when the 
+    /// manager uses another transport or has been shut down.
+    pub fn socket(&self)->Option<&UdpSocket> 
+    {
+        return self.udp.as_udp_socket();
+    }
+
+    /// Provides the local addresses of every socket the manager receives on, in the order they were
+    /// configured with the Builder struct. The first is the one used for sending
+    /// unless send_socket is set.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the address of a socket could not be queried.
+    pub fn local_addrs(&self)->Result<Vec<SocketAddr>, std::io::Error> 
+    {
+        return self.sockets.iter().map(|udp| udp.local_addr()).collect();
+    }
+
+    /// Provides the oldest datagram of the specified type, if one exists. 
+    /// 
+    /// Attempts to retrieve the serialized object from the underlying storage depending
+    /// on the requested data type. The serialized object is removed (if one exists) 
+    /// from the underyling storage regardless of deserialization success.
+    /// The deserialized object is returned to the user, if deserialization is successful
+    /// 
+    /// Datagrams of a type are kept in arrival order across all senders, so the datagrams of each 
+    /// sender are always provided in the order they arrived, interleaved with those of others.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get<J>(&self)->Result<(SocketAddr, J), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        let (obj, msg) = self.msg_map.get_obj(self.use_ids, self.decoder::<J>())?;
+        return Ok((msg.addr, obj));
+    }
+
+    /// Provides the oldest datagram of the specified type along with the time it was received.
+    /// 
+    /// Behaves exactly like get, but also returns the Instant captured by the background thread
+    /// when the datagram was read from the socket. This is useful for measuring latency or
+    /// discarding stale datagrams.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_with_time<J>(&self)->Result<(SocketAddr, J, Instant), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        let (obj, msg) = self.msg_map.get_obj(self.use_ids, self.decoder::<J>())?;
+        return Ok((msg.addr, obj, msg.received));
+    }
+
+    /// Provides the oldest datagram of the specified type along with its envelope metadata.
+    /// 
+    /// Behaves exactly like get, but also returns the fields configured with Builder::envelope, 
+    /// as sent with send_env. Every field is 0 when no envelope is configured.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_env<J>(&self)->Result<(SocketAddr, EnvelopeMeta, J), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        let (obj, msg) = self.msg_map.get_obj(self.use_ids, self.decoder::<J>())?;
+        return Ok((msg.addr, msg.meta, obj));
+    }
+
+    /// Provides the oldest datagram of the specified type along with the address of the peer it 
+    /// was received from and the address it originates from.
+    /// 
+    /// Behaves exactly like get. The origin is the address given to send_with_origin by the 
+    /// sender, which is usually a relay, or the address of the sender itself for datagrams sent 
+    /// any other way.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_with_origin<J>(&self)->Result<(SocketAddr, SocketAddr, J), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        let (obj, msg) = self.msg_map.get_obj(self.use_ids, self.decoder::<J>())?;
+        return Ok((msg.addr, msg.origin.unwrap_or(msg.addr), obj));
+    }
+
+    /// Provides the oldest datagram of the specified type along with the TTL (or IPv6 hop limit)
+    /// it arrived with.
+    /// 
+    /// Behaves exactly like get. The TTL is only captured when recv_ttl is enabled with the 
+    /// Builder struct on Linux, and is None otherwise.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_with_ttl<J>(&self)->Result<(SocketAddr, Option<u8>, J), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        let (obj, msg) = self.msg_map.get_obj(self.use_ids, self.decoder::<J>())?;
+        return Ok((msg.addr, msg.ttl, obj));
+    }
+
+    /// Provides all datagrams of the specified type, if any exist.
+    /// 
+    /// Attempts to retrieve all serialized objects from the underlying storage depending
+    /// on the requested data type. If storage for the object exists, it will attempt to 
+    /// deserialize any datagrams that exist. If deserialization fails, the datagram is lost.
+    /// It will return an empty vector as long as the underlying storage existed. If use_ids is 
+    /// set to false, this will return only the datagrams that were able to be converted. All 
+    /// others are removed.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the underlying storage for that data type does not exist (different 
+    /// than being empty) or the data could not be deserialized
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_all<J>(&self)->Result<Vec<(std::net::SocketAddr, J)>, std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.get_obj_all(self.use_ids, usize::MAX, self.decoder::<J>()).map(|(objs, _)| objs);
+    }
+
+    /// Provides all datagrams of the specified type, along with the number that failed to 
+    /// deserialize.
+    /// 
+    /// Behaves like get_all, but an empty vector is returned when the underlying storage does not
+    /// exist. When use_ids is set to false every type shares one queue, so the count tells how many
+    /// queued datagrams were of another type. They are lost, or kept when get_retains_on_error is set.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_all_counted<J>(&self)->(Vec<(SocketAddr, J)>, usize)
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.get_obj_all(self.use_ids, usize::MAX, self.decoder::<J>()).unwrap_or_default();
+    }
+
+    /// Provides at most max of the oldest datagrams of the specified type.
+    /// 
+    /// Behaves like get_all, but removes no more than max datagrams from the underlying storage.
+    /// This bounds the time the lock is held and the size of the returned vector, allowing large
+    /// backlogs to be processed in batches. Datagrams that fail to deserialize are lost and count
+    /// towards max.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the underlying storage for that data type does not exist.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_n<J>(&self, max: usize)->Result<Vec<(SocketAddr, J)>, std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.get_obj_all(self.use_ids, max, self.decoder::<J>()).map(|(objs, _)| objs);
+    }
+
+    /// Provides all datagrams of the specified type that satisfy the predicate.
+    /// 
+    /// Attempts to deserialize every datagram queued for the requested data type and applies
+    /// the predicate to the sender and deserialized object. Matching datagrams are removed from
+    /// the underlying storage and returned. Datagrams that do not match, or that fail to 
+    /// deserialize, stay queued in their original order.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_where<J, P>(&self, pred: P)->Vec<(SocketAddr, J)>
+        where J: de::DeserializeOwned + 'static, P: Fn(&SocketAddr, &J) -> bool
+    {
+        return self.msg_map.get_where(self.use_ids, self.decoder::<J>(), pred);
+    }
+
+    /// Provides the oldest untyped datagram decoded by the first decoder that succeeds.
+    /// 
+    /// Intended for use_ids set to false, where datagrams of heterogeneous types share a single
+    /// queue. The oldest datagram is handed to each decoder in order; the first decoder that 
+    /// returns Some wins and the datagram is removed from the underlying storage. If no decoder
+    /// succeeds, the datagram is left in place. The get_first_of macro builds the decoders from 
+    /// a list of types for convenience.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or no decoder could decode the datagram.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_first_of<R>(&self, decoders: &[Decoder<'_, R>])->Result<(SocketAddr, R), std::io::Error>
+    {
+        return self.msg_map.get_first_of(decoders);
+    }
+
+    /// Deserializes a raw payload using the SerDes format of the manager.
+    /// 
+    /// Useful for payloads obtained without a type, such as those returned by get_next_any.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the data could not be deserialized.
+    pub fn decode_payload<J>(&self, payload: &[u8])->Result<J, std::io::Error>
+        where J: de::DeserializeOwned
+    {
+        return Self::decode_with(&self.format, payload);
+    }
+
+    /// Deserializes a raw payload with the runtime format, if one is set, or the SerDes format T.
+    fn decode_with<J>(format: &Option<Arc<dyn DynSerDes>>, payload: &[u8])->Result<J, std::io::Error>
+        where J: de::DeserializeOwned
+    {
+        if let Some(format) = format {
+            return serdes::deserial_dyn(format.as_ref(), payload);
+        }
+
+        match T::deserial(payload) {
+            Ok(obj) => return Ok(obj),
+            Err(_) => return Err(std::io::Error::new(ErrorKind::InvalidData, "Could not be deserialized"))
+        }
+    }
+
+    /// Serializes a datagram using the SerDes format of the manager.
+    fn encode_payload<J>(&self, datagram: &J)->Result<Vec<u8>, std::io::Error>
+        where J: ser::Serialize
+    {
+        #[cfg(feature = "json")]
+        if let Some(field) = &self.tag_in_body {
+            return tag_body(field, short_type_name::<J>(), datagram);
+        }
+
+        if let Some(format) = &self.format {
+            return format.serial(datagram);
+        }
+
+        match T::serial(datagram) {
+            Ok(obj) => return Ok(obj),
+            Err(_) => return Err(std::io::Error::new(ErrorKind::InvalidData, "Could not serialize"))
+        }
+    }
+
+    /// Serializes a datagram onto the end of the buffer using the SerDes format of the manager.
+    fn encode_payload_into<J>(&self, datagram: &J, wtr: &mut Vec<u8>)->Result<(), std::io::Error>
+        where J: ser::Serialize
+    {
+        #[cfg(feature = "json")]
+        let tagged = self.tag_in_body.is_some();
+        #[cfg(not(feature = "json"))]
+        let tagged = false;
+
+        if tagged || self.format.is_some() {
+            wtr.extend_from_slice(&self.encode_payload(datagram)?);
+            return Ok(());
+        }
+
+        match T::serial_into(datagram, wtr) {
+            Ok(()) => return Ok(()),
+            Err(_) => return Err(std::io::Error::new(ErrorKind::InvalidData, "Could not serialize"))
+        }
+    }
+
+    /// Provides a decoder for the specified type that uses the SerDes format of the manager.
+    fn decoder<J>(&self)->impl Fn(&[u8]) -> Result<J, std::io::Error> + '_
+        where J: de::DeserializeOwned
+    {
+        return move |payload: &[u8]| self.decode_payload::<J>(payload);
+    }
+
+    /// Subscribes to datagrams of the specified type through a channel.
+    /// 
+    /// Once subscribed, the background thread deserializes datagrams of the requested type as they
+    /// arrive and sends them into the returned channel instead of the underlying storage. Datagrams
+    /// that fail to deserialize are stored as usual. Only one subscription exists per type; 
+    /// subscribing again replaces the previous one. Dropping the Receiver ends the subscription
+    /// and datagrams are stored again.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn subscribe<J>(&self)->mpsc::Receiver<(SocketAddr, J)>
+        where J: de::DeserializeOwned + Send + 'static, T: 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        let format = self.format.clone();
+
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id::<J>();
+        }
+
+        self.msg_map.subscribe(id, Box::new(move |addr, payload| {
+            match Self::decode_with::<J>(&format, payload) {
+                Ok(obj) => match tx.send((addr, obj)) {
+                    Ok(_) => return Delivery::Delivered,
+                    Err(_) => return Delivery::Closed
+                },
+                Err(_) => return Delivery::Undecodable
+            }
+        }));
+
+        return rx;
+    }
+
+    /// Provides the oldest datagram of any registered type, dispatched into a single enum.
+    /// 
+    /// Finds the oldest datagram whose type was registered with the registry, removes it from the 
+    /// underlying storage and converts it with the registered function. Datagrams of unregistered
+    /// types are left in place. The oldest datagram is the one received first by the background 
+    /// thread, while preserve_global_order also orders datagrams received at the same instant. A 
+    /// datagram that fails to deserialize is lost, like with get.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_enum<E>(&self, registry: &MessageRegistry<E>)->Option<(SocketAddr, E)>
+    {
+        let entries: Vec<(u64, &RegistryEntry<E>)> = registry.entries
+            .iter()
+            .map(|entry| {
+                let mut id = 1;
+                if self.use_ids {
+                    id = (entry.id_of)(&self.msg_map);
+                }
+                return (id, entry);
+            })
+            .collect();
+
+        let ids: Vec<u64> = entries.iter().map(|(id, _)| *id).collect();
+        let (id, addr, payload) = self.msg_map.pop_oldest_of(&ids)?;
+
+        for (entry_id, entry) in entries {
+            if entry_id != id {
+                continue;
+            }
+            let mut obj = None;
+            let decoded = self.decode_erased(&payload, &mut |de| {
+                obj = Some((entry.decode)(addr, de)?);
+                return Ok(());
+            });
+            if decoded.is_ok() {
+                return obj.map(|obj| (addr, obj));
+            }
+        }
+        return None;
+    }
+
+    /// Creates a type-erased deserializer over a raw payload with the SerDes format of the manager.
+    fn decode_erased<'de>(&self, payload: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        if let Some(format) = &self.format {
+            return format.deserial_into(payload, visit);
+        }
+        return T::deserial_erased(payload, visit);
+    }
+
+    /// Provides the oldest datagram of the specified type, waiting up to the timeout for one to 
+    /// arrive, or the default value of the type with no address if none did.
+    /// 
+    /// Useful in loops that must produce a value on every tick. A datagram that arrives but fails
+    /// to deserialize is removed and waiting continues.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_or_default<J>(&self, timeout: Duration)->(Option<SocketAddr>, J)
+        where J: de::DeserializeOwned + Default + 'static
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id::<J>();
+        }
+
+        let deadline = Instant::now() + timeout;
+        while self.msg_map.wait_for(id, deadline) {
+            if let Ok((addr, obj)) = self.get::<J>() {
+                return (Some(addr), obj);
+            }
+        }
+        return (None, J::default());
+    }
+
+    /// Provides the oldest datagram of the specified type, if one exists, without
+    /// removing it from the underlying storage.
+    /// 
+    /// Attempts to retrieve the serialized object from the underlying storage depending
+    /// on the requested data type. If a serialized object is available, a copy is taken and
+    /// an attempt to deserialize the data is made. If successful, the deserialized object is 
+    /// returned to the user.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn peek<J>(&self)->Result<(SocketAddr, J), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.peek(self.use_ids, self.decoder::<J>());
+    }
+
+    /// Provides the oldest datagram of the specified type sent from the address, if one exists.
+    /// 
+    /// Datagrams from other senders, including older ones, are left untouched, so a sender's 
+    /// datagrams can be consumed strictly in the order they arrived regardless of others. Finding
+    /// the datagram takes time proportional to the datagrams of the type queued ahead of it, unless
+    /// per_sender_queues is set. If use_ids is set false, the oldest datagram from the address is 
+    /// used and the specified type is ignored. The datagram is removed regardless of deserialization success, as with get.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when no datagram from the address is stored or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_from<J>(&self, addr: SocketAddr)->Result<J, std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.get_from(self.use_ids, addr, self.decoder::<J>());
+    }
+
+    /// Provides the oldest datagram of the specified type sent from the address, if one exists, 
+    /// without removing it from the underlying storage.
+    /// 
+    /// Datagrams from other senders, including older ones, are left untouched. If use_ids is set 
+    /// false, the oldest datagram from the address is used and the specified type is ignored.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when no datagram from the address is stored or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn peek_from<J>(&self, addr: SocketAddr)->Result<J, std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.peek_from(self.use_ids, addr, self.decoder::<J>());
+    }
+
+    /// Removes the oldest datagram of the specified type, if one exists, without providing
+    /// it to the user.
+    /// 
+    /// if use_ids is set false, it will remove the oldest datagram and the specified type is ignored.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage does not exist.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn remove_front<J>(&self)->Result<(), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.remove_front::<J>(self.use_ids);
+    }
+
+    /// Removes all datagram of the specified type, if one exists, without providing
+    /// it to the user.
+    /// 
+    /// if use_ids is set false, it will remove all datagram and the specified type is ignored.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage does not exist.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn remove_all<J>(&self) -> Result<(), std::io::Error>
+        where J: de::DeserializeOwned + 'static
+    {
+        return self.msg_map.remove_all::<J>(self.use_ids);
+    }
+
+    /// Takes every stored datagram of the specified type at once, oldest first, without deserializing them.
+    /// 
+    /// The queue is swapped for an empty one under a single lock, so the background thread is held up
+    /// for less time than with get_all, which deserializes while holding the lock. The payloads can
+    /// then be deserialized with decode_payload.
+    /// 
+    /// if use_ids is set false, it will take every datagram and the specified type is ignored.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn take_queue<J>(&self) -> Vec<(SocketAddr, Vec<u8>)>
+        where J: 'static
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id::<J>();
+        }
+        return self.msg_map.take_queue(id);
+    }
+
+    /// Moves the oldest datagram of the specified type to the back of its queue, so other 
+    /// datagrams get a turn first.
+    /// 
+    /// if use_ids is set false, it will rotate the single queue and the specified type is ignored.
+    /// With preserve_global_order, the datagram is also moved behind every other datagram
+    /// for get_next_any.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when no datagram of the specified type is stored.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn rotate<J>(&self) -> Result<(), std::io::Error>
+        where J: 'static
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id::<J>();
+        }
+        return self.msg_map.rotate(id);
+    }
+
+    /// Puts a datagram taken out of storage (e.g. by get_next_any) back at the front of the 
+    /// queue for the id, so it is the next one returned.
+    /// 
+    /// if use_ids is set false, the single queue is keyed by the id 1. With 
+    /// preserve_global_order, the datagram is also placed ahead of every other datagram 
+    /// for get_next_any.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn requeue_front(&self, id: u64, addr: SocketAddr, bytes: Vec<u8>) 
+    {
+        self.msg_map.requeue_front(id, addr, bytes);
+    }
+
+    /// Deserializes the datagram, appends the ID, and sends to requested location.
+    /// 
+    /// Consumes a datagram and a destination address for the datagram to be sent to.
+    /// An attempt to serialize the data is made; If use_id is true, the datagram ID is prepended 
+    /// to the message. A request to the underlying UDP socket is then made to send the data.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the data could not be serialized or when the underyling 
+    /// UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send<J, A>(&self, datagram: J, dest_addr: A)->Result<(),std::io::Error> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        return self.send_ref(&datagram, dest_addr);
+    }
+
+    /// Serializes and sends each datagram to its own destination.
+    /// 
+    /// Behaves like calling send for every (datagram, address) pair, but collects the result of 
+    /// each send instead of stopping at the first failure. The results are in the order of the items.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_batch<J, A, I>(&self, items: I)->Vec<Result<(),std::io::Error>> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs, I: IntoIterator<Item=(J, A)>
+    {
+        return items.into_iter()
+            .map(|(datagram, dest_addr)| self.send_ref(&datagram, dest_addr))
+            .collect();
+    }
+
+    /// Serializes a borrowed datagram, appends the ID, and sends to requested location.
+    /// 
+    /// Behaves exactly like send, but borrows the datagram so the same message can be sent to 
+    /// several destinations without cloning it. The id is still derived from the type of the datagram.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the data could not be serialized or when the underyling 
+    /// UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_ref<J, A>(&self, datagram: &J, dest_addr: A)->Result<(),std::io::Error> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        let wtr = self.encode(datagram)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Serializes a borrowed datagram into a caller-owned buffer and sends it from there.
+    /// 
+    /// Behaves exactly like send_ref, but the datagram is built in scratch, which is cleared first
+    /// and keeps its capacity. Reusing one buffer across a loop of sends avoids allocating for each
+    /// datagram, with formats that serialize straight into it (see SerDesType::serial_into). With
+    /// async_send or coalesce the datagram is still copied, as it outlives the call.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the data could not be serialized or when the underyling 
+    /// UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_into<J, A>(&self, datagram: &J, dest_addr: A, scratch: &mut Vec<u8>)->Result<(),std::io::Error> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        self.framing.encode_into(self.msg_map.get_id::<J>(), EnvelopeMeta::default(), false, scratch, 
+            |wtr| self.encode_payload_into(datagram, wtr))?;
+
+        if self.coalescer.is_some() || self.outgoing.is_some() {
+            return self.transmit(scratch.clone(), dest_addr);
+        }
+
+        let dest_addr = dest_addr.to_socket_addrs()?.next()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "No addresses to send data to"))?;
+        self.admit(scratch, dest_addr)?;
+        return self.send_direct(scratch, dest_addr);
+    }
+
+    /// Sends a datagram and waits until the destination acknowledges it, retransmitting it each 
+    /// time no acknowledgement arrives within the timeout.
+    /// 
+    /// The datagram is wrapped in a control message carrying a sequence number and is delivered to
+    /// the destination like any other datagram of its type. The destination must be started with 
+    /// Builder::auto_ack to reply. Up to retries retransmissions are made, so the destination may 
+    /// receive duplicates when an acknowledgement is lost. Acknowledgements are collected by the 
+    /// background thread on the socket they arrive at, so they are only seen when the sending 
+    /// socket is also a receiving one and, with manual_poll, while poll_once is called.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns TimedOut if the last retransmission was not acknowledged, Unsupported if use_ids is
+    /// false, or an error when the data could not be serialized or when the underyling UDP socket 
+    /// failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_reliable<J, A>(&self, datagram: J, dest_addr: A, timeout: Duration, retries: u32)->Result<(),std::io::Error> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        if !self.framing.use_ids {
+            return Err(std::io::Error::new(ErrorKind::Unsupported, "Reliable sends require use_ids"));
+        }
+        let dest_addr = dest_addr.to_socket_addrs()?.next()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "No addresses to send data to"))?;
+
+        let seq = self.msg_map.next_reliable_seq.fetch_add(1, Ordering::Relaxed);
+        let payload = self.encode_payload(&datagram)?;
+        let mut wrapped: Vec<u8> = Vec::with_capacity(16 + payload.len());
+        wrapped.write_u64::<BigEndian>(seq)?;
+        wrapped.write_u64::<BigEndian>(self.msg_map.get_id::<J>())?;
+        wrapped.extend_from_slice(&payload);
+        let wtr = self.frame(RELIABLE_ID, &wrapped)?;
+
+        for _ in 0..=retries {
+            self.transmit(wtr.clone(), dest_addr)?;
+            if self.msg_map.wait_for_ack(dest_addr, seq, Instant::now() + timeout) {
+                return Ok(());
+            }
+        }
+        return Err(std::io::Error::new(ErrorKind::TimedOut, "The datagram was not acknowledged"));
+    }
+
+    /// Serializes the datagram and sends it with envelope metadata, such as a tick number.
+    /// 
+    /// Behaves exactly like send, but writes the fields configured with Builder::envelope from 
+    /// meta instead of zeroes. Fields that are not configured are not sent.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the data could not be serialized or when the underyling 
+    /// UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_env<J, A>(&self, datagram: J, dest_addr: A, meta: EnvelopeMeta)->Result<(),std::io::Error> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        let payload = self.encode_payload(&datagram)?;
+
+        let wtr = self.framing.encode(self.msg_map.get_id::<J>(), meta, &payload, false, self.send_buffer_hint)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Serializes the datagram and sends it along with the address it originates from.
+    /// 
+    /// Intended for relays, so the final recipient can tell where a datagram came from with 
+    /// get_with_origin even though it was received from the relay. The datagram is wrapped in a 
+    /// control message carrying its id and the origin, and is delivered like any other datagram of
+    /// its type.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns Unsupported if use_ids is false, or an error when the data could not be serialized 
+    /// or when the underyling UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_with_origin<J, A>(&self, datagram: J, origin: SocketAddr, dest_addr: A)->Result<(),std::io::Error> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        if !self.framing.use_ids {
+            return Err(std::io::Error::new(ErrorKind::Unsupported, "Sending an origin requires use_ids"));
+        }
+
+        let payload = self.encode_payload(&datagram)?;
+        let mut wrapped: Vec<u8> = Vec::with_capacity(27 + payload.len());
+        wrapped.write_u64::<BigEndian>(self.msg_map.get_id::<J>())?;
+        write_addr(&mut wrapped, origin);
+        wrapped.extend_from_slice(&payload);
+        let wtr = self.frame(ORIGIN_ID, &wrapped)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Provides the exact bytes send would transmit for the datagram, without sending them.
+    /// 
+    /// The datagram is serialized and framed like send does: the id is prepended if use_ids is 
+    /// true, followed by the length prefix, compression flag, envelope and checksum when those are enabled.
+    /// The bytes can be cached and sent repeatedly, logged, or inspected in tests. With coalescing,
+    /// several of these frames are packed into a single datagram when sent.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the data could not be serialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn encode<J>(&self, datagram: &J)->Result<Vec<u8>, std::io::Error> 
+        where J: ser::Serialize + 'static
+    {
+        let payload = self.encode_payload(datagram)?;
+
+        return self.frame(self.msg_map.get_id::<J>(), &payload);
+    }
+
+    /// Parses the bytes of a single datagram, as produced by encode, into its id and a typed value.
+    /// 
+    /// Applies the same logic as the background thread: the checksum is verified and the id, length
+    /// prefix and compression flag are stripped when enabled, then the payload is deserialized. The 
+    /// id is 1 when use_ids is false. Useful for datagrams captured outside the manager, such as 
+    /// with a packet capture. Coalesced datagrams must be split first.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the bytes are malformed for the configured layout or could not be 
+    /// deserialized into J.
+    pub fn decode<J>(&self, bytes: &[u8])->Result<(u64, J), std::io::Error> 
+        where J: de::DeserializeOwned
+    {
+        let (id, payload) = self.framing.decode(bytes.to_vec())?;
+
+        return Ok((id, self.decode_payload(&payload)?));
+    }
+
+    /// Sends an already serialized payload under the given id without re-encoding it.
+    /// 
+    /// The id is prepended exactly as send would (or omitted if use_ids is false) and the payload
+    /// is sent unchanged. Combined with get_next_any, this allows relaying datagrams between
+    /// peers byte-for-byte without deserializing and serializing them again.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when use_ids is true and the id falls within RESERVED_IDS, which are used 
+    /// by the crate's internal control messages, or when the underyling UDP socket failed to send 
+    /// the message.
+    pub fn forward<A>(&self, id: u64, payload: &[u8], dest_addr: A)->Result<(),std::io::Error> 
+        where A: ToSocketAddrs
+    {
+        if self.use_ids && RESERVED_IDS.contains(&id) {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Id is reserved for internal control messages"));
+        }
+        let wtr = self.frame(id, payload)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Sends a framed datagram, either directly or through the queue of the sending thread.
+    fn transmit<A>(&self, wtr: Vec<u8>, dest_addr: A)->Result<(),std::io::Error> 
+        where A: ToSocketAddrs
+    {
+        return self.transmit_until(wtr, dest_addr, None);
+    }
+
+    /// Sends a framed datagram. A queued datagram still waiting for the sending thread once 
+    /// expires has passed is discarded.
+    fn transmit_until<A>(&self, wtr: Vec<u8>, dest_addr: A, expires: Option<Instant>)->Result<(),std::io::Error> 
+        where A: ToSocketAddrs
+    {
+        if let Some(coalescer) = &self.coalescer {
+            let dest_addr = dest_addr.to_socket_addrs()?.next()
+                .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "No addresses to send data to"))?;
+            if let Some(full) = coalescer.push(&wtr, dest_addr) {
+                return self.transmit_now(full, dest_addr, expires);
+            }
+            return Ok(());
+        }
+        return self.transmit_now(wtr, dest_addr, expires);
+    }
+
+    /// Sends a datagram without coalescing it, either directly or through the queue of the 
+    /// sending thread.
+    fn transmit_now<A>(&self, wtr: Vec<u8>, dest_addr: A, expires: Option<Instant>)->Result<(),std::io::Error> 
+        where A: ToSocketAddrs
+    {
+        let dest_addr = dest_addr.to_socket_addrs()?.next()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "No addresses to send data to"))?;
+
+        self.admit(&wtr, dest_addr)?;
+
+        match &self.outgoing {
+            Some(outgoing) => {
+                outgoing.send((wtr, dest_addr, expires))
+                    .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "Sending thread has stopped"))?;
+            },
+            None => self.send_direct(&wtr, dest_addr)?
+        }
+        return Ok(());
+    }
+
+    /// Refuses a datagram for a destination whose circuit breaker is open, and waits for room in 
+    /// the receiving queue if block_send_when_full applies.
+    fn admit(&self, wtr: &[u8], dest_addr: SocketAddr)->Result<(),std::io::Error> 
+    {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            if circuit_breaker.is_open(dest_addr) {
+                return Err(std::io::Error::new(ErrorKind::BrokenPipe, "Circuit breaker is open for this destination"));
+            }
+        }
+
+        if self.block_send_when_full && self.is_local(dest_addr) {
+            let mut id = 1;
+            let at = self.framing.version.is_some() as usize;
+            if self.framing.use_ids && wtr.len() >= at + 8 {
+                id = BigEndian::read_u64(&wtr[at..at + 8]);
+            }
+            self.msg_map.wait_for_room(id);
+        }
+        return Ok(());
+    }
+
+    /// Sends a datagram on the calling thread, retrying as configured with send_retries.
+    fn send_direct(&self, wtr: &[u8], dest_addr: SocketAddr)->Result<(),std::io::Error> 
+    {
+        let result = self.send_retries.send_to(&*self.udp, wtr, dest_addr);
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.record(dest_addr, result.is_ok());
+        }
+        if result.is_err() {
+            self.dropped_sends.fetch_add(1, Ordering::Relaxed);
+            if self.ignore_send_errors {
+                return Ok(());
+            }
+        }
+        return result.map(|_| ());
+    }
+
+    /// Determines if the address reaches one of the sockets the manager receives on.
+    fn is_local(&self, dest_addr: SocketAddr)->bool 
+    {
+        return self.sockets.iter()
+            .filter_map(|udp| udp.local_addr().ok())
+            .any(|local| local.port() == dest_addr.port() && 
+                (local.ip() == dest_addr.ip() || local.ip().is_unspecified() || dest_addr.ip().is_loopback()));
+    }
+
+    /// Serializes the datagram, DEFLATEs it, sets the compression flag, and sends to requested location.
+    /// 
+    /// Requires compression_flag to be enabled with the Builder struct. Best suited to large 
+    /// datagrams, as compressing small ones costs time and often makes them larger.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when compression_flag is disabled, when the data could not be serialized 
+    /// or compressed, or when the underyling UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_compressed<J, A>(&self, datagram: &J, dest_addr: A)->Result<(),std::io::Error> 
+        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        let payload = self.compress(&self.encode_payload(datagram)?)?;
+
+        let wtr = self.encode_frame(self.msg_map.get_id::<J>(), &payload, true)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// DEFLATEs a serialized payload, failing if compression_flag is disabled.
+    fn compress(&self, payload: &[u8])->Result<Vec<u8>, std::io::Error> 
+    {
+        if !self.framing.compression_flag {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "compression_flag is not enabled"));
+        }
+
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(payload)?;
+        return encoder.finish();
+    }
+
+    /// Starts building a send with per-message options.
+    /// 
+    /// ```rust,ignore
+    /// net_msg.send_builder(pos)
+    ///     .compressed(true)
+    ///     .to("127.0.0.1:39507")
+    ///     .send()?;
+    /// ```
+    pub fn send_builder<J>(&self, datagram: J)->SendBuilder<'_, T, J> 
+        where J: ser::Serialize + 'static
+    {
+        return SendBuilder {
+            manager: self,
+            datagram,
+            dest_addr: None,
+            compressed: false,
+            id: None,
+            ttl: None
+        };
+    }
+
+    /// Builds the datagram sent over the wire from an id and a serialized payload.
+    fn frame(&self, id: u64, payload: &[u8])->Result<Vec<u8>, std::io::Error> 
+    {
+        return self.encode_frame(id, payload, false);
+    }
+
+    /// Builds the datagram sent over the wire, marking whether the payload is compressed.
+    fn encode_frame(&self, id: u64, payload: &[u8], compressed: bool)->Result<Vec<u8>, std::io::Error> 
+    {
+        return self.framing.encode(id, EnvelopeMeta::default(), payload, compressed, self.send_buffer_hint);
+    }
+
+    /// Provides the globally-oldest datagram across all types, if one exists.
+    /// 
+    /// Removes the oldest datagram from the underlying storage, regardless of type, and returns
+    /// its id, source address and raw payload. The payload can then be deserialized by the user once
+    /// the id has been matched to a type, or relays and proxies can decide what to do with it from
+    /// its id alone. Only available when preserve_global_order is enabled with the Builder struct;
+    /// otherwise always returns None.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_next_any(&self) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        return self.msg_map.get_next_any();
+    }
+
+    /// Sets the priority of a datagram type for get_highest_priority. Types without a priority have 
+    /// priority 0, the lowest.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn set_priority<J>(&self, level: u8) 
+        where J: 'static
+    {
+        self.msg_map.set_priority(self.msg_map.get_id::<J>(), level);
+    }
+
+    /// Sets the largest payload expected for a datagram type, checked as datagrams are received.
+    /// 
+    /// buffer_len must fit the largest type, so it alone cannot catch a small type arriving far
+    /// larger than it should. Received datagrams of the type whose payload, not counting the 
+    /// header, exceeds the size are dropped, counted as malformed in drop_stats and reported to 
+    /// on_recv_error along with their id, size and the limit.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn set_type_max_size<J>(&self, bytes: usize) 
+        where J: 'static
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id::<J>();
+        }
+        self.msg_map.lock(&self.msg_map.max_sizes).insert(id, bytes);
+    }
+
+    /// Removes and provides the oldest datagram of the highest priority type that has one, along 
+    /// with its id.
+    /// 
+    /// Lets a single dispatch loop serve important datagrams, such as position updates, before 
+    /// others like chat, regardless of the order they arrived in. Priorities are set with 
+    /// set_priority. Among types of equal priority, the oldest datagram is returned.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_highest_priority(&self) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        return self.msg_map.pop_highest_priority();
+    }
+
+    /// Provides a copy of the globally-oldest datagram along with its id, without removing it.
+    /// 
+    /// Shows the datagram the next get_next_any would return, so it can be inspected before deciding 
+    /// how to retrieve it, even when its type is unknown. Requires preserve_global_order to be 
+    /// enabled with the Builder struct; otherwise always returns None.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn peek_raw(&self) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        return self.msg_map.peek_next_any();
+    }
+
+    /// Sends a signal datagram for the specified marker type to the requested location.
+    /// 
+    /// Signals carry no data; only the id of the marker type is sent (or an empty datagram if 
+    /// use_ids is set to false). This makes it easy to send pure notifications such as pings
+    /// without relying on how a format serializes a unit struct.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the underyling UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send_signal<J, A>(&self, dest_addr: A)->Result<(),std::io::Error> 
+        where J: 'static, A: ToSocketAddrs
+    {
+        let wtr = self.frame(self.msg_map.get_id::<J>(), &[])?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Provides the sender of the oldest signal of the specified marker type, if one exists.
+    /// 
+    /// The signal is removed from the underlying storage. No deserialization is attempted, so
+    /// this works for any marker type sent with send_signal.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or when the oldest datagram carries a
+    /// payload, in which case it is left in place.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn recv_signal<J>(&self)->Result<SocketAddr, std::io::Error> 
+        where J: 'static
+    {
+        return self.msg_map.recv_signal::<J>(self.use_ids);
+    }
+
+    /// Changes the read timeout of the underlying sockets while the manager is running.
+    /// 
+    /// The background threads share the sockets, so the new timeout takes effect on their next 
+    /// receive attempt; a receive that is already waiting keeps its previous timeout. The timeout
+    /// only matters while the sockets are blocking. If non_blocking is true, the sockets never wait
+    /// and the timeout is ignored until non_blocking is turned off.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the timeout could not be applied to the socket, such as when a zero
+    /// duration is passed.
+    pub fn set_read_timeout(&self, timeout: Option<std::time::Duration>)->Result<(), std::io::Error> 
+    {
+        let mut socket_mode = self.msg_map.lock(&self.socket_mode);
+        for udp in &self.sockets {
+            udp.set_read_timeout(timeout)?;
+        }
+        socket_mode.1 = timeout;
+        return Ok(());
+    }
+
+    /// Switches the underlying sockets between non-blocking and blocking mode while the manager is running.
+    /// 
+    /// Non-blocking mode busy-polls the socket, while blocking mode waits for datagrams using the read
+    /// timeout set with set_read_timeout. Switching to blocking mode without a read timeout means the 
+    /// background thread only wakes when a datagram arrives, which also delays dropping the manager.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the mode could not be applied to the socket.
+    pub fn set_nonblocking(&self, nonblocking: bool)->Result<(), std::io::Error> 
+    {
+        let mut socket_mode = self.msg_map.lock(&self.socket_mode);
+        for udp in &self.sockets {
+            udp.set_nonblocking(nonblocking)?;
+        }
+        socket_mode.0 = nonblocking;
+        return Ok(());
+    }
+
+    /// Waits for a single datagram of the specified type straight from the socket, bypassing the 
+    /// underlying storage.
+    /// 
+    /// Intended for simple request/response clients, ideally started with Builder::manual_poll. 
+    /// The socket is made blocking with a read timeout derived from the deadline, then restored to
+    /// its previous mode. The deadline is checked against the clock of the manager. Datagrams that
+    /// are malformed, of another type or longer than buffer_len are discarded while waiting. While
+    /// the background thread is running, it competes with this method for every datagram. Only the
+    /// first socket is read when several are bound.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns TimedOut if no datagram of the specified type arrived before the deadline, or an 
+    /// error when the underlying socket failed to receive a datagram.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn recv_one<J>(&self, deadline: Instant)->Result<(SocketAddr, J), std::io::Error> 
+        where J: de::DeserializeOwned + 'static
+    {
+        let udp = &self.sockets[0];
+
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id::<J>();
+        }
+
+        let mut buffer = self.msg_map.lock(&self.recv_one_buffer);
+        let result = loop {
+            let now = self.msg_map.clock.now();
+            if now >= deadline {
+                break Err(std::io::Error::new(ErrorKind::TimedOut, "No datagram arrived before the deadline"));
+            }
+            //The mode is switched for every receive, as set_nonblocking may have been called while waiting
+            let switched = {
+                let _socket_mode = self.msg_map.lock(&self.socket_mode);
+                udp.set_nonblocking(false).and_then(|_| udp.set_read_timeout(Some(deadline - now)))
+            };
+            if let Err(e) = switched {
+                break Err(e);
+            }
+
+            let (len, addr) = match udp.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => continue,
+                Err(e) => break Err(e)
+            };
+            match self.decode::<J>(&buffer[..len]) {
+                Ok((received_id, obj)) if received_id == id => break Ok((addr, obj)),
+                _ => continue
+            }
+        };
+
+        drop(buffer);
+
+        let socket_mode = self.msg_map.lock(&self.socket_mode);
+        udp.set_nonblocking(socket_mode.0)?;
+        udp.set_read_timeout(socket_mode.1)?;
+        return result;
+    }
+
+    /// Receives a single datagram from every socket, if one is waiting, and stores it like the 
+    /// background thread would.
+    /// 
+    /// Intended for managers started with Builder::manual_poll, which call this from their own loop.
+    /// The call blocks or returns immediately according to the non_blocking and read_timeout settings.
+    /// Returns whether a datagram was stored; malformed datagrams are reported and dropped as usual.
+    /// Without manual_poll, the background thread does the receiving and this always returns false.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the underlying socket failed to receive a datagram.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn poll_once(&self)->Result<bool, std::io::Error> 
+    {
+        let mut stored = false;
+        for listener in &self.listeners {
+            match listener.recv_once() {
+                Ok(delivered) => stored |= delivered,
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {},
+                Err(e) => return Err(e)
+            }
+        }
+        return Ok(stored);
+    }
+
+    /// Waits until the background threads have received everything that was readable on their 
+    /// sockets when this was called, or the timeout passes. Returns whether they did.
+    /// 
+    /// A deterministic alternative to sleeping after a send to the manager's own address: once 
+    /// this returns true, every datagram that had arrived beforehand has been stored. A thread 
+    /// proves it by finding its socket empty twice after the call, so this relies on non_blocking 
+    /// or a read_timeout and otherwise waits for the whole timeout. Returns false straight away 
+    /// with manual_poll, where the caller decides when datagrams are received.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn sync(&self, timeout: Duration)->bool 
+    {
+        if self.idle.is_empty() {
+            return false;
+        }
+
+        let deadline = Instant::now() + timeout;
+        for idle in &self.idle {
+            let mut count = self.msg_map.lock(&idle.count);
+            //The first pass may have found the socket empty just before this was called
+            let target = *count + 2;
+            while *count < target {
+                let now = Instant::now();
+                if now >= deadline {
+                    return false;
+                }
+                count = idle.passed.wait_timeout(count, deadline - now).unwrap_or_else(|e| self.msg_map.recover(&idle.count, e)).0;
+            }
+        }
+        return true;
+    }
+
+    /// Receives a single datagram straight from the socket into the buffer, bypassing the underlying storage.
+    /// 
+    /// The datagram is provided exactly as it arrived, including the id and any other header. The call
+    /// blocks or returns WouldBlock according to the non_blocking and read_timeout settings. Intended 
+    /// for managers started with Builder::manual_poll: while the background thread is running, it 
+    /// races this method for every datagram. Only the first socket is read when several are bound.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the underlying socket failed to receive a datagram.
+    pub fn recv_into(&self, buf: &mut [u8])->Result<(usize, SocketAddr), std::io::Error> 
+    {
+        return self.sockets[0].recv_from(buf);
+    }
+
+    /// Provides the datagrams that failed to deserialize in get or get_all, oldest first, as raw 
+    /// payloads with their source address.
+    /// 
+    /// Only filled when capture_dead_letters is enabled with the Builder struct; otherwise always 
+    /// empty. The dead letters are kept until clear_dead_letters is called.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn dead_letters(&self) -> Vec<(SocketAddr, Vec<u8>)> 
+    {
+        return match &self.msg_map.dead_letters {
+            Some(dead_letters) => self.msg_map.lock(dead_letters).clone(),
+            None => vec![]
+        };
+    }
+
+    /// Removes every dead letter.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn clear_dead_letters(&self) 
+    {
+        if let Some(dead_letters) = &self.msg_map.dead_letters {
+            self.msg_map.lock(dead_letters).clear();
+        }
+    }
+
+    /// Provides how many received datagrams were dropped so far, by reason.
+    /// 
+    /// Useful for diagnosing lossy deployments: malformed datagrams are counted as they are received,
+    /// while the others are counted when the storage removes them. Datagrams lost by the network
+    /// never reach the manager and cannot be counted.
+    pub fn drop_stats(&self) -> DropStats 
+    {
+        return self.msg_map.drops.snapshot();
+    }
+
+    /// Provides how many datagrams the socket failed to send so far, including those sent by the
+    /// async_send and coalesce threads and those ignored with ignore_send_errors.
+    pub fn dropped_sends(&self) -> u64 
+    {
+        return self.dropped_sends.load(Ordering::Relaxed);
+    }
+
+    /// Sets every counter provided by drop_stats back to zero.
+    pub fn reset_drop_stats(&self) 
+    {
+        self.msg_map.drops.reset();
+    }
+
+    /// Provides the most recent error encountered by the background thread while receiving, if any.
+    /// 
+    /// WouldBlock and TimedOut are not errors in this context and are never recorded. The error is 
+    /// kept until it is replaced by a newer one or removed with take_last_error.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn last_error(&self) -> Option<std::io::Error> 
+    {
+        return self.msg_map.lock(self.last_error.mutex())
+            .as_ref()
+            .map(|(kind, msg)| std::io::Error::new(*kind, msg.clone()));
+    }
+
+    /// Removes and provides the most recent error encountered by the background thread while receiving, if any.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn take_last_error(&self) -> Option<std::io::Error> 
+    {
+        return self.msg_map.lock(self.last_error.mutex())
+            .take()
+            .map(|(kind, msg)| std::io::Error::new(kind, msg));
+    }
+
+    /// Provides whether datagrams carry ids, as set with Builder::use_ids. When they do not, every 
+    /// datagram shares a single queue and the type passed to get, peek and the like is ignored.
+    pub fn uses_ids(&self) -> bool 
+    {
+        return self.use_ids;
+    }
+
+    /// Provides the canonical name of the SerDes format used by the manager (e.g. "json").
+    pub fn format_name(&self) -> &'static str 
+    {
+        if let Some(format) = &self.format {
+            return format.name();
+        }
+        return T::NAME;
+    }
+
+    /// Provides the distinct source addresses of all datagrams currently queued, across all types.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn active_peers(&self) -> Vec<SocketAddr> 
+    {
+        return self.msg_map.active_peers();
+    }
+
+    /// Provides whether a lock of the manager or its underlying storage was poisoned and recovered 
+    /// since the manager started or clear_poison was last called.
+    /// 
+    /// A panic while a lock is held, such as one raised by a Deserialize implementation during get
+    /// or by a subscriber, would otherwise make every later call panic. The lock is instead 
+    /// recovered, as the data it guards stays consistent, and the poisoning is recorded here so it
+    /// can be logged or alerted on. The datagram being read when the panic happened is lost. Locks
+    /// held by a transport, such as the DTLS sessions, are not covered.
+    pub fn was_poisoned(&self) -> bool 
+    {
+        return self.msg_map.poisoned.load(Ordering::SeqCst) || self.msg_map.msgs.is_poisoned();
+    }
+
+    /// Resets the flag provided by was_poisoned.
+    pub fn clear_poison(&self) 
+    {
+        self.msg_map.msgs.clear_poison();
+        self.msg_map.poisoned.store(false, Ordering::SeqCst);
+    }
+
+    /// Provides the number of datagrams queued under each id, sorted by id.
+    /// 
+    /// Taken under a single lock, so the depths are consistent with each other. Useful for 
+    /// monitoring and for spotting a single type piling up. Ids that once had datagrams may be 
+    /// reported with a depth of 0.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn queue_depths(&self) -> Vec<(u64, usize)> 
+    {
+        return self.msg_map.queue_depths();
+    }
+
+    /// Clears the send failures recorded for a destination, closing its circuit breaker.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn reset_peer(&self, addr: SocketAddr) 
+    {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.reset(addr);
+        }
+    }
+
+    /// Provides a single line describing the manager, for quick logging. Identical to the Debug 
+    /// output: the local addresses, format, use_ids, whether the background threads are still 
+    /// running, and the number of datagrams waiting to be retrieved.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn summary(&self) -> String 
+    {
+        return format!("{:?}", self);
+    }
+
+    /// Provides the header id of a particular struct, either set with set_id or generated from its name.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_id<F>(&self)->u64 
+        where F: 'static
+    {
+        return self.msg_map.get_id::<F>();
+    }
+
+    /// Allows the header id of a particular struct to be specified rather than be automatically generated.
+    /// 
+    /// Generally, the struct ID is automatically created using a hash of the TypeID. This method allows
+    /// the struct id to be set by the user. This should be called before any attempt to send or receive
+    /// a datagram is made. This is commonly used if interacting with a socket that does not use this crate
+    /// and is expecting a specific ID for the type of message you are sending.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the id falls within RESERVED_IDS, which are used by the crate's internal 
+    /// control messages.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn set_id<F>(&self, id: u64)->Result<(), std::io::Error> 
+        where F: 'static
+    {
+        if RESERVED_IDS.contains(&id) {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Id is reserved for internal control messages"));
+        }
+        self.msg_map.set_id(std::any::TypeId::of::<F>(), id);
+        return Ok(());
+    }
+
+    /// Serializes the datagram, appends its header, and sends to requested location.
+    /// 
+    /// Behaves like send_ref, but the id is the header supplied by the Datagram trait rather than 
+    /// one generated from the type.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the header falls within RESERVED_IDS, which are used by the crate's
+    /// internal control messages, when the data could not be serialized, or when the underyling 
+    /// UDP socket failed to send the message.
+    pub fn send_with_header<J, A>(&self, datagram: &J, dest_addr: A)->Result<(),std::io::Error> 
+        where J: Datagram + ser::Serialize + 'static, A: ToSocketAddrs
+    {
+        if RESERVED_IDS.contains(&(J::header() as u64)) {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Header is reserved for internal control messages"));
+        }
+        self.msg_map.declare_id(J::header() as u64);
+        let payload = self.encode_payload(datagram)?;
+
+        let wtr = self.frame(J::header() as u64, &payload)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Provides the oldest datagram sent under the header of the specified type, if one exists.
+    /// 
+    /// Behaves like get, but looks the datagram up by the header supplied by the Datagram trait. 
+    /// The header counts as known for on_unknown once the type was sent or requested.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_with_header<J>(&self)->Result<(SocketAddr, J), std::io::Error> 
+        where J: Datagram + de::DeserializeOwned + 'static
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = J::header() as u64;
+            self.msg_map.declare_id(id);
+        }
+        let (obj, msg) = self.msg_map.get_obj_by_id(id, self.decoder::<J>())?;
+        return Ok((msg.addr, obj));
+    }
+
+    /// Serializes the datagram, appends the id declared by its type, and sends to requested location.
+    /// 
+    /// Behaves like send_ref, but the id is MsgId::ID rather than one generated from the type or 
+    /// set with set_id.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when the id falls within RESERVED_IDS, when the data could not be 
+    /// serialized, or when the underyling UDP socket failed to send the message.
+    pub fn send_typed<J, A>(&self, datagram: &J, dest_addr: A)->Result<(),std::io::Error> 
+        where J: MsgId + ser::Serialize, A: ToSocketAddrs
+    {
+        if RESERVED_IDS.contains(&J::ID) {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Id is reserved for internal control messages"));
+        }
+        self.msg_map.declare_id(J::ID);
+        let payload = self.encode_payload(datagram)?;
+
+        let wtr = self.frame(J::ID, &payload)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Provides the oldest datagram sent under the id declared by the specified type, if one exists.
+    /// 
+    /// Behaves like get, but looks the datagram up by MsgId::ID. The id counts as known for 
+    /// on_unknown once the type was sent or requested.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty or the data could not be deserialized.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_typed<J>(&self)->Result<(SocketAddr, J), std::io::Error> 
+        where J: MsgId + de::DeserializeOwned
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = J::ID;
+            self.msg_map.declare_id(id);
+        }
+        let (obj, msg) = self.msg_map.get_obj_by_id(id, self.decoder::<J>())?;
+        return Ok((msg.addr, obj));
+    }
+
+    /// Allows the header id of a named message to be specified rather than be automatically generated.
+    /// 
+    /// Named ids live alongside the ids of Rust types, allowing schema-driven protocols to route 
+    /// messages whose types are not statically known. They are used with send_named and get_named.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error if the id falls within RESERVED_IDS, which are used by the crate's internal 
+    /// control messages.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn set_id_by_name(&self, name: &str, id: u64)->Result<(), std::io::Error> 
+    {
+        if RESERVED_IDS.contains(&id) {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Id is reserved for internal control messages"));
+        }
+        self.msg_map.set_id_by_name(name, id);
+        return Ok(());
+    }
+
+    /// Provides the header id of a named message.
+    /// 
+    /// If no id was set with set_id_by_name, the id is generated the same way it is for a type of 
+    /// the same name, so a named message interoperates with a struct of that name.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_id_by_name(&self, name: &str)->u64 
+    {
+        return self.msg_map.get_id_by_name(name);
+    }
+
+    /// Sends an already serialized payload under the id of a named message.
     /// 
     /// # Errors
     /// 
-    /// Returns error when the underlying storage does not exist.
+    /// Returns an error when the underyling UDP socket failed to send the message.
     /// 
     /// # Panics
     /// 
     /// This will panic if the lock becomes poisioned.
-    pub fn remove_all<J>(&self) -> Result<(), std::io::Error>
-        where J: de::DeserializeOwned + 'static
+    pub fn send_named<A>(&self, name: &str, payload: Vec<u8>, dest_addr: A)->Result<(),std::io::Error> 
+        where A: ToSocketAddrs
+    {
+        let wtr = self.frame(self.msg_map.get_id_by_name(name), &payload)?;
+
+        return self.transmit(wtr, dest_addr);
+    }
+
+    /// Provides the oldest raw payload of a named message, if one exists.
+    /// 
+    /// The payload is removed from the underlying storage and returned without deserialization.
+    /// If use_ids is set to false, the name is ignored and the oldest datagram is returned.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns error when the underlying storage is empty.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_named(&self, name: &str)->Result<(SocketAddr, Vec<u8>), std::io::Error> 
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id_by_name(name);
+        }
+        return self.msg_map.get_raw(id);
+    }
+}
+
+impl <T>UdpManager<T> 
+    where T: BorrowSerDes
+{
+    /// Hands the oldest datagram of the specified type to a closure without copying borrowed fields.
+    /// 
+    /// The datagram is deserialized into J::Borrowed, which may borrow strings and byte slices 
+    /// straight from the received buffer rather than allocating. The result of the closure is 
+    /// returned. The datagram is removed from the underlying storage regardless of deserialization
+    /// success, like with get.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn with_front<J, R, F>(&self, f: F)->Option<R>
+        where J: Borrowable, F: for<'de> FnOnce(SocketAddr, J::Borrowed<'de>) -> R
+    {
+        let mut id = 1;
+        if self.use_ids {
+            id = self.msg_map.get_id::<J>();
+        }
+        let (addr, payload) = self.msg_map.get_raw(id).ok()?;
+
+        let obj = T::deserial_borrowed::<J::Borrowed<'_>>(&payload).ok()?;
+        return Some(f(addr, obj));
+    }
+}
+
+#[cfg(feature = "json")]
+impl UdpManager<serdes::JSON> 
+{
+    /// Provides the oldest datagram of any type as a parsed JSON tree.
+    /// 
+    /// This allows arbitrary messages to be routed or transformed without defining a struct for
+    /// each of them. The oldest datagram is the one received first by the background thread. It is
+    /// removed from the underlying storage regardless of deserialization success, like with get.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn get_json(&self)->Option<(SocketAddr, serde_json::Value)> 
+    {
+        let (_, addr, payload) = self.msg_map.pop_oldest()?;
+        let value = serde_json::from_slice(&payload).ok()?;
+        return Some((addr, value));
+    }
+}
+
+/// Helper struct for sending a single datagram with per-message options. Created with 
+/// UdpManager::send_builder.
+pub struct SendBuilder<'a, T, J>
+    where T: SerDesType
+{
+    manager: &'a UdpManager<T>,
+    datagram: J,
+    dest_addr: Option<Result<SocketAddr, std::io::Error>>,
+    compressed: bool,
+    id: Option<u64>,
+    ttl: Option<Duration>
+}
+
+impl<'a, T, J> SendBuilder<'a, T, J> 
+    where T: SerDesType, J: ser::Serialize + 'static
+{
+    /// Sets the destination of the datagram. Only the first resolved address is used.
+    /// 
+    /// **Default value:** None (sending fails)
+    /// 
+    pub fn to<A>(mut self, dest_addr: A)->SendBuilder<'a, T, J> 
+        where A: ToSocketAddrs
+    {
+        self.dest_addr = Some(dest_addr.to_socket_addrs().and_then(|mut addrs| addrs.next()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "No addresses to send data to"))));
+        return self;
+    }
+
+    /// Determines if the payload is compressed, like with send_compressed. Requires 
+    /// compression_flag to be enabled with the Builder struct.
+    /// 
+    /// **Default value:** false
+    /// 
+    pub fn compressed(mut self, compressed: bool)->SendBuilder<'a, T, J> 
+    {
+        self.compressed = compressed;
+        return self;
+    }
+
+    /// Sends the datagram under an explicit id rather than the one of its type. The id must not 
+    /// fall within RESERVED_IDS, or sending fails.
+    /// 
+    /// **Default value:** None (the id of the type is used)
+    /// 
+    pub fn id(mut self, id: u64)->SendBuilder<'a, T, J> 
+    {
+        self.id = Some(id);
+        return self;
+    }
+
+    /// Sets how long the datagram may wait in the queue of the sending thread before it is 
+    /// discarded instead of sent. Only has an effect when async_send is enabled.
+    /// 
+    /// **Default value:** None (never discarded)
+    /// 
+    pub fn ttl(mut self, ttl: Duration)->SendBuilder<'a, T, J> 
+    {
+        self.ttl = Some(ttl);
+        return self;
+    }
+
+    /// Serializes and sends the datagram with the chosen options.
+    /// 
+    /// # Errors
+    /// 
+    /// Returns an error when no destination was set or could be resolved, when the id falls within
+    /// RESERVED_IDS, when compression was requested without compression_flag, when the data could not be serialized, or when the 
+    /// underyling UDP socket failed to send the message.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn send(self)->Result<(), std::io::Error> 
+    {
+        let manager = self.manager;
+        let dest_addr = self.dest_addr
+            .unwrap_or_else(|| Err(std::io::Error::new(ErrorKind::InvalidInput, "No destination set")))?;
+
+        let mut payload = manager.encode_payload(&self.datagram)?;
+        if self.compressed {
+            payload = manager.compress(&payload)?;
+        }
+
+        if let Some(id) = self.id {
+            if RESERVED_IDS.contains(&id) {
+                return Err(std::io::Error::new(ErrorKind::InvalidInput, "Id is reserved for internal control messages"));
+            }
+        }
+
+        let id = self.id.unwrap_or_else(|| manager.msg_map.get_id::<J>());
+        let wtr = manager.encode_frame(id, &payload, self.compressed)?;
+
+        let expires = self.ttl.map(|ttl| Instant::now() + ttl);
+        return manager.transmit_until(wtr, dest_addr, expires);
+    }
+}
+
+/// Pending buffers of length-prefixed messages per destination, used by Builder::coalesce.
+#[doc(hidden)]
+struct Coalescer 
+{
+    pending: Mutex<HashMap<SocketAddr, (Vec<u8>, Instant)>>,
+    max_bytes: usize,
+    max_delay: Duration,
+    msg_map: Arc<MsgStorage>
+}
+
+#[doc(hidden)]
+impl Coalescer 
+{
+    fn new(max_bytes: usize, max_delay: Duration, msg_map: Arc<MsgStorage>)->Coalescer 
+    {
+        return Coalescer { pending: Mutex::from(HashMap::new()), max_bytes, max_delay, msg_map };
+    }
+
+    /// Appends a message to the pending buffer of the destination. Provides the previous buffer if
+    /// the message did not fit, which must then be sent.
+    fn push(&self, msg: &[u8], dest_addr: SocketAddr)->Option<Vec<u8>> 
+    {
+        let mut pending = self.msg_map.lock(&self.pending);
+
+        let mut full = None;
+        if let Some((buffer, _)) = pending.get(&dest_addr) {
+            if buffer.len() + 4 + msg.len() > self.max_bytes {
+                full = pending.remove(&dest_addr).map(|(buffer, _)| buffer);
+            }
+        }
+
+        let (buffer, _) = pending.entry(dest_addr).or_insert_with(|| (vec![], Instant::now()));
+        buffer.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(msg);
+        return full;
+    }
+
+    /// Removes the buffers whose oldest message has waited max_delay, or every buffer if all is true
+    fn take_due(&self, all: bool)->Vec<(Vec<u8>, SocketAddr)> 
+    {
+        let mut pending = self.msg_map.lock(&self.pending);
+
+        let due: Vec<SocketAddr> = pending.iter()
+            .filter(|(_, (_, started))| all || started.elapsed() >= self.max_delay)
+            .map(|(dest_addr, _)| *dest_addr)
+            .collect();
+
+        return due.into_iter()
+            .filter_map(|dest_addr| pending.remove(&dest_addr).map(|(buffer, _)| (buffer, dest_addr)))
+            .collect();
+    }
+}
+
+/// Datagrams held back by the network simulation, delivered by a single thread once due.
+#[cfg(feature = "netsim")]
+#[doc(hidden)]
+struct DelayQueue 
+{
+    pending: Mutex<BinaryHeap<Delayed>>,
+    changed: Condvar,
+    msg_map: Arc<MsgStorage>
+}
+
+#[cfg(feature = "netsim")]
+#[doc(hidden)]
+impl DelayQueue 
+{
+    fn new(msg_map: Arc<MsgStorage>)->DelayQueue 
+    {
+        return DelayQueue { pending: Mutex::from(BinaryHeap::new()), changed: Condvar::new(), msg_map };
+    }
+
+    fn push(&self, due: Instant, id: u64, msg: Msg) 
+    {
+        self.msg_map.lock(&self.pending).push(Delayed { due, id, msg });
+        self.changed.notify_one();
+    }
+
+    /// Waits until the earliest datagram is due, at most max_wait, and removes every datagram that is due
+    fn take_due(&self, max_wait: Duration)->Vec<(u64, Msg)> 
+    {
+        let mut pending = self.msg_map.lock(&self.pending);
+
+        let wait = match pending.peek() {
+            Some(next) => next.due.saturating_duration_since(Instant::now()).min(max_wait),
+            None => max_wait
+        };
+        if !wait.is_zero() {
+            pending = self.changed.wait_timeout(pending, wait).unwrap_or_else(|e| self.msg_map.recover(&self.pending, e)).0;
+        }
+
+        let now = Instant::now();
+        let mut due = vec![];
+        while pending.peek().is_some_and(|next| next.due <= now) {
+            if let Some(next) = pending.pop() {
+                due.push((next.id, next.msg));
+            }
+        }
+        return due;
+    }
+}
+
+/// Datagram in a DelayQueue, ordered so the earliest due is at the top of the heap.
+#[cfg(feature = "netsim")]
+#[doc(hidden)]
+struct Delayed 
+{
+    due: Instant,
+    id: u64,
+    msg: Msg
+}
+
+#[cfg(feature = "netsim")]
+impl PartialEq for Delayed 
+{
+    fn eq(&self, other: &Delayed)->bool 
+    {
+        return self.due == other.due;
+    }
+}
+
+#[cfg(feature = "netsim")]
+impl Eq for Delayed {}
+
+#[cfg(feature = "netsim")]
+impl PartialOrd for Delayed 
+{
+    fn partial_cmp(&self, other: &Delayed)->Option<std::cmp::Ordering> 
+    {
+        return Some(self.cmp(other));
+    }
+}
+
+#[cfg(feature = "netsim")]
+impl Ord for Delayed 
+{
+    fn cmp(&self, other: &Delayed)->std::cmp::Ordering 
+    {
+        return other.due.cmp(&self.due);
+    }
+}
+
+/// Retry policy for transient send errors, set by Builder::send_retries.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+struct SendRetries 
+{
+    max: u32,
+    backoff: Duration
+}
+
+#[doc(hidden)]
+impl SendRetries 
+{
+    fn send_to(&self, udp: &dyn Transport, wtr: &[u8], dest_addr: SocketAddr)->Result<usize, std::io::Error> 
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match udp.send_to(wtr, dest_addr) {
+                Err(e) if attempt < self.max && matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                },
+                result => return result
+            }
+        }
+    }
+}
+
+/// Number of received datagrams dropped for each reason, provided by UdpManager::drop_stats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DropStats 
+{
+    /// Shorter than their id or not matching their length prefix
+    pub truncated: u64,
+    /// Failed checksum verification
+    pub checksum: u64,
+    /// Carrying an invalid compression flag, a payload that could not be inflated, or a payload 
+    /// larger than set with set_type_max_size
+    pub malformed: u64,
+    /// Removed to make room in a queue at max_queue_len
+    pub queue_full: u64,
+    /// Older than message_ttl
+    pub expired: u64,
+    /// Failed to deserialize when read with get or get_all, unless kept as dead letters
+    pub deserialize: u64,
+    /// Carrying a new id after max_queues distinct ids were already queued
+    pub too_many_queues: u64,
+    /// Carrying another protocol_version
+    pub version: u64
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+enum DropReason 
+{
+    Truncated,
+    Checksum,
+    Malformed,
+    QueueFull,
+    Expired,
+    Deserialize,
+    TooManyQueues,
+    Version
+}
+
+/// Counters behind DropStats, updated by the background thread and the storage.
+#[doc(hidden)]
+#[derive(Default)]
+struct DropCounters 
+{
+    counts: [AtomicU64; 8]
+}
+
+#[doc(hidden)]
+impl DropCounters 
+{
+    fn record(&self, reason: DropReason, count: u64) 
+    {
+        self.counts[reason as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self)->DropStats 
+    {
+        let count = |reason: DropReason| self.counts[reason as usize].load(Ordering::Relaxed);
+        return DropStats {
+            truncated: count(DropReason::Truncated),
+            checksum: count(DropReason::Checksum),
+            malformed: count(DropReason::Malformed),
+            queue_full: count(DropReason::QueueFull),
+            expired: count(DropReason::Expired),
+            deserialize: count(DropReason::Deserialize),
+            too_many_queues: count(DropReason::TooManyQueues),
+            version: count(DropReason::Version)
+        };
+    }
+
+    fn reset(&self) 
+    {
+        for count in &self.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Consecutive send failures per destination, used by Builder::send_circuit_breaker.
+#[doc(hidden)]
+struct CircuitBreaker 
+{
+    failures: Mutex<HashMap<SocketAddr, (u32, Option<Instant>)>>,
+    threshold: u32,
+    cooldown: Duration,
+    clock: Arc<dyn Clock>,
+    msg_map: Arc<MsgStorage>
+}
+
+#[doc(hidden)]
+impl CircuitBreaker 
+{
+    fn new(threshold: u32, cooldown: Duration, clock: Arc<dyn Clock>, msg_map: Arc<MsgStorage>)->CircuitBreaker 
+    {
+        return CircuitBreaker { failures: Mutex::from(HashMap::new()), threshold, cooldown, clock, msg_map };
+    }
+
+    fn is_open(&self, addr: SocketAddr)->bool 
+    {
+        let failures = self.msg_map.lock(&self.failures);
+        return failures.get(&addr)
+            .and_then(|(_, opened)| *opened)
+            .is_some_and(|opened| self.clock.now().saturating_duration_since(opened) < self.cooldown);
+    }
+
+    fn record(&self, addr: SocketAddr, success: bool) 
+    {
+        let mut failures = self.msg_map.lock(&self.failures);
+        if success {
+            failures.remove(&addr);
+            return;
+        }
+
+        let (count, opened) = failures.entry(addr).or_insert((0, None));
+        *count += 1;
+        if *count >= self.threshold {
+            *opened = Some(self.clock.now());
+        }
+    }
+
+    fn reset(&self, addr: SocketAddr) 
+    {
+        self.msg_map.lock(&self.failures).remove(&addr);
+    }
+}
+
+/// Datagram waiting for the sending thread, with the instant after which it is discarded
+#[doc(hidden)]
+type Outgoing = (Vec<u8>, SocketAddr, Option<Instant>);
+
+/// Attempts to decode a raw payload, used by UdpManager::get_first_of.
+pub type Decoder<'a, R> = &'a dyn Fn(&[u8]) -> Option<R>;
+
+/// Provides the oldest untyped datagram as the first of the listed types it deserializes into.
+/// 
+/// Each type is paired with a function (typically an enum variant) that wraps the decoded value.
+/// Expands to a call to UdpManager::get_first_of.
+/// 
+/// ```rust,ignore
+/// match get_first_of!(net_msg, UpdatePos => Msg::Pos, RenameObj => Msg::Rename) {
+///     Ok((addr, Msg::Pos(pos))) => {},
+///     Ok((addr, Msg::Rename(name))) => {},
+///     Err(_) => {}
+/// }
+/// ```
+#[macro_export]
+macro_rules! get_first_of {
+    ($mgr:expr, $($ty:ty => $wrap:expr),+ $(,)?) => {{
+        let mgr = &$mgr;
+        mgr.get_first_of(&[$(&|payload: &[u8]| mgr.decode_payload::<$ty>(payload).ok().map($wrap)),+])
+    }};
+}
+
+#[doc(hidden)]
+type RecvErrorFn = dyn Fn(&std::io::Error) + Send + Sync;
+
+#[doc(hidden)]
+type RawFn = dyn Fn(u64, SocketAddr, &[u8]) -> bool + Send + Sync;
+
+#[doc(hidden)]
+type UnknownFn = dyn Fn(u64, SocketAddr, Vec<u8>) + Send + Sync;
+
+#[doc(hidden)]
+type VersionMismatchFn = dyn Fn(SocketAddr, Vec<u8>) + Send + Sync;
+
+/// Layout of every datagram on the wire: 
+/// `[version][id][length prefix][compression flag][envelope][payload][checksum]`, where each part 
+/// but the payload is optional.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+struct Framing 
+{
+    version: Option<u8>,
+    use_ids: bool,
+    compression_flag: bool,
+    checksum: bool,
+    length_prefix: LengthPrefix,
+    envelope: EnvelopeSpec
+}
+
+#[doc(hidden)]
+impl Framing 
+{
+    /// Number of bytes every datagram carries besides its payload
+    fn header_len(&self)->usize 
+    {
+        let prefix_len = match self.length_prefix {
+            LengthPrefix::None => 0,
+            LengthPrefix::U16 => 2,
+            LengthPrefix::U32 => 4
+        };
+        return self.version.is_some() as usize + 8 * self.use_ids as usize + prefix_len + self.compression_flag as usize + self.envelope.len() + 4 * self.checksum as usize;
+    }
+
+    /// Builds a datagram from an id, envelope and serialized payload, marking whether the payload 
+    /// is compressed.
+    fn encode(&self, id: u64, meta: EnvelopeMeta, payload: &[u8], compressed: bool, capacity_hint: usize)->Result<Vec<u8>, std::io::Error> 
+    {
+        let mut wtr: Vec<u8> = Vec::with_capacity(22 + payload.len().max(capacity_hint));
+        self.encode_into(id, meta, compressed, &mut wtr, |wtr| {
+            wtr.extend_from_slice(payload);
+            return Ok(());
+        })?;
+
+        return Ok(wtr);
+    }
+
+    /// Clears the buffer and builds the datagram in it, with the payload written by the closure. 
+    /// The length prefix is filled in once the payload is written.
+    fn encode_into<W>(&self, id: u64, meta: EnvelopeMeta, compressed: bool, wtr: &mut Vec<u8>, write_payload: W)->Result<(), std::io::Error> 
+        where W: FnOnce(&mut Vec<u8>) -> Result<(), std::io::Error>
+    {
+        wtr.clear();
+        if let Some(version) = self.version {
+            wtr.push(version);
+        }
+        if self.use_ids {
+            wtr.write_u64::<BigEndian>(id)?;
+        }
+
+        let prefix_at = wtr.len();
+        match self.length_prefix {
+            LengthPrefix::None => {},
+            LengthPrefix::U16 => wtr.write_u16::<BigEndian>(0)?,
+            LengthPrefix::U32 => wtr.write_u32::<BigEndian>(0)?
+        }
+        let len_from = wtr.len();
+
+        if self.compression_flag {
+            wtr.push(compressed as u8);
+        }
+        self.envelope.write(meta, wtr);
+        write_payload(wtr)?;
+
+        let len = wtr.len() - len_from;
+        match self.length_prefix {
+            LengthPrefix::None => {},
+            LengthPrefix::U16 => {
+                let len = u16::try_from(len)
+                    .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Payload is too long for a U16 length prefix"))?;
+                BigEndian::write_u16(&mut wtr[prefix_at..len_from], len);
+            },
+            LengthPrefix::U32 => {
+                let len = u32::try_from(len)
+                    .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Payload is too long for a U32 length prefix"))?;
+                BigEndian::write_u32(&mut wtr[prefix_at..len_from], len);
+            }
+        }
+
+        if self.checksum {
+            let crc = crc32fast::hash(wtr);
+            wtr.write_u32::<BigEndian>(crc)?;
+        }
+        return Ok(());
+    }
+
+    /// Verifies the checksum, strips the id, length prefix, compression flag and envelope, and 
+    /// inflates the payload if it is compressed. Provides the id (1 when use_ids is false) and the payload.
+    fn decode(&self, buffer: Vec<u8>)->Result<(u64, Vec<u8>), std::io::Error> 
     {
-        return self.msg_map.remove_all::<T, J>(self.use_ids);
+        return self.decode_frame(buffer).map(|(id, _, payload)| (id, payload)).map_err(|(_, e)| e);
     }
 
-    /// Deserializes the datagram, appends the ID, and sends to requested location.
+    /// Whether the datagram ends with a valid checksum, or checksums are disabled
+    fn checksum_valid(&self, buffer: &[u8])->bool 
+    {
+        if !self.checksum {
+            return true;
+        }
+        return buffer.len() >= 4 && {
+            let (frame, crc) = buffer.split_at(buffer.len() - 4);
+            crc32fast::hash(frame) == BigEndian::read_u32(crc)
+        };
+    }
+
+    /// Behaves like decode, but also provides the envelope and the reason a malformed datagram is dropped.
+    fn decode_frame(&self, mut buffer: Vec<u8>)->Result<(u64, EnvelopeMeta, Vec<u8>), (DropReason, std::io::Error)> 
+    {
+        if self.checksum {
+            if !self.checksum_valid(&buffer) {
+                return Err((DropReason::Checksum, std::io::Error::new(ErrorKind::InvalidData, "invalid checksum")));
+            }
+            buffer.truncate(buffer.len() - 4);
+        }
+
+        if let Some(version) = self.version {
+            match buffer.first() {
+                Some(found) if *found == version => { buffer.remove(0); },
+                Some(found) => return Err((DropReason::Version, std::io::Error::new(ErrorKind::InvalidData, 
+                    format!("protocol version {} does not match {}", found, version)))),
+                None => return Err((DropReason::Truncated, std::io::Error::new(ErrorKind::InvalidData, "missing the protocol version")))
+            }
+        }
+
+        let mut id = 1;
+        if self.use_ids {
+            //Datagrams too short to carry an id cannot be sorted
+            if buffer.len() < 8 {
+                return Err((DropReason::Truncated, std::io::Error::new(ErrorKind::InvalidData, 
+                    format!("{} bytes is too short to carry an id", buffer.len()))));
+            }
+            let header: Vec<_> = buffer.drain(..8).collect();
+            id = BigEndian::read_u64(&header);
+        }
+
+        let (prefix_len, declared) = match self.length_prefix {
+            LengthPrefix::None => (0, None),
+            LengthPrefix::U16 => (2, buffer.get(..2).map(|len| BigEndian::read_u16(len) as usize)),
+            LengthPrefix::U32 => (4, buffer.get(..4).map(|len| BigEndian::read_u32(len) as usize))
+        };
+        if prefix_len > 0 {
+            if declared != Some(buffer.len().saturating_sub(prefix_len)) {
+                return Err((DropReason::Truncated, std::io::Error::new(ErrorKind::InvalidData, "length does not match the length prefix")));
+            }
+            buffer.drain(..prefix_len);
+        }
+
+        let mut compressed = false;
+        if self.compression_flag {
+            compressed = match buffer.first() {
+                Some(0) => false,
+                Some(1) => true,
+                Some(flag) => return Err((DropReason::Malformed, std::io::Error::new(ErrorKind::InvalidData, format!("unknown compression flag {}", flag)))),
+                None => return Err((DropReason::Malformed, std::io::Error::new(ErrorKind::InvalidData, "missing the compression flag")))
+            };
+            buffer.remove(0);
+        }
+
+        let meta = self.envelope.read(&mut buffer)
+            .ok_or_else(|| (DropReason::Truncated, std::io::Error::new(ErrorKind::InvalidData, "too short to carry the envelope")))?;
+
+        if !compressed {
+            return Ok((id, meta, buffer));
+        }
+        let mut inflated = vec![];
+        DeflateDecoder::new(&buffer[..]).read_to_end(&mut inflated).map_err(|e| (DropReason::Malformed, e))?;
+        return Ok((id, meta, inflated));
+    }
+}
+
+/// Writes an address as its family (4 or 6), IP and big endian port.
+fn write_addr(wtr: &mut Vec<u8>, addr: SocketAddr) 
+{
+    match addr.ip() {
+        std::net::IpAddr::V4(ip) => {
+            wtr.push(4);
+            wtr.extend_from_slice(&ip.octets());
+        },
+        std::net::IpAddr::V6(ip) => {
+            wtr.push(6);
+            wtr.extend_from_slice(&ip.octets());
+        }
+    }
+    wtr.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+/// Splits a datagram sent with send_with_origin into the id, origin and payload of the message it
+/// wraps. Returns None if it is malformed.
+fn unwrap_origin(mut buffer: Vec<u8>) -> Option<(u64, SocketAddr, Vec<u8>)> 
+{
+    let id = BigEndian::read_u64(buffer.get(..8)?);
+    let (ip, len): (std::net::IpAddr, usize) = match buffer.get(8)? {
+        4 => (<[u8; 4]>::try_from(buffer.get(9..13)?).ok()?.into(), 13),
+        6 => (<[u8; 16]>::try_from(buffer.get(9..25)?).ok()?.into(), 25),
+        _ => return None
+    };
+    let port = BigEndian::read_u16(buffer.get(len..len + 2)?);
+    buffer.drain(..len + 2);
+    return Some((id, SocketAddr::new(ip, port), buffer));
+}
+
+/// Provides the name of a type without its module path, from which ids are generated.
+fn short_type_name<T>() -> &'static str 
+    where T: ?Sized
+{
+    return std::any::type_name::<T>().rsplit("::").next().unwrap_or_default();
+}
+
+/// Serializes a datagram as a JSON object with its type name added under the field.
+#[cfg(feature = "json")]
+fn tag_body<J>(field: &str, name: &str, datagram: &J) -> Result<Vec<u8>, std::io::Error> 
+    where J: ser::Serialize + ?Sized
+{
+    let mut value = serde_json::to_value(datagram)
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "Could not serialize"))?;
+    match value.as_object_mut() {
+        Some(object) => { object.insert(String::from(field), serde_json::Value::from(name)); },
+        None => return Err(std::io::Error::new(ErrorKind::InvalidInput, "A type tag requires a datagram that serializes to a JSON object"))
+    }
+    return serde_json::to_vec(&value).map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "Could not serialize"));
+}
+
+/// Reads the type name a datagram carries under the field of its JSON body.
+#[cfg(feature = "json")]
+fn read_tag(field: &str, payload: &[u8]) -> Option<String> 
+{
+    let value: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    return value.get(field)?.as_str().map(String::from);
+}
+
+/// Number of times a background thread found its socket empty, used by UdpManager::sync.
+#[doc(hidden)]
+#[derive(Default)]
+struct IdlePasses 
+{
+    count: Mutex<u64>,
+    passed: Condvar
+}
+
+/// State shared with the background thread that receives datagrams.
+#[doc(hidden)]
+struct Listener 
+{
+    udp: Arc<dyn Transport>,
+    stop: ThreadSafe<bool>,
+    idle: Arc<IdlePasses>,
+    msg_map: Arc<MsgStorage>,
+    last_error: ThreadSafe<Option<(ErrorKind, String)>>,
+    on_recv_error: Option<Arc<RecvErrorFn>>,
+    on_raw: Option<Arc<RawFn>>,
+    on_unknown: Option<Arc<UnknownFn>>,
+    on_version_mismatch: Option<Arc<VersionMismatchFn>>,
+    /// batch_recv buffers of buffer_len bytes, reused by every read
+    buffers: Mutex<Vec<Vec<u8>>>,
+    batch_recv: usize,
+    recv_ttl: bool,
+    idle_sleep: Duration,
+    auto_ack: bool,
+    coalesce: bool,
+    framing: Framing,
+    #[cfg(feature = "json")]
+    tag_in_body: Option<Arc<str>>,
+    #[cfg(feature = "netsim")]
+    simulate: Option<SimConfig>,
+    #[cfg(feature = "netsim")]
+    delayed: Arc<DelayQueue>
+}
+
+#[doc(hidden)]
+impl Listener {
+
+    /// Attempts to receive a datagram from the underyling socket. 
     /// 
-    /// Consumes a datagram and a destination address for the datagram to be sent to.
-    /// An attempt to serialize the data is made; If use_id is true, the datagram ID is prepended 
-    /// to the message. A request to the underlying UDP socket is then made to send the data.
+    /// Attempts to receive a datagram from the underlying socket and remove it from the queue.
+    /// If no datagram is available, it will either return, or sit and wait depending on if the 
+    /// the value of non_blocking, set with the Builder struct.
     /// 
     /// # Errors
     /// 
-    /// Returns an error when the data could not be serialized or when the underyling 
-    /// UDP socket failed to send the message.
+    /// Errors when the there is an issue receiving data from the underyling socket. 
+    /// Does not return an error, reports it with report_error instead.
     /// 
     /// # Panics
     /// 
     /// This will panic if the lock becomes poisioned.
-    pub fn send<J, A>(&self, datagram: J, dest_addr: A)->Result<(),std::io::Error> 
-        where J: ser::Serialize + 'static, A: ToSocketAddrs
+    fn try_recv(&self) 
     {
+        if let Err(e) = self.recv_once() {
+            //WouldBlock is the Unix response and TimedOut the Windows response when non_blocking is true
+            if e.kind() != ErrorKind::WouldBlock && e.kind() != ErrorKind::TimedOut {
+                self.report_error(e);
+                return;
+            }
+            *self.msg_map.lock(&self.idle.count) += 1;
+            self.idle.passed.notify_all();
+            if e.kind() == ErrorKind::WouldBlock && !self.idle_sleep.is_zero() {
+                thread::sleep(self.idle_sleep);
+            }
+        }
+    }
 
-        let mut wtr: Vec<u8> = vec![];
-        let mut payload = match T::serial(&datagram) {
-            Ok(obj) => obj,
-            Err(_) => return Err(std::io::Error::new(ErrorKind::InvalidData, "Could not serialize"))
+    /// Receives up to batch_recv datagrams and delivers the messages they carry. Returns whether 
+    /// any message was delivered, or the error of the underlying socket.
+    fn recv_once(&self) -> Result<bool, std::io::Error> 
+    {
+        let mut buffers = self.msg_map.lock(&self.buffers);
+
+        if self.batch_recv == 1 {
+            let (num_bytes, addr, ttl) = match self.recv_ttl {
+                true => self.udp.recv_from_ttl(&mut buffers[0])?,
+                false => {
+                    let (num_bytes, addr) = self.udp.recv_from(&mut buffers[0])?;
+                    (num_bytes, addr, None)
+                }
+            };
+            //The datagram may only be the one sent by stop to wake this thread
+            if *self.msg_map.lock(self.stop.mutex()) {
+                return Ok(false);
+            }
+            let buffer = buffers[0][..num_bytes].to_vec();
+            drop(buffers);
+            return Ok(self.unpack(buffer, addr, ttl, self.msg_map.clock.now()));
+        }
+
+        let datagrams = self.udp.recv_batch(&mut buffers)?;
+        if *self.msg_map.lock(self.stop.mutex()) {
+            return Ok(false);
+        }
+
+        let received: Vec<(Vec<u8>, SocketAddr)> = buffers.iter()
+            .zip(datagrams)
+            .map(|(buffer, (num_bytes, addr))| (buffer[..num_bytes].to_vec(), addr))
+            .collect();
+        drop(buffers);
+
+        let now = self.msg_map.clock.now();
+        let mut delivered = false;
+        for (buffer, addr) in received {
+            delivered |= self.unpack(buffer, addr, None, now);
+        }
+        return Ok(delivered);
+    }
+
+    /// Splits a received datagram into the messages it carries and processes each. Returns 
+    /// whether any message was delivered.
+    fn unpack(&self, buffer: Vec<u8>, addr: SocketAddr, ttl: Option<u8>, received: Instant) -> bool
+    {
+        if !self.coalesce {
+            return self.process(buffer, addr, ttl, received);
+        }
+
+        //Coalesced datagrams carry several messages, each prefixed with its length
+        let mut delivered = false;
+        let mut rest = &buffer[..];
+        while !rest.is_empty() {
+            let len = match rest.get(..4) {
+                Some(len) => BigEndian::read_u32(len) as usize,
+                None => 0
+            };
+            if len == 0 || rest.len() - 4 < len {
+                self.msg_map.drops.record(DropReason::Truncated, 1);
+                self.report_error(std::io::Error::new(ErrorKind::InvalidData, 
+                    format!("Dropped the rest of a coalesced datagram from {} with an invalid length", addr)));
+                return delivered;
+            }
+            delivered |= self.process(rest[4..4 + len].to_vec(), addr, ttl, received);
+            rest = &rest[4 + len..];
+        }
+        return delivered;
+    }
+
+    /// Verifies the checksum and strips the id and compression flag from a single message, then 
+    /// delivers it. Returns whether it was delivered.
+    fn process(&self, buffer: Vec<u8>, addr: SocketAddr, ttl: Option<u8>, received: Instant) -> bool
+    {
+        //A corrupted datagram falls through to decode_frame, which drops it for its checksum
+        if let (Some(on_version_mismatch), Some(version)) = (&self.on_version_mismatch, self.framing.version) {
+            if self.framing.checksum_valid(&buffer) && buffer.first().is_some_and(|found| *found != version) {
+                self.msg_map.drops.record(DropReason::Version, 1);
+                on_version_mismatch(addr, buffer);
+                return false;
+            }
+        }
+
+        let (id, meta, buffer) = match self.framing.decode_frame(buffer) {
+            Ok(frame) => frame,
+            Err((reason, e)) => {
+                self.msg_map.drops.record(reason, 1);
+                self.report_error(std::io::Error::new(e.kind(), format!("Dropped datagram from {}: {}", addr, e)));
+                return false;
+            }
         };
 
-        if self.use_ids {
-            let id = self.msg_map.get_id::<J>();
-            wtr.write_u64::<BigEndian>(id)?;
+        #[cfg(feature = "json")]
+        let id = match &self.tag_in_body {
+            Some(field) => match read_tag(field, &buffer) {
+                Some(name) => self.msg_map.lookup_id_by_name(&name),
+                None => {
+                    self.drop_malformed(addr, "missing type tag");
+                    return false;
+                }
+            },
+            None => id
+        };
+
+        if let Some(on_raw) = &self.on_raw {
+            if on_raw(id, addr, &buffer) {
+                return false;
+            }
+        }
+
+        let mut origin = None;
+        let (id, buffer) = match id {
+            RELIABLE_ID if self.framing.use_ids => match self.unwrap_reliable(addr, buffer) {
+                Some(msg) => msg,
+                None => return false
+            },
+            ORIGIN_ID if self.framing.use_ids => match unwrap_origin(buffer) {
+                Some((id, from, buffer)) => {
+                    origin = Some(from);
+                    (id, buffer)
+                },
+                None => {
+                    self.drop_malformed(addr, "invalid origin");
+                    return false;
+                }
+            },
+            ACK_ID if self.framing.use_ids => {
+                match buffer.get(..8) {
+                    Some(seq) => self.msg_map.ack(addr, BigEndian::read_u64(seq)),
+                    None => self.drop_malformed(addr, "acknowledgement is too short")
+                }
+                return false;
+            },
+            _ => (id, buffer)
+        };
+
+        //Tagged datagrams carry no id on the wire, but are still routed by the id of their tag
+        #[cfg(feature = "json")]
+        let routed = self.framing.use_ids || self.tag_in_body.is_some();
+        #[cfg(not(feature = "json"))]
+        let routed = self.framing.use_ids;
+
+        if let Some(max_size) = self.msg_map.max_size(id).filter(|max_size| buffer.len() > *max_size) {
+            self.drop_malformed(addr, &format!("payload of {} bytes exceeds the limit of {} bytes for id {}", buffer.len(), max_size, id));
+            return false;
+        }
+
+        if let Some(on_unknown) = &self.on_unknown {
+            if routed && !self.msg_map.is_known(id) {
+                on_unknown(id, addr, buffer);
+                return false;
+            }
         }
-        wtr.append(&mut payload);
+        return self.deliver(id, Msg { addr, payload: buffer, seq: 0, received, meta, origin, ttl });
+    }
 
-        self.udp.send_to(&wtr, dest_addr)?;
+    /// Acknowledges a datagram sent with send_reliable if auto_ack is enabled, and provides the 
+    /// id and payload of the message it wraps.
+    fn unwrap_reliable(&self, addr: SocketAddr, mut buffer: Vec<u8>) -> Option<(u64, Vec<u8>)>
+    {
+        if buffer.len() < 16 {
+            self.drop_malformed(addr, "reliable datagram is too short");
+            return None;
+        }
 
-        return Ok(());
+        if self.auto_ack {
+            let ack = self.framing.encode(ACK_ID, EnvelopeMeta::default(), &buffer[..8], false, 0)
+                .and_then(|ack| self.udp.send_to(&ack, addr));
+            if let Err(e) = ack {
+                self.report_error(e);
+            }
+        }
+
+        let id = BigEndian::read_u64(&buffer[8..16]);
+        buffer.drain(..16);
+        return Some((id, buffer));
     }
 
-    /// Allows the header id of a particular struct to be specified rather than be automatically generated.
+    fn drop_malformed(&self, addr: SocketAddr, reason: &str) 
+    {
+        self.msg_map.drops.record(DropReason::Malformed, 1);
+        self.report_error(std::io::Error::new(ErrorKind::InvalidData, format!("Dropped datagram from {}: {}", addr, reason)));
+    }
+
+    /// Hands a received datagram to the underlying storage, passing it through the network 
+    /// simulation first when one is configured. Returns false if the simulation lost it.
+    fn deliver(&self, id: u64, msg: Msg) -> bool
+    {
+        #[cfg(feature = "netsim")]
+        if let Some(simulate) = &self.simulate {
+            if rand::random::<f32>() < simulate.loss {
+                return false;
+            }
+            let delay = simulate.sample_delay();
+            if !delay.is_zero() {
+                self.delayed.push(Instant::now() + delay, id, msg);
+                return true;
+            }
+        }
+
+        self.msg_map.add_msg(id, msg);
+        return true;
+    }
+
+    /// Records an error from the underlying socket and hands it to the on_recv_error callback.
     /// 
-    /// Generally, the struct ID is automatically created using a hash of the TypeID. This method allows
-    /// the struct id to be set by the user. This should be called before any attempt to send or receive
-    /// a datagram is made. This is commonly used if interacting with a socket that does not use this crate
-    /// and is expecting a specific ID for the type of message you are sending.
+    /// Without a callback, the error is printed to the command line instead of crashing for one 
+    /// failed read. Unreachable peers are expected on connected sockets (a previous send triggered an
+    /// ICMP error), so those are never printed.
+    fn report_error(&self, e: std::io::Error) 
+    {
+        *self.msg_map.lock(self.last_error.mutex()) = Some((e.kind(), e.to_string()));
+
+        let unreachable = matches!(e.kind(), 
+            ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset | ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable);
+
+        match &self.on_recv_error {
+            Some(on_recv_error) => on_recv_error(&e),
+            None if unreachable => {},
+            None => println!("{}",e)
+        }
+    }
+}
+
+/// Outcome of handing a datagram to a subscription.
+#[doc(hidden)]
+enum Delivery 
+{
+    Delivered,
+    Undecodable,
+    Closed
+}
+
+/// Datagram that failed to deserialize, kept when capture_dead_letters is enabled
+#[doc(hidden)]
+type DeadLetter = (SocketAddr, Vec<u8>);
+
+#[doc(hidden)]
+type Subscriber = Box<dyn FnMut(SocketAddr, &[u8]) -> Delivery + Send>;
+
+/// Maps datagram types to the variants of a single message enum, for use with UdpManager::get_enum.
+/// 
+/// ```rust,ignore
+/// let mut registry = MessageRegistry::new();
+/// registry.register::<UpdatePos, _>(Msg::Pos);
+/// registry.register::<RenameObj, _>(Msg::Rename);
+/// registry.register_with_addr(|addr, ack: Ack| Msg::Ack(addr, ack));
+/// 
+/// while let Some((addr, msg)) = net_msg.get_enum(&registry) {}
+/// ```
+pub struct MessageRegistry<E> 
+{
+    entries: Vec<RegistryEntry<E>>
+}
+
+#[doc(hidden)]
+struct RegistryEntry<E> 
+{
+    id_of: fn(&MsgStorage) -> u64,
+    decode: Box<ErasedDecoder<E>>
+}
+
+type ErasedDecoder<E> = dyn Fn(SocketAddr, &mut dyn erased_serde::Deserializer<'_>) -> Result<E, erased_serde::Error>;
+
+impl<E> MessageRegistry<E> 
+{
+    /// Creates an empty registry
+    pub fn new()->MessageRegistry<E> 
+    {
+        return MessageRegistry { entries: vec![] };
+    }
+
+    /// Registers a datagram type along with the function converting it into the message enum.
     /// 
-    /// # Panics
+    /// The id of the type is resolved by the manager when get_enum is called, so ids set with
+    /// set_id are respected.
+    pub fn register<J, F>(&mut self, f: F)->&mut MessageRegistry<E> 
+        where J: de::DeserializeOwned + 'static, F: Fn(J) -> E + 'static
+    {
+        return self.register_with_addr(move |_, obj: J| f(obj));
+    }
+
+    /// Registers a datagram type along with a function converting it and the address of its 
+    /// sender into the message enum.
     /// 
-    /// This will panic if the lock becomes poisioned.
-    pub fn set_id<F>(&self, id: u64) 
-        where F: 'static
+    /// When several functions are registered for a type, the first one registered is used.
+    pub fn register_with_addr<J, F>(&mut self, f: F)->&mut MessageRegistry<E> 
+        where J: de::DeserializeOwned + 'static, F: Fn(SocketAddr, J) -> E + 'static
+    {
+        self.entries.push(RegistryEntry {
+            id_of: MsgStorage::get_id::<J>,
+            decode: Box::new(move |addr, de| {
+                return erased_serde::deserialize::<J>(de).map(|obj| f(addr, obj));
+            })
+        });
+        return self;
+    }
+}
+
+impl<E> Default for MessageRegistry<E> 
+{
+    fn default()->MessageRegistry<E> 
+    {
+        return MessageRegistry::new();
+    }
+}
+
+#[doc(hidden)]
+struct Msg 
+{
+    addr: SocketAddr,
+    payload: Vec<u8>,
+    seq: u64,
+    received: Instant,
+    meta: EnvelopeMeta,
+    origin: Option<SocketAddr>,
+    ttl: Option<u8>
+}
+
+/// Datagrams queued under one id. With per_sender_queues the datagrams of each sender are kept in
+/// a queue of their own, and the oldest datagram overall is found among the fronts of those queues
+/// by its sequence number.
+#[doc(hidden)]
+enum Queue 
+{
+    Shared(VecDeque<Msg>),
+    PerSender(HashMap<SocketAddr, VecDeque<Msg>>)
+}
+
+impl Queue {
+
+    fn new(per_sender: bool, capacity: usize) -> Queue 
+    {
+        return match per_sender {
+            true => Queue::PerSender(HashMap::new()),
+            false => Queue::Shared(VecDeque::with_capacity(capacity))
+        };
+    }
+
+    fn len(&self) -> usize 
+    {
+        return match self {
+            Queue::Shared(vec) => vec.len(),
+            Queue::PerSender(senders) => senders.values().map(VecDeque::len).sum()
+        };
+    }
+
+    fn is_empty(&self) -> bool 
+    {
+        return self.front().is_none();
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Msg> + '_> 
+    {
+        return match self {
+            Queue::Shared(vec) => Box::new(vec.iter()),
+            Queue::PerSender(senders) => Box::new(senders.values().flatten())
+        };
+    }
+
+    /// Provides the oldest datagram
+    fn front(&self) -> Option<&Msg> 
+    {
+        return match self {
+            Queue::Shared(vec) => vec.front(),
+            Queue::PerSender(senders) => senders.values().filter_map(VecDeque::front).min_by_key(|msg| msg.seq)
+        };
+    }
+
+    /// Removes and provides the oldest datagram
+    fn pop_front(&mut self) -> Option<Msg> 
+    {
+        let addr = match self {
+            Queue::Shared(vec) => return vec.pop_front(),
+            Queue::PerSender(_) => self.front()?.addr
+        };
+        return self.pop_from(addr);
+    }
+
+    fn push_front(&mut self, msg: Msg) 
+    {
+        match self {
+            Queue::Shared(vec) => vec.push_front(msg),
+            Queue::PerSender(senders) => senders.entry(msg.addr).or_default().push_front(msg)
+        }
+    }
+
+    fn push_back(&mut self, msg: Msg) 
+    {
+        match self {
+            Queue::Shared(vec) => vec.push_back(msg),
+            Queue::PerSender(senders) => senders.entry(msg.addr).or_default().push_back(msg)
+        }
+    }
+
+    /// Provides the oldest datagram sent from the address
+    fn front_from(&self, addr: SocketAddr) -> Option<&Msg> 
+    {
+        return match self {
+            Queue::Shared(vec) => vec.iter().find(|msg| msg.addr == addr),
+            Queue::PerSender(senders) => senders.get(&addr)?.front()
+        };
+    }
+
+    /// Removes and provides the oldest datagram sent from the address. Senders are forgotten once
+    /// their queue is empty, so addresses that stop sending do not keep a queue allocated.
+    fn pop_from(&mut self, addr: SocketAddr) -> Option<Msg> 
+    {
+        match self {
+            Queue::Shared(vec) => {
+                let pos = vec.iter().position(|msg| msg.addr == addr)?;
+                return vec.remove(pos);
+            },
+            Queue::PerSender(senders) => {
+                let vec = senders.get_mut(&addr)?;
+                let msg = vec.pop_front();
+                if vec.is_empty() {
+                    senders.remove(&addr);
+                }
+                return msg;
+            }
+        }
+    }
+
+    /// Removes and provides up to count of the oldest datagrams, oldest first
+    fn take_oldest(&mut self, count: usize) -> Vec<Msg> 
     {
-        self.msg_map.set_id(std::any::TypeId::of::<F>(), id);
+        if let Queue::Shared(vec) = self {
+            let count = count.min(vec.len());
+            return vec.drain(..count).collect();
+        }
+        return std::iter::from_fn(|| self.pop_front()).take(count).collect();
+    }
+
+    fn clear(&mut self) 
+    {
+        match self {
+            Queue::Shared(vec) => vec.clear(),
+            Queue::PerSender(senders) => senders.clear()
+        }
+    }
+
+    /// Removes the datagrams at the front of each sender's queue for as long as they are expired,
+    /// and provides how many were removed
+    fn expire_where<F>(&mut self, expired: F) -> u64 
+        where F: Fn(&Msg) -> bool
+    {
+        let mut removed = 0;
+        let mut expire = |vec: &mut VecDeque<Msg>| {
+            while vec.front().is_some_and(&expired) {
+                vec.pop_front();
+                removed += 1;
+            }
+        };
+        match self {
+            Queue::Shared(vec) => expire(vec),
+            Queue::PerSender(senders) => senders.retain(|_, vec| { expire(vec); !vec.is_empty() })
+        }
+        return removed;
     }
 }
 
 #[doc(hidden)]
 struct MsgStorage 
 {
-    msgs: Mutex<HashMap<u64, VecDeque<(SocketAddr, Vec<u8>)>>>,
-    ids: Mutex<HashMap<TypeId, u64>>
+    msgs: Mutex<HashMap<u64, Queue>>,
+    arrived: Condvar,
+    drained: Condvar,
+    drops: DropCounters,
+    dead_letters: Option<Mutex<Vec<DeadLetter>>>,
+    ids: Mutex<HashMap<TypeId, u64>>,
+    declared: Mutex<HashSet<u64>>,
+    names: Mutex<HashMap<String, u64>>,
+    priorities: Mutex<HashMap<u64, u8>>,
+    max_sizes: Mutex<HashMap<u64, usize>>,
+    subscribers: Mutex<HashMap<u64, Subscriber>>,
+    acks: Mutex<VecDeque<(SocketAddr, u64)>>,
+    acked: Condvar,
+    next_reliable_seq: AtomicU64,
+    next_seq: AtomicU64,
+    poisoned: AtomicBool,
+    preserve_global_order: bool,
+    retain_on_error: bool,
+    queue_capacity: usize,
+    max_queue_len: Option<usize>,
+    max_queues: Option<usize>,
+    per_sender_queues: bool,
+    message_ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    hash_seed: Option<(u64, u64)>
 }
 
 #[doc(hidden)]
 impl MsgStorage {
+
+    /// Locks the queues
+    fn lock_msgs(&self) -> MutexGuard<'_, HashMap<u64, Queue>> 
+    {
+        return self.lock(&self.msgs);
+    }
+
+    /// Locks a mutex of the storage or the manager. A panic while one was locked, such as in a 
+    /// Deserialize implementation or a subscriber, leaves the data consistent, so the lock is 
+    /// recovered and the poisoning recorded.
+    fn lock<'a, G>(&self, mutex: &'a Mutex<G>) -> MutexGuard<'a, G> 
+    {
+        return mutex.lock().unwrap_or_else(|e| self.recover(mutex, e));
+    }
+
+    fn recover<T, G>(&self, mutex: &Mutex<T>, e: PoisonError<G>) -> G 
+    {
+        self.poisoned.store(true, Ordering::SeqCst);
+        mutex.clear_poison();
+        return e.into_inner();
+    }
     
-    fn get_obj<T, J>(&self, use_ids: bool)->Result<(SocketAddr, J), std::io::Error> 
-        where T: SerDesType, J: de::DeserializeOwned + 'static
+    fn get_obj<J, D>(&self, use_ids: bool, decode: D)->Result<(J, Msg), std::io::Error> 
+        where J: 'static, D: Fn(&[u8]) -> Result<J, std::io::Error>
     {
         let mut id = 1;
         if use_ids {
             id = self.get_id::<J>();
         }
-        let mut msgs = self.msgs.lock().unwrap();
+        return self.get_obj_by_id(id, decode);
+    }
+
+    fn get_obj_by_id<J, D>(&self, id: u64, decode: D)->Result<(J, Msg), std::io::Error> 
+        where D: Fn(&[u8]) -> Result<J, std::io::Error>
+    {
+        let mut msgs = self.lock_msgs();
 
         match msgs.get_mut(&id) {
             Some(msg_type_vec) => {
+                self.expire(msg_type_vec);
                 match msg_type_vec.pop_front() {
-                    Some((addr, msg_vec)) => {
-                        match T::deserial(&msg_vec){
+                    Some(msg) => {
+                        self.drained.notify_all();
+                        match decode(&msg.payload){
                             Ok(obj) => {
-                                return Ok((addr, obj))
+                                return Ok((obj, msg))
                             },
-                            Err(_) => {
-                                return Err(std::io::Error::new(ErrorKind::InvalidData, "Could not be deserialized"))
+                            Err(e) if self.retain_on_error => {
+                                msg_type_vec.push_front(msg);
+                                return Err(e)
+                            },
+                            Err(e) => {
+                                self.undeliverable(msg.addr, msg.payload);
+                                return Err(e)
                             }
                         }
                     },
@@ -471,25 +4906,26 @@ impl MsgStorage {
         }
     }
 
-    fn peek<T, J>(&self, use_ids: bool)->Result<(SocketAddr, J), std::io::Error> 
-        where T: SerDesType, J: de::DeserializeOwned + 'static
+    fn peek<J, D>(&self, use_ids: bool, decode: D)->Result<(SocketAddr, J), std::io::Error> 
+        where J: 'static, D: Fn(&[u8]) -> Result<J, std::io::Error>
     {
         let mut id = 1;
         if use_ids {
             id = self.get_id::<J>();
         }
 
-        let mut msgs = self.msgs.lock().unwrap();
+        let mut msgs = self.lock_msgs();
 
         match msgs.get_mut(&id) {
             Some(vec) => {
+                self.expire(vec);
                 match vec.front() {
-                    Some((addr, vec)) => {
-                        match T::deserial(&vec){
+                    Some(msg) => {
+                        match decode(&msg.payload){
                             Ok(obj) => {
-                                return Ok((*addr, obj))
+                                return Ok((msg.addr, obj))
                             },
-                            Err(_) => return Err(std::io::Error::new(ErrorKind::InvalidData, "Could not be deserialized"))
+                            Err(e) => return Err(e)
                         }
                     },
                     None => return Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
@@ -499,20 +4935,103 @@ impl MsgStorage {
         }
     }
 
-    fn remove_front<T, J>(&self, use_ids: bool) -> Result<(), std::io::Error>
-        where T: SerDesType, J: de::DeserializeOwned + 'static
+    fn get_from<J, D>(&self, use_ids: bool, addr: SocketAddr, decode: D)->Result<J, std::io::Error> 
+        where J: 'static, D: Fn(&[u8]) -> Result<J, std::io::Error>
+    {
+        let mut id = 1;
+        if use_ids {
+            id = self.get_id::<J>();
+        }
+
+        let mut msgs = self.lock_msgs();
+
+        let vec = msgs.get_mut(&id).ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))?;
+        self.expire(vec);
+        let msg = vec.front_from(addr)
+            .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "No datagram from the address"))?;
+
+        match decode(&msg.payload) {
+            Ok(obj) => {
+                vec.pop_from(addr);
+                self.drained.notify_all();
+                return Ok(obj)
+            },
+            Err(e) if self.retain_on_error => return Err(e),
+            Err(e) => {
+                let msg = vec.pop_from(addr).unwrap();
+                self.drained.notify_all();
+                self.undeliverable(msg.addr, msg.payload);
+                return Err(e)
+            }
+        }
+    }
+
+    fn peek_from<J, D>(&self, use_ids: bool, addr: SocketAddr, decode: D)->Result<J, std::io::Error> 
+        where J: 'static, D: Fn(&[u8]) -> Result<J, std::io::Error>
+    {
+        let mut id = 1;
+        if use_ids {
+            id = self.get_id::<J>();
+        }
+
+        let mut msgs = self.lock_msgs();
+
+        match msgs.get_mut(&id) {
+            Some(vec) => {
+                self.expire(vec);
+                match vec.front_from(addr) {
+                    Some(msg) => return decode(&msg.payload),
+                    None => return Err(std::io::Error::new(ErrorKind::NotFound, "No datagram from the address"))
+                }
+            },
+            None => Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+        }
+    }
+
+    fn remove_front<J>(&self, use_ids: bool) -> Result<(), std::io::Error>
+        where J: de::DeserializeOwned + 'static
     {
         let mut id = 1;
         if use_ids {
             id = self.get_id::<J>();
         }
 
-        let mut msgs = self.msgs.lock().unwrap();
+        let mut msgs = self.lock_msgs();
 
         match msgs.get_mut(&id) {
             Some(vec) => {
                 match vec.pop_front() {
-                    Some(_) => {return Ok(())},
+                    Some(_) => {
+                        self.drained.notify_all();
+                        return Ok(());
+                    },
+                    None => return Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+                }
+            },
+            None => Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+        }
+    }
+
+    fn recv_signal<J>(&self, use_ids: bool) -> Result<SocketAddr, std::io::Error>
+        where J: 'static
+    {
+        let mut id = 1;
+        if use_ids {
+            id = self.get_id::<J>();
+        }
+        let mut msgs = self.lock_msgs();
+
+        match msgs.get_mut(&id) {
+            Some(vec) => {
+                self.expire(vec);
+                match vec.front() {
+                    Some(msg) if !msg.payload.is_empty() => {
+                        return Err(std::io::Error::new(ErrorKind::InvalidData, "Datagram is not a signal"))
+                    },
+                    Some(_) => {
+                        let msg = vec.pop_front().unwrap();
+                        return Ok(msg.addr);
+                    },
                     None => return Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
                 }
             },
@@ -520,67 +5039,398 @@ impl MsgStorage {
         }
     }
 
-    fn remove_all<T, J>(&self, use_ids: bool) -> Result<(), std::io::Error>
-        where T: SerDesType, J: de::DeserializeOwned + 'static
+    fn remove_all<J>(&self, use_ids: bool) -> Result<(), std::io::Error>
+        where J: de::DeserializeOwned + 'static
     {
         let mut id = 1;
         if use_ids {
             id = self.get_id::<J>();
         }
-        let mut msgs = self.msgs.lock().unwrap();
+        let mut msgs = self.lock_msgs();
 
         match msgs.get_mut(&id) {
             Some(vec) => {
-                vec.drain(..);
+                vec.clear();
+                self.drained.notify_all();
                 return Ok(());    
             }
             None => Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
         }
     }
 
-    fn get_obj_all<T, J>(&self, use_ids: bool) -> Result<Vec<(SocketAddr, J)>, std::io::Error>
-        where T: SerDesType, J: de::DeserializeOwned + 'static
+    /// Also provides the number of datagrams that failed to deserialize
+    fn get_obj_all<J, D>(&self, use_ids: bool, max: usize, decode: D) -> Result<(Vec<(SocketAddr, J)>, usize), std::io::Error>
+        where J: 'static, D: Fn(&[u8]) -> Result<J, std::io::Error>
     {
         let mut id = 1;
         if use_ids {
             id = self.get_id::<J>();
         }
-        let mut msgs = self.msgs.lock().unwrap();
+        let mut msgs = self.lock_msgs();
 
         match msgs.get_mut(&id) {
             Some(vec) => {
-                let x: Vec<(SocketAddr, J)> = vec
-                    .drain(..)
-                    .into_iter()
-                    .filter_map(|(addr, vec)| 
-                    {
-                        match T::deserial(&vec) 
-                        {
-                            Ok(obj) => return Some((addr, obj)),
-                            Err(_) => return None
-                        }  
-                    })
-                    .collect();
-                    return Ok(x)       
+                self.expire(vec);
+                let taken = vec.take_oldest(max);
+                let mut x: Vec<(SocketAddr, J)> = Vec::with_capacity(taken.len());
+                let mut retained = vec![];
+                let mut failed = 0;
+                for msg in taken {
+                    match decode(&msg.payload) {
+                        Ok(obj) => x.push((msg.addr, obj)),
+                        Err(_) if self.retain_on_error => { failed += 1; retained.push(msg) },
+                        Err(_) => { failed += 1; self.undeliverable(msg.addr, msg.payload) }
+                    }
+                }
+                //Datagrams that failed to deserialize go back to the front in their original order
+                for msg in retained.into_iter().rev() {
+                    vec.push_front(msg);
+                }
+                self.drained.notify_all();
+                return Ok((x, failed))
+            }
+            None => Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+        }
+    }
+
+    fn get_where<J, D, P>(&self, use_ids: bool, decode: D, pred: P) -> Vec<(SocketAddr, J)>
+        where J: 'static, D: Fn(&[u8]) -> Result<J, std::io::Error>, P: Fn(&SocketAddr, &J) -> bool
+    {
+        let mut id = 1;
+        if use_ids {
+            id = self.get_id::<J>();
+        }
+        let mut msgs = self.lock_msgs();
+
+        let mut matches = vec![];
+        if let Some(vec) = msgs.get_mut(&id) {
+            self.expire(vec);
+            let len = vec.len();
+            for msg in vec.take_oldest(len) {
+                match decode(&msg.payload) {
+                    Ok(obj) if pred(&msg.addr, &obj) => matches.push((msg.addr, obj)),
+                    _ => vec.push_back(msg)
+                }
             }
+        }
+        return matches;
+    }
+
+    fn get_first_of<R>(&self, decoders: &[Decoder<'_, R>]) -> Result<(SocketAddr, R), std::io::Error>
+    {
+        let mut msgs = self.lock_msgs();
+
+        match msgs.get_mut(&1) {
+            Some(vec) => {
+                self.expire(vec);
+                let decoded = match vec.front() {
+                    Some(msg) => decoders.iter().find_map(|decode| decode(&msg.payload)),
+                    None => return Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+                };
+                match decoded {
+                    Some(obj) => {
+                        let msg = vec.pop_front().unwrap();
+                        return Ok((msg.addr, obj));
+                    },
+                    None => return Err(std::io::Error::new(ErrorKind::InvalidData, "Could not be deserialized"))
+                }
+            },
             None => Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
         }
     }
 
-    fn add_msg(&self, id: u64, addr: SocketAddr, buffer: Vec<u8>) {
+    fn active_peers(&self) -> Vec<SocketAddr> 
+    {
+        let msgs = self.lock_msgs();
+
+        let peers: HashSet<SocketAddr> = msgs.values().flat_map(Queue::iter).map(|msg| msg.addr).collect();
+        return peers.into_iter().collect();
+    }
+
+    fn queue_depths(&self) -> Vec<(u64, usize)> 
+    {
+        let msgs = self.lock_msgs();
+
+        let mut depths: Vec<(u64, usize)> = msgs.iter().map(|(id, vec)| (*id, vec.len())).collect();
+        depths.sort_unstable();
+        return depths;
+    }
+
+    /// Number of datagrams queued across all types
+    fn pending(&self) -> usize 
+    {
+        let msgs = self.lock_msgs();
+        return msgs.values().map(Queue::len).sum();
+    }
+
+    fn add_msg(&self, id: u64, mut msg: Msg) {
+        {
+            let mut subscribers = self.lock(&self.subscribers);
+            if let Some(deliver) = subscribers.get_mut(&id) {
+                match deliver(msg.addr, &msg.payload) {
+                    Delivery::Delivered => return,
+                    Delivery::Closed => { subscribers.remove(&id); },
+                    Delivery::Undecodable => {}
+                }
+            }
+        }
         
-        let mut msgs = self.msgs.lock().unwrap();
+        let mut msgs = self.lock_msgs();
+
+        if self.preserve_global_order || self.per_sender_queues {
+            msg.seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if self.max_queues.is_some_and(|max_queues| msgs.len() >= max_queues) && !msgs.contains_key(&id) {
+            self.drops.record(DropReason::TooManyQueues, 1);
+            return;
+        }
         
-        match msgs.get_mut(&id) {
-            Some(vec) => {
-                vec.push_back((addr, buffer));
+        let vec = msgs.entry(id).or_insert_with(|| Queue::new(self.per_sender_queues, self.queue_capacity));
+        if let Some(max_queue_len) = self.max_queue_len {
+            while !vec.is_empty() && vec.len() >= max_queue_len {
+                vec.pop_front();
+                self.drops.record(DropReason::QueueFull, 1);
             }
-            None => {
-                let mut vec = VecDeque::new();
-                vec.push_back((addr, buffer));
-                msgs.insert(id, vec);
+        }
+        vec.push_back(msg);
+        self.arrived.notify_all();
+    }
+
+    /// Waits until a datagram is queued under the id or the deadline passes. Returns whether one is queued.
+    fn wait_for(&self, id: u64, deadline: Instant) -> bool 
+    {
+        let mut msgs = self.lock_msgs();
+        loop {
+            if let Some(vec) = msgs.get_mut(&id) {
+                self.expire(vec);
+                if !vec.is_empty() {
+                    return true;
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            msgs = self.arrived.wait_timeout(msgs, deadline - now).unwrap_or_else(|e| self.recover(&self.msgs, e)).0;
+        }
+    }
+
+    /// Waits until the queue under the id holds fewer than max_queue_len datagrams. Consumers 
+    /// notify drained, while the timeout covers the rarer ways of removing datagrams.
+    fn wait_for_room(&self, id: u64) 
+    {
+        let max_queue_len = match self.max_queue_len {
+            Some(max_queue_len) => max_queue_len,
+            None => return
+        };
+
+        let mut msgs = self.lock_msgs();
+        while msgs.get(&id).is_some_and(|vec| vec.len() >= max_queue_len) {
+            msgs = self.drained.wait_timeout(msgs, Duration::from_millis(10)).unwrap_or_else(|e| self.recover(&self.msgs, e)).0;
+        }
+    }
+
+    /// Removes and provides the oldest datagram among the queues of the given ids
+    fn pop_oldest_of(&self, ids: &[u64]) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        let mut msgs = self.lock_msgs();
+
+        let mut oldest: Option<(u64, (Instant, u64))> = None;
+        for id in ids {
+            if let Some(vec) = msgs.get_mut(id) {
+                self.expire(vec);
+                if let Some(msg) = vec.front() {
+                    let key = (msg.received, msg.seq);
+                    if oldest.is_none_or(|(_, oldest_key)| key < oldest_key) {
+                        oldest = Some((*id, key));
+                    }
+                }
             }
         }
+
+        let (id, _) = oldest?;
+        let msg = msgs.get_mut(&id)?.pop_front()?;
+        return Some((id, msg.addr, msg.payload));
+    }
+
+    /// Removes and provides the datagram received first, whatever its id
+    #[cfg(feature = "json")]
+    fn pop_oldest(&self) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        let mut msgs = self.lock_msgs();
+        for vec in msgs.values_mut() {
+            self.expire(vec);
+        }
+
+        let id = msgs
+            .iter()
+            .filter_map(|(id, vec)| vec.front().map(|msg| (*id, (msg.received, msg.seq))))
+            .min_by_key(|(_, key)| *key)
+            .map(|(id, _)| id)?;
+
+        let msg = msgs.get_mut(&id)?.pop_front()?;
+        return Some((id, msg.addr, msg.payload));
+    }
+
+    /// Removes and provides the oldest datagram of the highest priority queue that is not empty
+    fn pop_highest_priority(&self) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        let priorities = self.lock(&self.priorities).clone();
+        let mut msgs = self.lock_msgs();
+        for vec in msgs.values_mut() {
+            self.expire(vec);
+        }
+
+        let id = msgs
+            .iter()
+            .filter_map(|(id, vec)| vec.front().map(|msg| {
+                let priority = priorities.get(id).copied().unwrap_or(0);
+                (*id, (std::cmp::Reverse(priority), msg.received, msg.seq))
+            }))
+            .min_by_key(|(_, key)| *key)
+            .map(|(id, _)| id)?;
+
+        let msg = msgs.get_mut(&id)?.pop_front()?;
+        self.drained.notify_all();
+        return Some((id, msg.addr, msg.payload));
+    }
+
+    /// Largest payload set for the id with set_type_max_size
+    fn max_size(&self, id: u64) -> Option<usize> 
+    {
+        return self.lock(&self.max_sizes).get(&id).copied();
+    }
+
+    fn set_priority(&self, id: u64, level: u8) 
+    {
+        self.lock(&self.priorities).insert(id, level);
+    }
+
+    fn subscribe(&self, id: u64, deliver: Subscriber) 
+    {
+        self.lock(&self.subscribers).insert(id, deliver);
+    }
+
+    fn get_next_any(&self) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        if !self.preserve_global_order {
+            return None;
+        }
+
+        let mut msgs = self.lock_msgs();
+        let id = self.next_any_id(&mut msgs)?;
+
+        let msg = msgs.get_mut(&id)?.pop_front()?;
+        return Some((id, msg.addr, msg.payload));
+    }
+
+    /// Provides a copy of the datagram get_next_any would return, without removing it
+    fn peek_next_any(&self) -> Option<(u64, SocketAddr, Vec<u8>)> 
+    {
+        if !self.preserve_global_order {
+            return None;
+        }
+
+        let mut msgs = self.lock_msgs();
+        let id = self.next_any_id(&mut msgs)?;
+
+        let msg = msgs.get(&id)?.front()?;
+        return Some((id, msg.addr, msg.payload.clone()));
+    }
+
+    /// Expires old datagrams, then finds the id of the queue holding the globally-oldest one
+    fn next_any_id(&self, msgs: &mut HashMap<u64, Queue>) -> Option<u64> 
+    {
+        for vec in msgs.values_mut() {
+            self.expire(vec);
+        }
+
+        return msgs
+            .iter()
+            .filter_map(|(id, vec)| vec.front().map(|msg| (*id, msg.seq)))
+            .min_by_key(|(_, seq)| *seq)
+            .map(|(id, _)| id);
+    }
+
+    /// Removes expired datagrams from every queue
+    fn sweep(&self) 
+    {
+        let mut msgs = self.lock_msgs();
+        for vec in msgs.values_mut() {
+            self.expire(vec);
+        }
+    }
+
+    /// Replaces the queue with an empty one and provides its datagrams
+    fn take_queue(&self, id: u64) -> Vec<(SocketAddr, Vec<u8>)> 
+    {
+        let mut queue = match self.lock_msgs().get_mut(&id) {
+            Some(vec) => std::mem::replace(vec, Queue::new(self.per_sender_queues, 0)),
+            None => return vec![]
+        };
+        self.drained.notify_all();
+        self.expire(&mut queue);
+
+        let len = queue.len();
+        return queue.take_oldest(len).into_iter().map(|msg| (msg.addr, msg.payload)).collect();
+    }
+
+    /// Moves the oldest datagram of the queue to its back. With preserve_global_order it also
+    /// becomes the newest datagram overall.
+    fn rotate(&self, id: u64) -> Result<(), std::io::Error> 
+    {
+        let mut msgs = self.lock_msgs();
+
+        let vec = match msgs.get_mut(&id) {
+            Some(vec) => vec,
+            None => return Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+        };
+        self.expire(vec);
+
+        let mut msg = match vec.pop_front() {
+            Some(msg) => msg,
+            None => return Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+        };
+        if self.preserve_global_order || self.per_sender_queues {
+            msg.seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        }
+        vec.push_back(msg);
+        return Ok(());
+    }
+
+    /// Puts a datagram back at the front of the queue. With preserve_global_order it also
+    /// becomes the oldest datagram overall.
+    fn requeue_front(&self, id: u64, addr: SocketAddr, buffer: Vec<u8>) 
+    {
+        let msg = Msg { addr, payload: buffer, seq: 0, received: self.clock.now(), meta: EnvelopeMeta::default(), origin: None, ttl: None };
+
+        let mut msgs = self.lock_msgs();
+        msgs.entry(id)
+            .or_insert_with(|| Queue::new(self.per_sender_queues, self.queue_capacity))
+            .push_front(msg);
+        self.arrived.notify_all();
+    }
+
+    /// Keeps a datagram that failed to deserialize as a dead letter, or counts it as dropped
+    fn undeliverable(&self, addr: SocketAddr, payload: Vec<u8>) 
+    {
+        match &self.dead_letters {
+            Some(dead_letters) => self.lock(dead_letters).push((addr, payload)),
+            None => self.drops.record(DropReason::Deserialize, 1)
+        }
+    }
+
+    /// Removes datagrams older than the message ttl from the front of the queue. Datagrams are
+    /// queued in arrival order, so the expired ones are always at the front.
+    fn expire(&self, vec: &mut Queue) 
+    {
+        if let Some(ttl) = self.message_ttl {
+            let now = self.clock.now();
+            let expired = vec.expire_where(|msg| now.saturating_duration_since(msg.received) > ttl);
+            self.drops.record(DropReason::Expired, expired);
+        }
     }
 
     fn get_id<T>(&self)->u64 
@@ -588,44 +5438,174 @@ impl MsgStorage {
     {
         
         let id = std::any::TypeId::of::<T>();
-        let mut ids = self.ids.lock().unwrap();
+        let mut ids = self.lock(&self.ids);
 
         match ids.get(&id) {        
             Some(val) => return *val,
             None => {
-                let obj = MsgStorage::calculate_hash::<T>();
+                let obj = self.calculate_hash::<T>();
                 ids.insert(id, obj);
                 return obj;
             }
         }
     }
 
-    fn calculate_hash<T>()->u64 
+    fn calculate_hash<T>(&self)->u64 
         where T: 'static
+    {
+        return self.calculate_name_hash(short_type_name::<T>());
+    }
+
+    fn calculate_name_hash(&self, name: &str)->u64 
     {
         let mut hasher = hash_map::DefaultHasher::new();
-        std::any::type_name::<T>()
-            .split("::")
-            .collect::<Vec<_>>()
-            .pop()
-            .hash(&mut hasher);
-        return hasher.finish();
+        //The unseeded hash must stay unchanged so existing ids remain compatible
+        if let Some(seed) = self.hash_seed {
+            seed.hash(&mut hasher);
+        }
+        Some(name).hash(&mut hasher);
+        //Setting the high bit keeps generated ids out of the reserved range
+        return hasher.finish() | AUTO_ID_BIT;
+    }
+
+    fn get_id_by_name(&self, name: &str)->u64 
+    {
+        let mut names = self.lock(&self.names);
+
+        match names.get(name) {
+            Some(val) => return *val,
+            None => {
+                let obj = self.calculate_name_hash(name);
+                names.insert(String::from(name), obj);
+                return obj;
+            }
+        }
+    }
+
+    /// Provides the id of a name without recording it, for names received from the network
+    #[cfg(feature = "json")]
+    fn lookup_id_by_name(&self, name: &str)->u64 
+    {
+        let names = self.lock(&self.names);
+        return names.get(name).copied().unwrap_or_else(|| self.calculate_name_hash(name));
     }
 
-    fn new()->MsgStorage 
+    fn set_id_by_name(&self, name: &str, id: u64) 
+    {
+        let mut names = self.lock(&self.names);
+        names.insert(String::from(name), id);
+    }
+
+    /// Removes and provides the oldest raw datagram stored under the id
+    fn get_raw(&self, id: u64)->Result<(SocketAddr, Vec<u8>), std::io::Error> 
+    {
+        let mut msgs = self.lock_msgs();
+
+        match msgs.get_mut(&id) {
+            Some(msg_type_vec) => {
+                self.expire(msg_type_vec);
+                match msg_type_vec.pop_front() {
+                    Some(msg) => {
+                        self.drained.notify_all();
+                        return Ok((msg.addr, msg.payload));
+                    },
+                    None => return Err(std::io::Error::new(ErrorKind::NotFound, "Empty Vector"))
+                }
+            },
+            None => return Err(std::io::Error::new(ErrorKind::NotFound, "Key not found"))
+        }
+    }
+
+    fn new(preserve_global_order: bool, queue_capacity: usize, max_queue_len: Option<usize>, 
+        message_ttl: Option<Duration>, clock: Arc<dyn Clock>, hash_seed: Option<(u64, u64)>, capture_dead_letters: bool)->MsgStorage 
     {
         let ids = Mutex::from(HashMap::new());
+        let names = Mutex::from(HashMap::new());
+        let priorities = Mutex::from(HashMap::new());
         let msgs = Mutex::from(HashMap::new());
+        let arrived = Condvar::new();
+        let drained = Condvar::new();
+        let next_seq = AtomicU64::new(0);
+        let subscribers = Mutex::from(HashMap::new());
 
         return MsgStorage {
             ids,
-            msgs
+            declared: Mutex::from(HashSet::new()),
+            names,
+            priorities,
+            max_sizes: Mutex::from(HashMap::new()),
+            subscribers,
+            acks: Mutex::from(VecDeque::new()),
+            acked: Condvar::new(),
+            next_reliable_seq: AtomicU64::new(0),
+            msgs,
+            arrived,
+            drained,
+            drops: DropCounters::default(),
+            dead_letters: capture_dead_letters.then(|| Mutex::from(vec![])),
+            next_seq,
+            poisoned: AtomicBool::new(false),
+            preserve_global_order,
+            retain_on_error: false,
+            queue_capacity,
+            max_queue_len,
+            max_queues: None,
+            per_sender_queues: false,
+            message_ttl,
+            clock,
+            hash_seed
         }
     }
 
     pub fn set_id(&self, type_id: TypeId, id: u64) 
     {
-        let mut ids = self.ids.lock().unwrap();
+        let mut ids = self.lock(&self.ids);
         ids.insert(type_id, id);
     }
+
+    /// Records an id declared by a type rather than generated or set, so it counts as known
+    fn declare_id(&self, id: u64) 
+    {
+        self.lock(&self.declared).insert(id);
+    }
+
+    /// Whether the id belongs to a type or name the manager has used, or has a subscription
+    fn is_known(&self, id: u64) -> bool 
+    {
+        return self.lock(&self.declared).contains(&id)
+            || self.lock(&self.ids).values().any(|known| *known == id)
+            || self.lock(&self.names).values().any(|known| *known == id)
+            || self.lock(&self.subscribers).contains_key(&id);
+    }
+
+    /// Records an acknowledgement received for a reliable datagram
+    fn ack(&self, addr: SocketAddr, seq: u64) 
+    {
+        let mut acks = self.lock(&self.acks);
+        if acks.len() >= MAX_PENDING_ACKS {
+            acks.pop_front();
+        }
+        acks.push_back((addr, seq));
+        self.acked.notify_all();
+    }
+
+    /// Waits until the address acknowledges the sequence number or the deadline passes. Returns 
+    /// whether it was acknowledged.
+    fn wait_for_ack(&self, addr: SocketAddr, seq: u64, deadline: Instant) -> bool 
+    {
+        let mut acks = self.lock(&self.acks);
+        loop {
+            if acks.contains(&(addr, seq)) {
+                //Retransmissions may have been acknowledged more than once
+                acks.retain(|ack| *ack != (addr, seq));
+                return true;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            acks = self.acked.wait_timeout(acks, deadline - now).unwrap_or_else(|e| self.recover(&self.acks, e)).0;
+        }
+    }
 }
@@ -0,0 +1,23 @@
+/// Trait for datagram types declaring their own id.
+/// 
+/// Types implementing it are sent and received under ID with UdpManager::send_typed and 
+/// UdpManager::get_typed, without generating an id from the type name or calling set_id. The id 
+/// stays the same across programs and Rust versions, unlike a generated one.
+/// 
+/// ```rust
+/// use udp_netmsg::msg_id::MsgId;
+/// 
+/// struct UpdatePos {
+///     pub x: f32,
+///     pub y: f32,
+///     pub z: f32
+/// }
+/// 
+/// impl MsgId for UpdatePos {
+///     const ID: u64 = 1000;
+/// }
+/// ```
+pub trait MsgId {
+    /// Id sent ahead of the payload. Must not fall within RESERVED_IDS.
+    const ID: u64;
+}
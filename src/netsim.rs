@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+/// Network conditions simulated on the receive path (see Builder::simulate).
+#[derive(Debug, Clone, Copy)]
+pub struct SimConfig {
+    /// Probability, between 0 and 1, that a received datagram is dropped
+    pub loss: f32,
+    /// Time a datagram is held before being added to the underlying storage
+    pub delay: Duration,
+    /// Largest random amount added to or removed from the delay
+    pub jitter: Duration
+}
+
+impl SimConfig {
+    /// Picks the delay of a single datagram within delay ± jitter
+    pub(crate) fn sample_delay(&self) -> Duration {
+        let offset = (rand::random::<f64>() * 2.0 - 1.0) * self.jitter.as_secs_f64();
+        return Duration::from_secs_f64((self.delay.as_secs_f64() + offset).max(0.0));
+    }
+}
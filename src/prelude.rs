@@ -1,2 +1,10 @@
 pub use crate::manager::Builder;
-pub use crate::serdes::JSON;
\ No newline at end of file
+#[cfg(feature = "json")]
+pub use crate::serdes::JSON;
+#[cfg(feature = "bincode")]
+pub use crate::serdes::Bincode;
+#[cfg(feature = "yaml")]
+pub use crate::serdes::YAML;
+pub use crate::get_first_of;
+#[cfg(feature = "dtls")]
+pub use crate::dtls::DtlsConfig;
@@ -1,7 +1,9 @@
-use serde::de::DeserializeOwned;
+use serde::de::{Deserialize, DeserializeOwned};
 use serde::ser::Serialize;
-use bincode;
-use serde_yaml;
+use std::io::ErrorKind;
+#[cfg(feature = "bincode")]
+use bincode::Options;
+use erased_serde;
 
 
 
@@ -9,49 +11,269 @@ use serde_yaml;
 pub trait SerDesType {
     type Error;
 
+    /// Canonical name of the format, useful for recording or negotiating the encoding
+    const NAME: &'static str = "custom";
+
+    /// MIME type of the format
+    const MIME: &'static str = "application/octet-stream";
+
     fn serial<T: ?Sized + Serialize>(obj: &T) -> Result<Vec<u8>, Self::Error>;
 
+    /// Serializes onto the end of the buffer (see UdpManager::send_into). Formats able to write 
+    /// into an existing buffer should override it to avoid the intermediate Vec of serial.
+    fn serial_into<T: ?Sized + Serialize>(obj: &T, buf: &mut Vec<u8>) -> Result<(), Self::Error> {
+        buf.extend_from_slice(&Self::serial(obj)?);
+        return Ok(());
+    }
+
     fn deserial<T: DeserializeOwned>(v: &'_ [u8]) -> Result<T, Self::Error>;
+
+    /// Creates a deserializer over the bytes and hands it to the visitor. Used for type-erased
+    /// decoding, such as with MessageRegistry. Formats that do not implement it report the 
+    /// operation as unsupported.
+    fn deserial_erased<'de>(_v: &'de [u8], 
+        _visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        return Err(std::io::Error::new(ErrorKind::Unsupported, "Format does not support type-erased decoding"));
+    }
 }
 
-/// Convenience struct for SerDes Operations using the JSON format
+/// Convenience struct for SerDes Operations using the JSON format. Requires the json feature.
+#[cfg(feature = "json")]
 pub struct JSON;
+#[cfg(feature = "json")]
 impl SerDesType for JSON {
     type Error = serde_json::Error;
 
+    const NAME: &'static str = "json";
+    const MIME: &'static str = "application/json";
+
     fn serial<T: ?Sized + Serialize>(obj: &T) -> Result<Vec<u8>, Self::Error> {
         return serde_json::to_vec(obj);
     }
 
+    fn serial_into<T: ?Sized + Serialize>(obj: &T, buf: &mut Vec<u8>) -> Result<(), Self::Error> {
+        return serde_json::to_writer(buf, obj);
+    }
+
     fn deserial<T: DeserializeOwned>(v: &'_ [u8])-> Result<T, Self::Error> {
         return serde_json::from_slice(v);
     }
+
+    fn deserial_erased<'de>(v: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        return JSON.deserial_into(v, visit);
+    }
 }
 
-/// Convenience struct for SerDes Operations using the Bincode format
+/// Convenience struct for SerDes Operations using the Bincode format. Requires the bincode feature.
+#[cfg(feature = "bincode")]
 pub struct Bincode;
+#[cfg(feature = "bincode")]
 impl SerDesType for Bincode {
     type Error = bincode::Error;
 
+    const NAME: &'static str = "bincode";
+
     fn serial<T: ?Sized + Serialize>(obj: &T) -> Result<Vec<u8>, Self::Error> {
         return bincode::serialize(obj);
     }
 
+    fn serial_into<T: ?Sized + Serialize>(obj: &T, buf: &mut Vec<u8>) -> Result<(), Self::Error> {
+        return bincode::serialize_into(buf, obj);
+    }
+
     fn deserial<T: DeserializeOwned>(v: &'_ [u8])-> Result<T, Self::Error> {
         return bincode::deserialize(v);
     }
+
+    fn deserial_erased<'de>(v: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        return Bincode.deserial_into(v, visit);
+    }
 }
 
-/// Convenience struct for SerDes Operations using the YAML format
+/// Convenience struct for SerDes Operations using the YAML format. Requires the yaml feature.
+#[cfg(feature = "yaml")]
 pub struct YAML;
+#[cfg(feature = "yaml")]
 impl SerDesType for YAML {
     type Error = serde_yaml::Error;
 
+    const NAME: &'static str = "yaml";
+    const MIME: &'static str = "application/yaml";
+
     fn serial<T: ?Sized + Serialize>(obj: &T) -> Result<Vec<u8>, Self::Error> {
         return serde_yaml::to_vec(obj);
     }
 
+    fn serial_into<T: ?Sized + Serialize>(obj: &T, buf: &mut Vec<u8>) -> Result<(), Self::Error> {
+        return serde_yaml::to_writer(buf, obj);
+    }
+
     fn deserial<T: DeserializeOwned>(v: &'_ [u8])-> Result<T, Self::Error> {
         return serde_yaml::from_slice(v);
     }
-}
\ No newline at end of file
+
+    fn deserial_erased<'de>(v: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        return YAML.deserial_into(v, visit);
+    }
+}
+
+/// Implemented on SerDes formats able to deserialize types that borrow from the input bytes 
+/// (see UdpManager::with_front).
+pub trait BorrowSerDes: SerDesType {
+    fn deserial_borrowed<'de, T: Deserialize<'de>>(v: &'de [u8]) -> Result<T, Self::Error>;
+}
+
+#[cfg(feature = "json")]
+impl BorrowSerDes for JSON {
+    fn deserial_borrowed<'de, T: Deserialize<'de>>(v: &'de [u8]) -> Result<T, Self::Error> {
+        return serde_json::from_slice(v);
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl BorrowSerDes for Bincode {
+    fn deserial_borrowed<'de, T: Deserialize<'de>>(v: &'de [u8]) -> Result<T, Self::Error> {
+        return bincode::deserialize(v);
+    }
+}
+
+/// Links the 'static form of a borrowing datagram, whose type determines the id, to the form 
+/// deserialized from the received bytes.
+/// 
+/// ```rust,ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Chat<'a> {
+///     #[serde(borrow)]
+///     text: Cow<'a, str>
+/// }
+/// 
+/// impl Borrowable for Chat<'static> {
+///     type Borrowed<'de> = Chat<'de>;
+/// }
+/// ```
+pub trait Borrowable: 'static {
+    type Borrowed<'de>: Deserialize<'de>;
+}
+
+/// Implemented on a SerDes format so it can be chosen at runtime (see Builder::start_dyn).
+/// 
+/// Unlike SerDesType, this trait is object safe. The object is bridged to serde using erased_serde.
+pub trait DynSerDes: Send + Sync {
+    /// Canonical name of the format
+    fn name(&self) -> &'static str;
+
+    fn serial(&self, obj: &dyn erased_serde::Serialize) -> Result<Vec<u8>, std::io::Error>;
+
+    /// Creates a deserializer over the bytes and hands it to the visitor
+    fn deserial_into<'de>(&self, v: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error>;
+}
+
+/// Placeholder format of managers started with Builder::start_dyn, where the format is chosen at runtime
+pub struct Dynamic;
+impl SerDesType for Dynamic {
+    type Error = std::io::Error;
+
+    const NAME: &'static str = "dynamic";
+
+    fn serial<T: ?Sized + Serialize>(_obj: &T) -> Result<Vec<u8>, Self::Error> {
+        return Err(std::io::Error::new(ErrorKind::Unsupported, "Format is chosen at runtime"));
+    }
+
+    fn deserial<T: DeserializeOwned>(_v: &'_ [u8])-> Result<T, Self::Error> {
+        return Err(std::io::Error::new(ErrorKind::Unsupported, "Format is chosen at runtime"));
+    }
+}
+
+#[cfg(feature = "json")]
+impl DynSerDes for JSON {
+    fn name(&self) -> &'static str {
+        return JSON::NAME;
+    }
+
+    fn serial(&self, obj: &dyn erased_serde::Serialize) -> Result<Vec<u8>, std::io::Error> {
+        return serde_json::to_vec(obj).map_err(|_| serialize_error());
+    }
+
+    fn deserial_into<'de>(&self, v: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        let mut de = serde_json::Deserializer::from_slice(v);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(|_| deserialize_error())?;
+        return de.end().map_err(|_| deserialize_error());
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl DynSerDes for Bincode {
+    fn name(&self) -> &'static str {
+        return Bincode::NAME;
+    }
+
+    fn serial(&self, obj: &dyn erased_serde::Serialize) -> Result<Vec<u8>, std::io::Error> {
+        return bincode::serialize(obj).map_err(|_| serialize_error());
+    }
+
+    fn deserial_into<'de>(&self, v: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        //Same configuration as bincode::deserialize
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes();
+        let mut de = bincode::Deserializer::from_slice(v, options);
+        return visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de)).map_err(|_| deserialize_error());
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl DynSerDes for YAML {
+    fn name(&self) -> &'static str {
+        return YAML::NAME;
+    }
+
+    fn serial(&self, obj: &dyn erased_serde::Serialize) -> Result<Vec<u8>, std::io::Error> {
+        return serde_yaml::to_vec(obj).map_err(|_| serialize_error());
+    }
+
+    fn deserial_into<'de>(&self, v: &'de [u8], 
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer<'de>) -> Result<(), erased_serde::Error>) 
+        -> Result<(), std::io::Error> 
+    {
+        let de = serde_yaml::Deserializer::from_slice(v);
+        return visit(&mut <dyn erased_serde::Deserializer>::erase(de)).map_err(|_| deserialize_error());
+    }
+}
+
+/// Deserializes the bytes into an owned object using a runtime chosen format
+pub(crate) fn deserial_dyn<T: DeserializeOwned>(format: &dyn DynSerDes, v: &[u8]) -> Result<T, std::io::Error> {
+    let mut obj = None;
+    format.deserial_into(v, &mut |de| {
+        obj = Some(erased_serde::deserialize::<T>(de)?);
+        return Ok(());
+    })?;
+    return obj.ok_or_else(deserialize_error);
+}
+
+#[cfg(any(feature = "json", feature = "bincode", feature = "yaml"))]
+fn serialize_error() -> std::io::Error {
+    return std::io::Error::new(ErrorKind::InvalidData, "Could not serialize");
+}
+
+fn deserialize_error() -> std::io::Error {
+    return std::io::Error::new(ErrorKind::InvalidData, "Could not be deserialized");
+}
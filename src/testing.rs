@@ -0,0 +1,30 @@
+use crate::manager::{Builder, UdpManager};
+use crate::serdes::SerDesType;
+
+/// Starts two managers on ephemeral loopback ports, for tests that exchange datagrams.
+/// 
+/// The socket of each manager is connected to the other, so it only receives datagrams from its
+/// peer. Each manager sends to the other through the address the socket is connected to, for 
+/// example `a.send(msg, a.socket().unwrap().peer_addr().unwrap())`. Both use the default 
+/// configuration of the Builder struct.
+/// 
+/// # Panics
+/// 
+/// This will panic if either manager fails to start or its socket cannot be connected.
+pub fn loopback_pair<T>() -> (UdpManager<T>, UdpManager<T>) 
+    where T: SerDesType
+{
+    let start = || Builder::init()
+        .socket(String::from("127.0.0.1:0"))
+        .start::<T>()
+        .expect("Could not start a loopback manager");
+
+    let (first, second) = (start(), start());
+    for (manager, peer) in [(&first, &second), (&second, &first)] {
+        let peer_addr = peer.local_addrs().expect("Could not read the address of a loopback manager")[0];
+        manager.socket()
+            .and_then(|socket| socket.connect(peer_addr).ok())
+            .expect("Could not connect a loopback manager to its peer");
+    }
+    return (first, second);
+}
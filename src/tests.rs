@@ -1,7 +1,6 @@
-#[cfg(test)]
+#[cfg(all(test, feature = "json"))]
 mod struct_creation {
     use crate::prelude::*;
-    use crate::serdes::{Bincode, YAML};
     use serde::{Serialize, Deserialize};
     use std::{thread, time};
 
@@ -21,7 +20,7 @@ mod struct_creation {
     fn test_manual() {
         let net_msg = Builder::init().start::<JSON>().unwrap();
 
-        net_msg.set_id::<UpdatePos>(505550550);
+        net_msg.set_id::<UpdatePos>(505550550).unwrap();
         let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
         let name = RenameObj{name: String::from("Billy")};
         net_msg.send(name, String::from("127.0.0.1:39507")).unwrap();
@@ -49,6 +48,3051 @@ mod struct_creation {
         net_msg.get::<UpdatePos>().unwrap();
     }
 
+    #[test]
+    fn no_ids_succeed() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50005"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap(); 
+
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50005")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.get::<RenameObj>().unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_ids_panic() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50006"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap(); 
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50006")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50006")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.get::<RenameObj>().unwrap();
+    }
+
+    #[test]
+    #[allow(clippy::single_match)]
+    fn fail_des_keep_item() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50007"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap(); 
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50007")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        match net_msg.peek::<RenameObj>() {
+            Ok(_) => panic!("Should not have serialized correctly"),
+            Err(_) => {}
+        }
+
+        net_msg.peek::<UpdatePos>().unwrap();
+    }
+
+    #[test]
+    #[allow(clippy::single_match)]
+    fn remove_front() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50008"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap(); 
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50008")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50008")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        match net_msg.peek::<RenameObj>() {
+            Ok(_) => panic!("Should not have serialized correctly"),
+            Err(_) => {}
+        }
+        net_msg.remove_front::<RenameObj>().unwrap();
+        net_msg.peek::<RenameObj>().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn dscp() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50010"))
+            .dscp(46)
+            .start::<JSON>()
+            .unwrap();
+
+        let tos = socket2::SockRef::from(net_msg.socket().unwrap()).tos().unwrap();
+        assert_eq!(tos >> 2, 46);
+    }
+
+    #[test]
+    fn dscp_out_of_range() {
+        let result = Builder::init()
+            .socket(String::from("0.0.0.0:50011"))
+            .dscp(64)
+            .start::<JSON>();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn global_order() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50012"))
+            .preserve_global_order(true)
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.set_id::<UpdatePos>(1000).unwrap();
+        net_msg.set_id::<RenameObj>(2000).unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50012")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50012")).unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50012")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50012")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let mut ids = vec![];
+        while let Some((id, _, _)) = net_msg.get_next_any() {
+            ids.push(id);
+        }
+        assert_eq!(ids, vec![1000, 2000, 1000, 2000]);
+    }
+
+    struct Ping;
+
+    #[test]
+    fn signal() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50013"))
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send_signal::<Ping, _>(String::from("127.0.0.1:50013")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let addr = net_msg.recv_signal::<Ping>().unwrap();
+        assert_eq!(addr.port(), 50013);
+        assert!(net_msg.recv_signal::<Ping>().is_err());
+    }
+
+    #[test]
+    fn get_where() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50014"))
+            .start::<JSON>()
+            .unwrap();
+        let other = Builder::init()
+            .socket(String::from("0.0.0.0:50015"))
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 1f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50014")).unwrap();
+        let pos = UpdatePos{x: 2f32, y: 15f32, z: 15f32};
+        other.send(pos, String::from("127.0.0.1:50014")).unwrap();
+        let pos = UpdatePos{x: 3f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50014")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let matches = net_msg.get_where::<UpdatePos, _>(|addr, _| addr.port() == 50015);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].1.x, 2f32);
+
+        let remaining = net_msg.get_all::<UpdatePos>().unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(remaining[0].1.x, 1f32);
+        assert_eq!(remaining[1].1.x, 3f32);
+    }
+
+    #[test]
+    fn format_name() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50016"))
+            .start::<JSON>()
+            .unwrap();
+
+        assert_eq!(net_msg.format_name(), "json");
+    }
+
+    enum Either {
+        Pos(UpdatePos),
+        Rename(RenameObj)
+    }
+
+    #[test]
+    fn get_first_of() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50017"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap();
+
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50017")).unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50017")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        match get_first_of!(net_msg, UpdatePos => Either::Pos, RenameObj => Either::Rename).unwrap() {
+            (_, Either::Rename(name)) => assert_eq!(name.name, "Billy"),
+            (_, Either::Pos(_)) => panic!("Should have decoded a RenameObj")
+        }
+        match get_first_of!(net_msg, UpdatePos => Either::Pos, RenameObj => Either::Rename).unwrap() {
+            (_, Either::Pos(pos)) => assert_eq!(pos.x, 15f32),
+            (_, Either::Rename(_)) => panic!("Should have decoded an UpdatePos")
+        }
+    }
+
+    #[test]
+    fn set_read_timeout() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50018"))
+            .read_timeout(Some(time::Duration::from_secs(10)))
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.set_read_timeout(Some(time::Duration::from_millis(50))).unwrap();
+        assert!(net_msg.socket().unwrap().read_timeout().unwrap().is_some());
+
+        //Wakes up the receive that is still waiting with the old timeout
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50018")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.get::<RenameObj>().unwrap();
+
+        let start = time::Instant::now();
+        drop(net_msg);
+        assert!(start.elapsed() < time::Duration::from_secs(1));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn set_nonblocking() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50019"))
+            .start::<JSON>()
+            .unwrap();
+
+        assert!(socket2::SockRef::from(net_msg.socket().unwrap()).nonblocking().unwrap());
+
+        net_msg.set_read_timeout(Some(time::Duration::from_millis(50))).unwrap();
+        net_msg.set_nonblocking(false).unwrap();
+        assert!(!socket2::SockRef::from(net_msg.socket().unwrap()).nonblocking().unwrap());
+
+        net_msg.set_nonblocking(true).unwrap();
+        assert!(socket2::SockRef::from(net_msg.socket().unwrap()).nonblocking().unwrap());
+    }
+
+    #[test]
+    fn queue_capacity() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50020"))
+            .queue_capacity(64)
+            .start::<JSON>()
+            .unwrap();
+
+        for _ in 0..5 {
+            let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+            net_msg.send(pos, String::from("127.0.0.1:50020")).unwrap();
+        }
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get_all::<UpdatePos>().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn active_peers() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50023"))
+            .start::<JSON>()
+            .unwrap();
+        let peer1 = Builder::init()
+            .socket(String::from("0.0.0.0:50024"))
+            .start::<JSON>()
+            .unwrap();
+        let peer2 = Builder::init()
+            .socket(String::from("0.0.0.0:50025"))
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50023")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        peer1.send(name, String::from("127.0.0.1:50023")).unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        peer1.send(pos, String::from("127.0.0.1:50023")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        peer2.send(name, String::from("127.0.0.1:50023")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let mut ports: Vec<u16> = net_msg.active_peers().iter().map(|addr| addr.port()).collect();
+        ports.sort();
+        assert_eq!(ports, vec![50023, 50024, 50025]);
+    }
+
+    #[test]
+    fn socket_buffer_sizes() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50026"))
+            .recv_buffer_size(65536)
+            .send_buffer_size(65536)
+            .start::<JSON>()
+            .unwrap();
+
+        let socket = socket2::SockRef::from(net_msg.socket().unwrap());
+        assert!(socket.recv_buffer_size().unwrap() >= 65536);
+        assert!(socket.send_buffer_size().unwrap() >= 65536);
+    }
+
+    #[test]
+    fn send_ref() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50027"))
+            .start::<JSON>()
+            .unwrap();
+        let other = Builder::init()
+            .socket(String::from("0.0.0.0:50028"))
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send_ref(&pos, String::from("127.0.0.1:50027")).unwrap();
+        net_msg.send_ref(&pos, String::from("127.0.0.1:50028")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.get::<UpdatePos>().unwrap();
+        other.get::<UpdatePos>().unwrap();
+    }
+
+    #[test]
+    fn concurrent_send() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50029"))
+            .start::<JSON>()
+            .unwrap();
+        let net_msg = std::sync::Arc::new(net_msg);
+
+        let handles: Vec<_> = (0..2).map(|_| {
+            let net_msg = net_msg.clone();
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+                    net_msg.send(pos, String::from("127.0.0.1:50029")).unwrap();
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get_all::<UpdatePos>().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn get_with_time() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50030"))
+            .start::<JSON>()
+            .unwrap();
+
+        let before = time::Instant::now();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50030")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (_, _, received) = net_msg.get_with_time::<UpdatePos>().unwrap();
+        assert!(received >= before);
+        assert!(received - before < time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn message_ttl() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50031"))
+            .message_ttl(time::Duration::from_millis(200))
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50031")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.peek::<UpdatePos>().unwrap();
+
+        thread::sleep(time::Duration::from_millis(200));
+
+        assert!(net_msg.get::<UpdatePos>().is_err());
+    }
+
+    #[test]
+    #[ignore]
+    #[cfg(target_os = "linux")]
+    fn bind_device() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50032"))
+            .bind_device(String::from("lo"))
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50032")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.get::<UpdatePos>().unwrap();
+    }
+
+    #[test]
+    fn get_n() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50033"))
+            .start::<JSON>()
+            .unwrap();
+
+        for _ in 0..10 {
+            let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+            net_msg.send(pos, String::from("127.0.0.1:50033")).unwrap();
+        }
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get_n::<UpdatePos>(3).unwrap().len(), 3);
+        assert_eq!(net_msg.get_all::<UpdatePos>().unwrap().len(), 7);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn last_error() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50034"))
+            .start::<JSON>()
+            .unwrap();
+
+        assert!(net_msg.last_error().is_none());
+
+        //Nothing listens on the connected port, so the ICMP unreachable surfaces as a receive error
+        net_msg.socket().unwrap().connect("127.0.0.1:50035").unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50035")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.last_error().unwrap().kind(), std::io::ErrorKind::ConnectionRefused);
+        assert!(net_msg.take_last_error().is_some());
+        assert!(net_msg.last_error().is_none());
+    }
+
+    #[test]
+    fn send_buffer_hint() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50036"))
+            .send_buffer_hint(4)
+            .start::<JSON>()
+            .unwrap();
+
+        let name = RenameObj{name: String::from("A name longer than the hint")};
+        net_msg.send(name, String::from("127.0.0.1:50036")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get::<RenameObj>().unwrap().1.name, "A name longer than the hint");
+    }
+
+    #[cfg(all(feature = "bincode", feature = "yaml"))]
+    fn format_from_config(name: &str) -> Box<dyn crate::serdes::DynSerDes> {
+        match name {
+            "bincode" => Box::new(Bincode),
+            "yaml" => Box::new(YAML),
+            _ => Box::new(JSON)
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "bincode", feature = "yaml"))]
+    fn dynamic_format() {
+        for (port, name) in [(50037, "json"), (50038, "bincode"), (50039, "yaml")] {
+            let net_msg = Builder::init()
+                .socket(format!("0.0.0.0:{}", port))
+                .start_dyn(format_from_config(name))
+                .unwrap();
+
+            assert_eq!(net_msg.format_name(), name);
+
+            let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+            net_msg.send(pos, format!("127.0.0.1:{}", port)).unwrap();
+
+            thread::sleep(time::Duration::from_millis(100));
+
+            assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 15f32);
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn unreachable_peer() {
+        let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = errors.clone();
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50040"))
+            .on_recv_error(move |e| {
+                assert_eq!(e.kind(), std::io::ErrorKind::ConnectionRefused);
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            })
+            .start::<JSON>()
+            .unwrap();
+
+        //Nothing listens on the connected port yet, so the ICMP unreachable surfaces as a receive error
+        net_msg.socket().unwrap().connect("127.0.0.1:50041").unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50041")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(errors.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        //The listener keeps running once the peer comes up
+        let peer = Builder::init()
+            .socket(String::from("0.0.0.0:50041"))
+            .start::<JSON>()
+            .unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        peer.send(pos, String::from("127.0.0.1:50040")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.get::<UpdatePos>().unwrap();
+    }
+
+    #[test]
+    fn subscribe() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50042"))
+            .start::<JSON>()
+            .unwrap();
+
+        let positions = net_msg.subscribe::<UpdatePos>();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50042")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50042")).unwrap();
+
+        let (addr, pos) = positions.recv_timeout(time::Duration::from_secs(1)).unwrap();
+        assert_eq!(addr.port(), 50042);
+        assert_eq!(pos.x, 15f32);
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(net_msg.get::<UpdatePos>().is_err());
+        net_msg.get::<RenameObj>().unwrap();
+    }
+
+    #[test]
+    fn reserved_ids() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50043"))
+            .preserve_global_order(true)
+            .start::<JSON>()
+            .unwrap();
+
+        assert!(net_msg.set_id::<UpdatePos>(0).is_err());
+        assert!(net_msg.set_id::<UpdatePos>(255).is_err());
+        net_msg.set_id::<UpdatePos>(256).unwrap();
+
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50043")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (id, _, _) = net_msg.get_next_any().unwrap();
+        assert!(!crate::manager::RESERVED_IDS.contains(&id));
+    }
+
+
+    enum Incoming {
+        Pos(UpdatePos),
+        Rename(RenameObj)
+    }
+
+    #[test]
+    fn enum_dispatch() {
+        use crate::manager::MessageRegistry;
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50044"))
+            .start::<JSON>()
+            .unwrap();
+
+        let mut registry = MessageRegistry::new();
+        registry.register::<UpdatePos, _>(Incoming::Pos);
+        registry.register::<RenameObj, _>(Incoming::Rename);
+
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50044")).unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50044")).unwrap();
+        let name = RenameObj{name: String::from("Bob")};
+        net_msg.send(name, String::from("127.0.0.1:50044")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let mut order = vec![];
+        while let Some((_, msg)) = net_msg.get_enum(&registry) {
+            match msg {
+                Incoming::Pos(pos) => order.push(format!("{}", pos.x)),
+                Incoming::Rename(name) => order.push(name.name)
+            }
+        }
+        assert_eq!(order, vec!["Billy", "15", "Bob"]);
+    }
+
+
+    #[test]
+    fn async_send_flush_on_drop() {
+        let receiver = Builder::init()
+            .socket(String::from("0.0.0.0:50045"))
+            .start::<JSON>()
+            .unwrap();
+
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50046"))
+            .async_send(true)
+            .start::<JSON>()
+            .unwrap();
+
+        for i in 0..20 {
+            let name = RenameObj{name: format!("Billy {}", i)};
+            sender.send(name, String::from("127.0.0.1:50045")).unwrap();
+        }
+        drop(sender);
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(receiver.get_n::<RenameObj>(100).unwrap().len(), 20);
+    }
+
+
+    #[test]
+    fn named_ids() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50047"))
+            .start::<JSON>()
+            .unwrap();
+
+        assert!(net_msg.set_id_by_name("status", 7).is_err());
+        net_msg.set_id_by_name("status", 4000).unwrap();
+        assert_eq!(net_msg.get_id_by_name("status"), 4000);
+
+        net_msg.send_named("status", b"online".to_vec(), String::from("127.0.0.1:50047")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50047")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (_, payload) = net_msg.get_named("status").unwrap();
+        assert_eq!(payload, b"online".to_vec());
+        assert!(net_msg.get_named("status").is_err());
+
+        let (_, payload) = net_msg.get_named("RenameObj").unwrap();
+        let name: RenameObj = net_msg.decode_payload(&payload).unwrap();
+        assert_eq!(name.name, "Billy");
+    }
+
+
+    #[test]
+    fn short_datagrams() {
+        let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = errors.clone();
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50048"))
+            .on_recv_error(move |e| {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            })
+            .start::<JSON>()
+            .unwrap();
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50049").unwrap();
+        raw.send_to(&[], "127.0.0.1:50048").unwrap();
+        raw.send_to(&[1, 2, 3], "127.0.0.1:50048").unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(errors.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50048")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 15f32);
+    }
+
+
+    #[test]
+    fn unknown_id() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50050"))
+            .start::<JSON>()
+            .unwrap();
+
+        //An id that no type or name maps to is queued on its own rather than crashing the listener
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50051").unwrap();
+        raw.send_to(&[0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 1, 42], "127.0.0.1:50050").unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50050")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 15f32);
+        assert!(net_msg.last_error().is_none());
+    }
+
+
+    #[test]
+    fn get_next_any_by_id() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50052"))
+            .preserve_global_order(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let pos_id = net_msg.get_id_by_name("UpdatePos");
+        let name_id = net_msg.get_id_by_name("RenameObj");
+
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50052")).unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50052")).unwrap();
+        net_msg.forward(4000, b"raw", String::from("127.0.0.1:50052")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (id, _, payload) = net_msg.get_next_any().unwrap();
+        assert_eq!(id, name_id);
+        let name: RenameObj = net_msg.decode_payload(&payload).unwrap();
+        assert_eq!(name.name, "Billy");
+
+        assert_eq!(net_msg.get_next_any().unwrap().0, pos_id);
+
+        let (id, _, payload) = net_msg.get_next_any().unwrap();
+        assert_eq!((id, payload), (4000, b"raw".to_vec()));
+        assert!(net_msg.get_next_any().is_none());
+    }
+
+
+    #[test]
+    fn compressed_send() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50053"))
+            .compression_flag(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let name = RenameObj{name: "Billy".repeat(200)};
+        net_msg.send_compressed(&name, String::from("127.0.0.1:50053")).unwrap();
+        let name = RenameObj{name: String::from("Bob")};
+        net_msg.send(name, String::from("127.0.0.1:50053")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let names = net_msg.get_n::<RenameObj>(2).unwrap();
+        assert_eq!(names[0].1.name, "Billy".repeat(200));
+        assert_eq!(names[1].1.name, "Bob");
+
+        let plain = Builder::init()
+            .socket(String::from("0.0.0.0:50054"))
+            .start::<JSON>()
+            .unwrap();
+        let name = RenameObj{name: String::from("Bob")};
+        assert!(plain.send_compressed(&name, String::from("127.0.0.1:50054")).is_err());
+    }
+
+
+    #[test]
+    fn multiple_sockets() {
+        let net_msg = Builder::init()
+            .sockets(vec![String::from("0.0.0.0:50055"), String::from("0.0.0.0:50056")])
+            .start::<JSON>()
+            .unwrap();
+
+        let ports: Vec<u16> = net_msg.local_addrs().unwrap().iter().map(|addr| addr.port()).collect();
+        assert_eq!(ports, vec![50055, 50056]);
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50055")).unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50056")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get_n::<UpdatePos>(3).unwrap().len(), 2);
+    }
+
+
+    #[derive(Serialize, Deserialize)]
+    struct Chat<'a> {
+        #[serde(borrow)]
+        text: std::borrow::Cow<'a, str>
+    }
+
+    impl crate::serdes::Borrowable for Chat<'static> {
+        type Borrowed<'de> = Chat<'de>;
+    }
+
+    #[test]
+    fn borrowed_front() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50057"))
+            .start::<JSON>()
+            .unwrap();
+
+        let chat = Chat{text: std::borrow::Cow::Borrowed("hello")};
+        net_msg.send(chat, String::from("127.0.0.1:50057")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let borrowed = net_msg.with_front::<Chat<'static>, _, _>(|_, chat| {
+            assert_eq!(chat.text, "hello");
+            return matches!(chat.text, std::borrow::Cow::Borrowed(_));
+        });
+        assert_eq!(borrowed, Some(true));
+        assert!(net_msg.with_front::<Chat<'static>, _, _>(|_, _| ()).is_none());
+    }
+
+
+    #[test]
+    #[cfg(feature = "netsim")]
+    fn simulated_network() {
+        use crate::netsim::SimConfig;
+
+        let lossy = Builder::init()
+            .socket(String::from("0.0.0.0:50058"))
+            .simulate(SimConfig{loss: 1.0, delay: time::Duration::ZERO, jitter: time::Duration::ZERO})
+            .start::<JSON>()
+            .unwrap();
+
+        let slow = Builder::init()
+            .socket(String::from("0.0.0.0:50059"))
+            .simulate(SimConfig{
+                loss: 0.0, 
+                delay: time::Duration::from_millis(50), 
+                jitter: time::Duration::from_millis(10)
+            })
+            .start::<JSON>()
+            .unwrap();
+
+        for _ in 0..10 {
+            let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+            lossy.send(pos, String::from("127.0.0.1:50058")).unwrap();
+            let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+            slow.send(pos, String::from("127.0.0.1:50059")).unwrap();
+        }
+
+        thread::sleep(time::Duration::from_millis(20));
+        assert!(slow.get::<UpdatePos>().is_err());
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(lossy.get::<UpdatePos>().is_err());
+        assert_eq!(slow.get_n::<UpdatePos>(20).unwrap().len(), 10);
+    }
+
+
+    #[test]
+    fn debug_output() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50060"))
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50060")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let debug = format!("{:?}", net_msg);
+        assert!(debug.contains("50060"));
+        assert!(debug.contains("listening: true"));
+        assert!(debug.contains("pending: 1"));
+        assert_eq!(net_msg.summary(), debug);
+    }
+
+
+    #[test]
+    fn sweep_expired() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50061"))
+            .message_ttl(time::Duration::from_millis(50))
+            .sweep_interval(time::Duration::from_millis(20))
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50061")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(20));
+        assert_eq!(net_msg.active_peers().len(), 1);
+
+        thread::sleep(time::Duration::from_millis(150));
+        assert!(net_msg.active_peers().is_empty());
+    }
+
+
+    #[test]
+    fn send_builder() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50062"))
+            .compression_flag(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let name = RenameObj{name: "Billy".repeat(200)};
+        net_msg.send_builder(name)
+            .compressed(true)
+            .to("127.0.0.1:50062")
+            .send()
+            .unwrap();
+
+        let name = RenameObj{name: String::from("Bob")};
+        net_msg.send_builder(name)
+            .id(5000)
+            .to("127.0.0.1:50062")
+            .send()
+            .unwrap();
+
+        let name = RenameObj{name: String::from("Bob")};
+        assert!(net_msg.send_builder(name).send().is_err());
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.get::<RenameObj>().unwrap().1.name, "Billy".repeat(200));
+
+        net_msg.set_id_by_name("renamed", 5000).unwrap();
+        let (_, payload) = net_msg.get_named("renamed").unwrap();
+        let name: RenameObj = net_msg.decode_payload(&payload).unwrap();
+        assert_eq!(name.name, "Bob");
+    }
+
+
+    #[derive(Serialize, Deserialize)]
+    struct LegacyPos {
+        pub x: f32
+    }
+
+    impl crate::datagram::Datagram for LegacyPos {
+        fn header()->u32 {
+            return 834;
+        }
+    }
+
+    #[test]
+    fn legacy_header() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50063"))
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send_with_header(&LegacyPos{x: 15f32}, String::from("127.0.0.1:50063")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(net_msg.get::<LegacyPos>().is_err());
+        assert_eq!(net_msg.get_with_header::<LegacyPos>().unwrap().1.x, 15f32);
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct LegacyAck {
+        pub seq: u32
+    }
+
+    impl crate::datagram::Datagram for LegacyAck {
+        fn header()->u32 {
+            return 3;
+        }
+    }
+
+    #[test]
+    fn legacy_header_checks() {
+        let unknown = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let seen = unknown.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50155"))
+            .on_unknown(move |_, _, _| { seen.fetch_add(1, std::sync::atomic::Ordering::SeqCst); })
+            .start::<JSON>()
+            .unwrap();
+        assert!(net_msg.get_with_header::<LegacyPos>().is_err());
+
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50156"))
+            .start::<JSON>()
+            .unwrap();
+        let err = sender.send_with_header(&LegacyAck{seq: 1}, String::from("127.0.0.1:50155")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        sender.send_with_header(&LegacyPos{x: 15f32}, String::from("127.0.0.1:50155")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        assert_eq!(unknown.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert!(net_msg.get_with_header::<LegacyAck>().is_err());
+        let (_, pos) = net_msg.get_with_header::<LegacyPos>().unwrap();
+        assert_eq!(pos.x, 15f32);
+    }
+
+
+    #[test]
+    fn coalesce() {
+        let receiver = Builder::init()
+            .socket(String::from("0.0.0.0:50064"))
+            .coalesce(1000, time::Duration::from_millis(20))
+            .buffer_len(1000)
+            .start::<JSON>()
+            .unwrap();
+
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50065"))
+            .coalesce(1000, time::Duration::from_millis(20))
+            .start::<JSON>()
+            .unwrap();
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50066").unwrap();
+        raw.set_read_timeout(Some(time::Duration::from_millis(200))).unwrap();
+
+        for i in 0..5 {
+            let pos = UpdatePos{x: i as f32, y: 15f32, z: 15f32};
+            sender.send_ref(&pos, String::from("127.0.0.1:50064")).unwrap();
+            sender.send(pos, String::from("127.0.0.1:50066")).unwrap();
+        }
+
+        //All five messages to the raw socket arrive as a single datagram
+        let mut buffer = [0; 1000];
+        let (len, _) = raw.recv_from(&mut buffer).unwrap();
+        assert!(len > 5 * 12);
+        assert!(raw.recv_from(&mut buffer).is_err());
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let positions = receiver.get_n::<UpdatePos>(10).unwrap();
+        let xs: Vec<f32> = positions.iter().map(|(_, pos)| pos.x).collect();
+        assert_eq!(xs, vec![0f32, 1f32, 2f32, 3f32, 4f32]);
+    }
+
+
+    #[test]
+    fn checksum() {
+        let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = errors.clone();
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50067"))
+            .checksum(true)
+            .on_recv_error(move |e| {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            })
+            .start::<JSON>()
+            .unwrap();
+
+        let mut frame = net_msg.get_id_by_name("UpdatePos").to_be_bytes().to_vec();
+        frame.extend_from_slice(br#"{"x":15.0,"y":15.0,"z":15.0}"#);
+        let crc = crc32fast::hash(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        //Corrupt a digit of the payload, which still deserializes
+        frame[10] = b'2';
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50068").unwrap();
+        raw.send_to(&frame, "127.0.0.1:50067").unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50067")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(errors.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(net_msg.get_n::<UpdatePos>(2).unwrap().len(), 1);
+    }
+
+
+    #[test]
+    fn raw_callback() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let observed = seen.clone();
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50069"))
+            .on_raw(move |id, _, payload| observed.lock().unwrap().push((id, payload.to_vec())))
+            .start::<JSON>()
+            .unwrap();
+
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50069")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].0, net_msg.get_id_by_name("RenameObj"));
+        assert_eq!(seen[0].1, br#"{"name":"Billy"}"#.to_vec());
+        assert_eq!(net_msg.get::<RenameObj>().unwrap().1.name, "Billy");
+
+        let consumer = Builder::init()
+            .socket(String::from("0.0.0.0:50070"))
+            .on_raw_consume(|id, _, _| id == 4000)
+            .start::<JSON>()
+            .unwrap();
+        consumer.set_id_by_name("status", 4000).unwrap();
+
+        consumer.send_named("status", b"online".to_vec(), String::from("127.0.0.1:50070")).unwrap();
+        let name = RenameObj{name: String::from("Billy")};
+        consumer.send(name, String::from("127.0.0.1:50070")).unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(consumer.get_named("status").is_err());
+        assert!(consumer.get::<RenameObj>().is_ok());
+    }
+
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn circuit_breaker() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50071"))
+            .send_circuit_breaker(3, time::Duration::from_secs(60))
+            .start::<JSON>()
+            .unwrap();
+
+        //Broadcasting without SO_BROADCAST is refused by the OS on every attempt
+        let dest: std::net::SocketAddr = "255.255.255.255:50072".parse().unwrap();
+        for _ in 0..3 {
+            let err = net_msg.send(RenameObj{name: String::from("Billy")}, dest).unwrap_err();
+            assert_ne!(err.kind(), std::io::ErrorKind::BrokenPipe);
+        }
+
+        let err = net_msg.send(RenameObj{name: String::from("Billy")}, dest).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+
+        net_msg.reset_peer(dest);
+        let err = net_msg.send(RenameObj{name: String::from("Billy")}, dest).unwrap_err();
+        assert_ne!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+
+    #[test]
+    fn length_prefix() {
+        use crate::manager::LengthPrefix;
+
+        let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = errors.clone();
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50073"))
+            .length_prefix(LengthPrefix::U16)
+            .on_recv_error(move |_| { counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst); })
+            .start::<JSON>()
+            .unwrap();
+
+        let name = RenameObj{name: String::from("Billy")};
+        net_msg.send(name, String::from("127.0.0.1:50073")).unwrap();
+
+        //Declares one more byte than the payload holds, as if it was truncated
+        let payload = br#"{"name":"Bob"}"#;
+        let mut frame = net_msg.get_id_by_name("RenameObj").to_be_bytes().to_vec();
+        frame.extend_from_slice(&(payload.len() as u16 + 1).to_be_bytes());
+        frame.extend_from_slice(payload);
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50074").unwrap();
+        raw.send_to(&frame, "127.0.0.1:50073").unwrap();
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(errors.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let names = net_msg.get_n::<RenameObj>(2).unwrap();
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[0].1.name, "Billy");
+    }
+
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Throttle {
+        pub value: f32
+    }
+
+    #[test]
+    fn get_or_default() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50075"))
+            .start::<JSON>()
+            .unwrap();
+
+        let started = time::Instant::now();
+        let (addr, throttle) = net_msg.get_or_default::<Throttle>(time::Duration::from_millis(50));
+        assert!(started.elapsed() >= time::Duration::from_millis(50));
+        assert!(addr.is_none());
+        assert_eq!(throttle.value, 0f32);
+
+        net_msg.send(Throttle{value: 0.5}, String::from("127.0.0.1:50075")).unwrap();
+        let (addr, throttle) = net_msg.get_or_default::<Throttle>(time::Duration::from_secs(5));
+        assert!(addr.is_some());
+        assert_eq!(throttle.value, 0.5);
+    }
+
+
+    #[test]
+    fn send_socket() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50076"))
+            .send_socket(String::from("0.0.0.0:50077"))
+            .start::<JSON>()
+            .unwrap();
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50078").unwrap();
+        raw.set_read_timeout(Some(time::Duration::from_millis(200))).unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50078")).unwrap();
+
+        let mut buffer = [0; 100];
+        let (_, source) = raw.recv_from(&mut buffer).unwrap();
+        assert_eq!(source.port(), 50077);
+        assert_eq!(net_msg.local_addrs().unwrap()[0].port(), 50076);
+    }
+
+
+    #[test]
+    fn in_memory_transport() {
+        use crate::transport::InMemoryNetwork;
+
+        let network = InMemoryNetwork::new();
+        let alice_addr: std::net::SocketAddr = "10.0.0.1:1".parse().unwrap();
+        let bob_addr: std::net::SocketAddr = "10.0.0.2:1".parse().unwrap();
+
+        let alice = Builder::init()
+            .transport(Box::new(network.bind(alice_addr)))
+            .start::<JSON>()
+            .unwrap();
+        let bob = Builder::init()
+            .transport(Box::new(network.bind(bob_addr)))
+            .start::<JSON>()
+            .unwrap();
+
+        let received = bob.subscribe::<UpdatePos>();
+        alice.send(UpdatePos{x: 1f32, y: 2f32, z: 3f32}, bob_addr.to_string()).unwrap();
+
+        let (source, pos) = received.recv_timeout(time::Duration::from_secs(5)).unwrap();
+        assert_eq!(source, alice_addr);
+        assert_eq!((pos.x, pos.y, pos.z), (1f32, 2f32, 3f32));
+        assert_eq!(bob.local_addrs().unwrap(), vec![bob_addr]);
+    }
+
+
+    #[test]
+    fn rotate() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50079"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap();
+
+        for name in ["Alice", "Bob", "Carol"].iter() {
+            net_msg.send(RenameObj{name: name.to_string()}, String::from("127.0.0.1:50079")).unwrap();
+            thread::sleep(time::Duration::from_millis(20));
+        }
+        thread::sleep(time::Duration::from_millis(100));
+
+        net_msg.rotate::<RenameObj>().unwrap();
+        let addr: std::net::SocketAddr = "127.0.0.1:50079".parse().unwrap();
+        net_msg.requeue_front(1, addr, b"{\"name\":\"Dave\"}".to_vec());
+
+        let names: Vec<String> = net_msg.get_all::<RenameObj>().unwrap()
+            .into_iter()
+            .map(|(_, obj)| obj.name)
+            .collect();
+        assert_eq!(names, vec!["Dave", "Bob", "Carol", "Alice"]);
+        assert!(net_msg.rotate::<RenameObj>().is_err());
+    }
+
+
+    #[test]
+    fn id_hash_seed() {
+        let first = Builder::init()
+            .socket(String::from("0.0.0.0:50080"))
+            .id_hash_seed((7, 11))
+            .start::<JSON>()
+            .unwrap();
+        let second = Builder::init()
+            .socket(String::from("0.0.0.0:50081"))
+            .id_hash_seed((7, 11))
+            .start::<JSON>()
+            .unwrap();
+        let unseeded = Builder::init()
+            .socket(String::from("0.0.0.0:50082"))
+            .start::<JSON>()
+            .unwrap();
+
+        assert_eq!(first.get_id::<UpdatePos>(), second.get_id::<UpdatePos>());
+        assert_eq!(first.get_id_by_name("UpdatePos"), first.get_id::<UpdatePos>());
+        assert_ne!(first.get_id::<UpdatePos>(), unseeded.get_id::<UpdatePos>());
+
+        first.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50081")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+        second.get::<UpdatePos>().unwrap();
+    }
+
+
+    #[test]
+    fn send_batch() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50083"))
+            .start::<JSON>()
+            .unwrap();
+
+        let peers: Vec<std::net::UdpSocket> = (50084..50087)
+            .map(|port| std::net::UdpSocket::bind(("0.0.0.0", port)).unwrap())
+            .collect();
+
+        let items = vec![
+            (RenameObj{name: String::from("Alice")}, "127.0.0.1:50084"),
+            (RenameObj{name: String::from("Bob")}, "127.0.0.1:50085"),
+            (RenameObj{name: String::from("Carol")}, "127.0.0.1:50086")
+        ];
+        let results = net_msg.send_batch(items);
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        for (peer, name) in peers.iter().zip(["Alice", "Bob", "Carol"].iter()) {
+            peer.set_read_timeout(Some(time::Duration::from_millis(200))).unwrap();
+            let mut buffer = [0; 100];
+            let (len, _) = peer.recv_from(&mut buffer).unwrap();
+            let obj: RenameObj = serde_json::from_slice(&buffer[8..len]).unwrap();
+            assert_eq!(obj.name, *name);
+        }
+    }
+
+
+    #[test]
+    fn get_json() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50087"))
+            .start::<JSON>()
+            .unwrap();
+
+        assert!(net_msg.get_json().is_none());
+
+        net_msg.send(UpdatePos{x: 1f32, y: 2f32, z: 3f32}, String::from("127.0.0.1:50087")).unwrap();
+        thread::sleep(time::Duration::from_millis(20));
+        net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50087")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (_, pos) = net_msg.get_json().unwrap();
+        assert_eq!(pos["y"], 2.0);
+        let (_, name) = net_msg.get_json().unwrap();
+        assert_eq!(name["name"], "Billy");
+        assert!(net_msg.get_json().is_none());
+    }
+
+
+    struct FlakyTransport {
+        failures: u32,
+        kind: std::io::ErrorKind,
+        attempts: std::sync::Arc<std::sync::atomic::AtomicU32>
+    }
+
+    impl crate::transport::Transport for FlakyTransport {
+        fn send_to(&self, buf: &[u8], _addr: std::net::SocketAddr) -> Result<usize, std::io::Error> {
+            let attempt = self.attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if attempt < self.failures {
+                return Err(std::io::Error::from(self.kind));
+            }
+            return Ok(buf.len());
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, std::net::SocketAddr), std::io::Error> {
+            return Err(std::io::Error::from(std::io::ErrorKind::WouldBlock));
+        }
+
+        fn local_addr(&self) -> Result<std::net::SocketAddr, std::io::Error> {
+            return Ok("10.0.0.1:1".parse().unwrap());
+        }
+
+        fn set_read_timeout(&self, _timeout: Option<time::Duration>) -> Result<(), std::io::Error> {
+            return Ok(());
+        }
+
+        fn set_nonblocking(&self, _nonblocking: bool) -> Result<(), std::io::Error> {
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn send_retries() {
+        let send = |failures: u32, kind: std::io::ErrorKind| {
+            let attempts = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+            let net_msg = Builder::init()
+                .transport(Box::new(FlakyTransport{failures, kind, attempts: attempts.clone()}))
+                .send_retries(3, time::Duration::from_millis(1))
+                .start::<JSON>()
+                .unwrap();
+
+            let result = net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("10.0.0.2:1"));
+            return (result, attempts.load(std::sync::atomic::Ordering::SeqCst));
+        };
+
+        let (result, attempts) = send(2, std::io::ErrorKind::WouldBlock);
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+
+        let (result, attempts) = send(10, std::io::ErrorKind::Interrupted);
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::Interrupted);
+        assert_eq!(attempts, 4);
+
+        let (result, attempts) = send(10, std::io::ErrorKind::PermissionDenied);
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(attempts, 1);
+    }
+
+
+    #[test]
+    fn uses_ids() {
+        let with_ids = Builder::init()
+            .socket(String::from("0.0.0.0:50088"))
+            .start::<JSON>()
+            .unwrap();
+        let without_ids = Builder::init()
+            .socket(String::from("0.0.0.0:50089"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap();
+
+        assert!(with_ids.uses_ids());
+        assert!(!without_ids.uses_ids());
+    }
+
+
+    #[test]
+    fn take_queue() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50090"))
+            .start::<JSON>()
+            .unwrap();
+
+        assert!(net_msg.take_queue::<UpdatePos>().is_empty());
+
+        for i in 0..3 {
+            net_msg.send(UpdatePos{x: i as f32, y: 0f32, z: 0f32}, String::from("127.0.0.1:50090")).unwrap();
+            thread::sleep(time::Duration::from_millis(20));
+        }
+        thread::sleep(time::Duration::from_millis(100));
+
+        let backlog = net_msg.take_queue::<UpdatePos>();
+        assert_eq!(backlog.len(), 3);
+        for (i, (_, payload)) in backlog.iter().enumerate() {
+            let pos = net_msg.decode_payload::<UpdatePos>(payload).unwrap();
+            assert_eq!(pos.x, i as f32);
+        }
+        assert!(net_msg.get::<UpdatePos>().is_err());
+    }
+
+
+    #[test]
+    fn bind_error_names_address() {
+        use crate::manager::BuilderError;
+
+        let _net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50091"))
+            .start::<JSON>()
+            .unwrap();
+
+        let e = Builder::init()
+            .socket(String::from("0.0.0.0:50091"))
+            .start::<JSON>()
+            .unwrap_err();
+
+        assert_eq!(e.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(e.to_string().contains("0.0.0.0:50091"));
+        match e.get_ref().and_then(|inner| inner.downcast_ref::<BuilderError>()) {
+            Some(BuilderError::Bind { addr, .. }) => assert_eq!(addr, "0.0.0.0:50091"),
+            _ => panic!("expected a bind error")
+        }
+    }
+
+
+    #[test]
+    fn recv_into() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50092"))
+            .read_timeout(Some(time::Duration::from_millis(200)))
+            .manual_poll(true)
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50092")).unwrap();
+
+        let mut buffer = [0; 100];
+        let (len, addr) = net_msg.recv_into(&mut buffer).unwrap();
+        assert_eq!(addr.port(), 50092);
+        assert_eq!(buffer[..8], net_msg.get_id::<RenameObj>().to_be_bytes());
+        let obj = net_msg.decode_payload::<RenameObj>(&buffer[8..len]).unwrap();
+        assert_eq!(obj.name, "Billy");
+        assert!(net_msg.get::<RenameObj>().is_err());
+    }
+
+
+    #[test]
+    fn poll_once() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50093"))
+            .read_timeout(Some(time::Duration::from_millis(200)))
+            .manual_poll(true)
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send(UpdatePos{x: 1f32, y: 2f32, z: 3f32}, String::from("127.0.0.1:50093")).unwrap();
+        assert!(net_msg.get::<UpdatePos>().is_err());
+
+        assert!(net_msg.poll_once().unwrap());
+        let (_, pos) = net_msg.get::<UpdatePos>().unwrap();
+        assert_eq!(pos.z, 3f32);
+        assert!(!net_msg.poll_once().unwrap());
+    }
+
+
+    #[test]
+    fn max_queue_len() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50094"))
+            .max_queue_len(2)
+            .start::<JSON>()
+            .unwrap();
+
+        for i in 0..3 {
+            net_msg.send(UpdatePos{x: i as f32, y: 0f32, z: 0f32}, String::from("127.0.0.1:50094")).unwrap();
+            thread::sleep(time::Duration::from_millis(20));
+        }
+        thread::sleep(time::Duration::from_millis(100));
+
+        let xs: Vec<f32> = net_msg.get_all::<UpdatePos>().unwrap().iter().map(|(_, pos)| pos.x).collect();
+        assert_eq!(xs, vec![1f32, 2f32]);
+    }
+
+    #[test]
+    fn block_send_when_full() {
+        let net_msg = std::sync::Arc::new(Builder::init()
+            .socket(String::from("0.0.0.0:50095"))
+            .max_queue_len(1)
+            .block_send_when_full(true)
+            .start::<JSON>()
+            .unwrap());
+
+        net_msg.send(UpdatePos{x: 1f32, y: 0f32, z: 0f32}, String::from("127.0.0.1:50095")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let consumer = net_msg.clone();
+        let consumer = thread::spawn(move || {
+            thread::sleep(time::Duration::from_millis(200));
+            return consumer.get::<UpdatePos>().unwrap().1.x;
+        });
+
+        let started = time::Instant::now();
+        net_msg.send(UpdatePos{x: 2f32, y: 0f32, z: 0f32}, String::from("127.0.0.1:50095")).unwrap();
+        assert!(started.elapsed() >= time::Duration::from_millis(150));
+        assert_eq!(consumer.join().unwrap(), 1f32);
+
+        thread::sleep(time::Duration::from_millis(100));
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 2f32);
+    }
+
+
+    #[test]
+    fn encode() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50096"))
+            .checksum(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50097").unwrap();
+        raw.set_read_timeout(Some(time::Duration::from_millis(200))).unwrap();
+
+        let pos = UpdatePos{x: 4f32, y: 5f32, z: 6f32};
+        let encoded = net_msg.encode(&pos).unwrap();
+        net_msg.send(pos, String::from("127.0.0.1:50097")).unwrap();
+
+        let mut buffer = [0; 100];
+        let (len, _) = raw.recv_from(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], &encoded[..]);
+    }
+
+
+    #[test]
+    fn decode() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50098"))
+            .compression_flag(true)
+            .length_prefix(crate::manager::LengthPrefix::U16)
+            .checksum(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let bytes = net_msg.encode(&RenameObj{name: String::from("Billy")}).unwrap();
+        let (id, obj) = net_msg.decode::<RenameObj>(&bytes).unwrap();
+        assert_eq!(id, net_msg.get_id::<RenameObj>());
+        assert_eq!(obj.name, "Billy");
+
+        let mut corrupted = bytes.clone();
+        corrupted[9] ^= 1;
+        assert_eq!(net_msg.decode::<RenameObj>(&corrupted).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+
+    #[test]
+    fn recv_one() {
+        let client = Builder::init()
+            .socket(String::from("0.0.0.0:50099"))
+            .manual_poll(true)
+            .start::<JSON>()
+            .unwrap();
+        let server = Builder::init()
+            .socket(String::from("0.0.0.0:50100"))
+            .start::<JSON>()
+            .unwrap();
+
+        let deadline = time::Instant::now() + time::Duration::from_millis(100);
+        assert_eq!(client.recv_one::<UpdatePos>(deadline).err().unwrap().kind(), std::io::ErrorKind::TimedOut);
+
+        server.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50099")).unwrap();
+        server.send(UpdatePos{x: 1f32, y: 2f32, z: 3f32}, String::from("127.0.0.1:50099")).unwrap();
+
+        let deadline = time::Instant::now() + time::Duration::from_secs(5);
+        let (addr, pos) = client.recv_one::<UpdatePos>(deadline).unwrap();
+        assert_eq!(addr.port(), 50100);
+        assert_eq!(pos.y, 2f32);
+    }
+
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reuse_port() {
+        let first = Builder::init()
+            .socket(String::from("0.0.0.0:50101"))
+            .reuse_port(true)
+            .start::<JSON>()
+            .unwrap();
+        let second = Builder::init()
+            .socket(String::from("0.0.0.0:50101"))
+            .reuse_port(true)
+            .start::<JSON>()
+            .unwrap();
+
+        //The kernel hashes each flow to one socket, so the datagrams come from many source ports
+        let mut bytes = first.get_id::<UpdatePos>().to_be_bytes().to_vec();
+        bytes.extend_from_slice(br#"{"x":1.0,"y":1.0,"z":1.0}"#);
+        for _ in 0..20 {
+            let raw = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            raw.send_to(&bytes, "127.0.0.1:50101").unwrap();
+        }
+
+        thread::sleep(time::Duration::from_millis(200));
+
+        let received = first.get_all::<UpdatePos>().map_or(0, |all| all.len()) 
+            + second.get_all::<UpdatePos>().map_or(0, |all| all.len());
+        assert_eq!(received, 20);
+    }
+
+
+    #[test]
+    fn get_highest_priority() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50102"))
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.set_priority::<UpdatePos>(10);
+
+        net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50102")).unwrap();
+        thread::sleep(time::Duration::from_millis(20));
+        net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50102")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (id, _, _) = net_msg.get_highest_priority().unwrap();
+        assert_eq!(id, net_msg.get_id::<UpdatePos>());
+        let (id, _, payload) = net_msg.get_highest_priority().unwrap();
+        assert_eq!(id, net_msg.get_id::<RenameObj>());
+        assert_eq!(net_msg.decode_payload::<RenameObj>(&payload).unwrap().name, "Billy");
+        assert!(net_msg.get_highest_priority().is_none());
+    }
+
+
+    #[test]
+    fn buffer_too_small() {
+        use crate::manager::BuilderError;
+
+        let e = Builder::init()
+            .socket(String::from("0.0.0.0:50103"))
+            .buffer_len(4)
+            .start::<JSON>()
+            .unwrap_err();
+
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        match e.get_ref().and_then(|inner| inner.downcast_ref::<BuilderError>()) {
+            Some(BuilderError::BufferTooSmall { buffer_len, required }) => assert_eq!((*buffer_len, *required), (4, 8)),
+            _ => panic!("expected a buffer too small error")
+        }
+
+        Builder::init()
+            .socket(String::from("0.0.0.0:50103"))
+            .buffer_len(4)
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap();
+    }
+
+
+    #[test]
+    fn drop_stats() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50104"))
+            .checksum(true)
+            .max_queue_len(1)
+            .on_recv_error(|_| {})
+            .start::<JSON>()
+            .unwrap();
+
+        let mut frame = net_msg.get_id::<UpdatePos>().to_be_bytes().to_vec();
+        frame.extend_from_slice(b"not json");
+        let crc = crc32fast::hash(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50105").unwrap();
+        raw.send_to(&frame, "127.0.0.1:50104").unwrap();
+        frame[9] ^= 1;
+        raw.send_to(&frame, "127.0.0.1:50104").unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(net_msg.get::<UpdatePos>().is_err());
+
+        for _ in 0..2 {
+            net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50104")).unwrap();
+            thread::sleep(time::Duration::from_millis(20));
+        }
+        thread::sleep(time::Duration::from_millis(100));
+
+        let stats = net_msg.drop_stats();
+        assert_eq!(stats.checksum, 1);
+        assert_eq!(stats.deserialize, 1);
+        assert_eq!(stats.queue_full, 1);
+        assert_eq!(stats.truncated, 0);
+
+        net_msg.reset_drop_stats();
+        assert_eq!(net_msg.drop_stats(), crate::manager::DropStats::default());
+    }
+
+
+    #[test]
+    fn mock_clock() {
+        use crate::clock::MockClock;
+        use crate::transport::InMemoryNetwork;
+
+        let network = InMemoryNetwork::new();
+        let addr: std::net::SocketAddr = "10.0.0.1:1".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new());
+
+        let net_msg = Builder::init()
+            .transport(Box::new(network.bind(addr)))
+            .manual_poll(true)
+            .message_ttl(time::Duration::from_secs(10))
+            .clock(clock.clone())
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, addr).unwrap();
+        assert!(net_msg.poll_once().unwrap());
+
+        clock.advance(time::Duration::from_secs(5));
+        assert!(net_msg.peek::<UpdatePos>().is_ok());
+
+        clock.advance(time::Duration::from_secs(6));
+        assert!(net_msg.get::<UpdatePos>().is_err());
+        assert_eq!(net_msg.drop_stats().expired, 1);
+    }
+
+
+    #[test]
+    fn dead_letters() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50106"))
+            .use_ids(false)
+            .capture_dead_letters(true)
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50106")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(net_msg.get::<UpdatePos>().is_err());
+
+        let dead_letters = net_msg.dead_letters();
+        assert_eq!(dead_letters.len(), 1);
+        let obj = net_msg.decode_payload::<RenameObj>(&dead_letters[0].1).unwrap();
+        assert_eq!(obj.name, "Billy");
+        assert_eq!(net_msg.drop_stats().deserialize, 0);
+
+        net_msg.clear_dead_letters();
+        assert!(net_msg.dead_letters().is_empty());
+    }
+
+
+    #[test]
+    fn peek_from() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50107"))
+            .start::<JSON>()
+            .unwrap();
+        let alice = Builder::init()
+            .socket(String::from("0.0.0.0:50108"))
+            .start::<JSON>()
+            .unwrap();
+        let bob = Builder::init()
+            .socket(String::from("0.0.0.0:50109"))
+            .start::<JSON>()
+            .unwrap();
+
+        alice.send(RenameObj{name: String::from("Alice")}, String::from("127.0.0.1:50107")).unwrap();
+        thread::sleep(time::Duration::from_millis(20));
+        bob.send(RenameObj{name: String::from("Bob")}, String::from("127.0.0.1:50107")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let bob_addr: std::net::SocketAddr = "127.0.0.1:50109".parse().unwrap();
+        assert_eq!(net_msg.peek_from::<RenameObj>(bob_addr).unwrap().name, "Bob");
+        assert_eq!(net_msg.peek_from::<RenameObj>(bob_addr).unwrap().name, "Bob");
+        assert!(net_msg.peek_from::<RenameObj>("127.0.0.1:50110".parse().unwrap()).is_err());
+
+        assert_eq!(net_msg.get_all::<RenameObj>().unwrap().len(), 2);
+    }
+
+
+    #[test]
+    fn shutdown() {
+        let mut net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50111"))
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.shutdown().unwrap();
+        let e = net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50111")).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::NotConnected);
+
+        let rebound = Builder::init()
+            .socket(String::from("0.0.0.0:50111"))
+            .start::<JSON>()
+            .unwrap();
+        rebound.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50111")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+        rebound.get::<UpdatePos>().unwrap();
+    }
+
+
+
+    #[test]
+    fn batch_recv() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50112"))
+            .batch_recv(32)
+            .start::<JSON>()
+            .unwrap();
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50113"))
+            .start::<JSON>()
+            .unwrap();
+
+        for i in 0..200 {
+            sender.send(UpdatePos{x: i as f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50112")).unwrap();
+        }
+        thread::sleep(time::Duration::from_millis(200));
+
+        let received = net_msg.get_all::<UpdatePos>().unwrap();
+        assert_eq!(received.len(), 200);
+        assert_eq!(received[199].1.x, 199f32);
+    }
+
+
+
+    #[test]
+    #[cfg(feature = "bincode")]
+    fn into_format() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50114"))
+            .preserve_global_order(true)
+            .start::<JSON>()
+            .unwrap();
+        let json_sender = Builder::init()
+            .socket(String::from("0.0.0.0:50115"))
+            .start::<JSON>()
+            .unwrap();
+
+        json_sender.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50114")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let net_msg = net_msg.into_format::<Bincode>();
+        assert_eq!(net_msg.local_addrs().unwrap()[0].port(), 50114);
+        assert_eq!(net_msg.format_name(), "bincode");
+
+        let (_, _, queued) = net_msg.get_next_any().unwrap();
+        assert_eq!(json_sender.decode_payload::<UpdatePos>(&queued).unwrap().x, 1f32);
+
+        let bincode_sender = json_sender.into_format::<Bincode>();
+        bincode_sender.send(UpdatePos{x: 2f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50114")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 2f32);
+    }
+
+
+
+    #[test]
+    fn on_unknown() {
+        let unknown = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = unknown.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50116"))
+            .on_unknown(move |id, _, payload| seen.lock().unwrap().push((id, payload)))
+            .start::<JSON>()
+            .unwrap();
+        net_msg.get_id::<UpdatePos>();
+
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50117"))
+            .start::<JSON>()
+            .unwrap();
+        sender.set_id::<RenameObj>(4000).unwrap();
+        sender.send(RenameObj{name: String::from("Gateway")}, String::from("127.0.0.1:50116")).unwrap();
+        sender.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50116")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let unknown = unknown.lock().unwrap();
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].0, 4000);
+        assert_eq!(sender.decode_payload::<RenameObj>(&unknown[0].1).unwrap().name, "Gateway");
+        net_msg.get::<UpdatePos>().unwrap();
+    }
+
+
+
+    #[test]
+    fn send_reliable() {
+        let dropped = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let drops = dropped.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50118"))
+            .auto_ack(true)
+            .on_raw_consume(move |_, _, _| drops.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 1)
+            .start::<JSON>()
+            .unwrap();
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50119"))
+            .start::<JSON>()
+            .unwrap();
+        let timeout = time::Duration::from_millis(100);
+
+        sender.send_reliable(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50118"), timeout, 0).unwrap();
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 1f32);
+
+        //The first attempt of the second datagram is consumed before it can be acknowledged
+        sender.send_reliable(UpdatePos{x: 2f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50118"), timeout, 2).unwrap();
+        assert_eq!(dropped.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 2f32);
+
+        let e = sender.send_reliable(UpdatePos{x: 3f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50120"), timeout, 1).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+
+
+    #[test]
+    fn socket() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50121"))
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.socket().unwrap().set_ttl(7).unwrap();
+        assert_eq!(net_msg.socket().unwrap().ttl().unwrap(), 7);
+
+        let in_memory = Builder::init()
+            .transport(Box::new(crate::transport::InMemoryNetwork::new().bind("10.0.0.1:1".parse().unwrap())))
+            .start::<JSON>()
+            .unwrap();
+        assert!(in_memory.socket().is_none());
+    }
+
+
+
+    #[test]
+    fn envelope() {
+        use crate::manager::{EnvelopeMeta, EnvelopeSpec};
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50122"))
+            .envelope(EnvelopeSpec{tick: true, channel: true})
+            .compression_flag(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let meta = EnvelopeMeta{tick: 70000, channel: 3};
+        net_msg.send_env(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50122"), meta).unwrap();
+        net_msg.send(UpdatePos{x: 2f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50122")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (_, received, pos) = net_msg.get_env::<UpdatePos>().unwrap();
+        assert_eq!(received, meta);
+        assert_eq!(pos.x, 1f32);
+
+        let (_, received, pos) = net_msg.get_env::<UpdatePos>().unwrap();
+        assert_eq!(received, EnvelopeMeta::default());
+        assert_eq!(pos.x, 2f32);
+    }
+
+
+
+    #[test]
+    fn get_retains_on_error() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50123"))
+            .use_ids(false)
+            .get_retains_on_error(true)
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send(RenameObj{name: String::from("Retained")}, String::from("127.0.0.1:50123")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(net_msg.get::<UpdatePos>().is_err());
+        assert!(net_msg.get_all::<UpdatePos>().unwrap().is_empty());
+        assert_eq!(net_msg.get::<RenameObj>().unwrap().1.name, "Retained");
+        assert!(net_msg.get::<RenameObj>().is_err());
+    }
+
+
+
+    #[test]
+    fn send_with_origin() {
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50124"))
+            .start::<JSON>()
+            .unwrap();
+        let relay = Builder::init()
+            .socket(String::from("0.0.0.0:50125"))
+            .start::<JSON>()
+            .unwrap();
+        let recipient = Builder::init()
+            .socket(String::from("0.0.0.0:50126"))
+            .start::<JSON>()
+            .unwrap();
+
+        sender.send(RenameObj{name: String::from("Relayed")}, String::from("127.0.0.1:50125")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (hop, origin, obj) = relay.get_with_origin::<RenameObj>().unwrap();
+        assert_eq!(hop, origin);
+        assert_eq!(origin.port(), 50124);
+        relay.send_with_origin(obj, origin, String::from("127.0.0.1:50126")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let (hop, origin, obj) = recipient.get_with_origin::<RenameObj>().unwrap();
+        assert_eq!(hop.port(), 50125);
+        assert_eq!(origin, "127.0.0.1:50124".parse().unwrap());
+        assert_eq!(obj.name, "Relayed");
+    }
+
+
+
+    #[test]
+    fn resolve() {
+        use crate::manager::{BuilderError, ResolveMode};
+
+        let net_msg = Builder::init()
+            .socket(String::from("localhost:50127"))
+            .resolve(ResolveMode::Ipv4Only)
+            .start::<JSON>()
+            .unwrap();
+        assert!(net_msg.local_addrs().unwrap()[0].is_ipv4());
+
+        let e = Builder::init()
+            .socket(String::from("127.0.0.1:50128"))
+            .resolve(ResolveMode::Ipv6Only)
+            .start::<JSON>()
+            .unwrap_err();
+        match e.get_ref().and_then(|inner| inner.downcast_ref::<BuilderError>()) {
+            Some(BuilderError::Resolve { addr, .. }) => assert_eq!(addr, "127.0.0.1:50128"),
+            _ => panic!("expected a resolve error")
+        }
+    }
+
+
+
+    #[test]
+    fn peek_raw() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50129"))
+            .preserve_global_order(true)
+            .start::<JSON>()
+            .unwrap();
+        assert!(net_msg.peek_raw().is_none());
+
+        net_msg.send(RenameObj{name: String::from("First")}, String::from("127.0.0.1:50129")).unwrap();
+        thread::sleep(time::Duration::from_millis(20));
+        net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50129")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let peeked = net_msg.peek_raw().unwrap();
+        assert_eq!(peeked.0, net_msg.get_id::<RenameObj>());
+        assert_eq!(net_msg.decode_payload::<RenameObj>(&peeked.2).unwrap().name, "First");
+        assert_eq!(net_msg.get_next_any().unwrap(), peeked);
+        assert_eq!(net_msg.peek_raw().unwrap().0, net_msg.get_id::<UpdatePos>());
+    }
+
+    #[test]
+    fn queue_depths() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50130"))
+            .start::<JSON>()
+            .unwrap();
+        assert!(net_msg.queue_depths().is_empty());
+
+        for _ in 0..3 {
+            net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50130")).unwrap();
+        }
+        net_msg.send(RenameObj{name: String::from("Depth")}, String::from("127.0.0.1:50130")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let depths = net_msg.queue_depths();
+        assert_eq!(depths.len(), 2);
+        assert!(depths.contains(&(net_msg.get_id::<UpdatePos>(), 3)));
+        assert!(depths.contains(&(net_msg.get_id::<RenameObj>(), 1)));
+    }
+
+    #[test]
+    fn start_shared() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50131"))
+            .start_shared::<JSON>()
+            .unwrap();
+
+        let senders: Vec<_> = (0..3).map(|i| {
+            let net_msg = net_msg.clone();
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    let pos = UpdatePos{x: i as f32, y: 0f32, z: 0f32};
+                    net_msg.send(pos, String::from("127.0.0.1:50131")).unwrap();
+                }
+            })
+        }).collect();
+
+        let getters: Vec<_> = (0..3).map(|_| {
+            let net_msg = net_msg.clone();
+            thread::spawn(move || {
+                let mut received = 0;
+                let started = time::Instant::now();
+                while received < 10 && started.elapsed() < time::Duration::from_secs(2) {
+                    if net_msg.get::<UpdatePos>().is_ok() {
+                        received += 1;
+                    }
+                }
+                return received;
+            })
+        }).collect();
+
+        for sender in senders {
+            sender.join().unwrap();
+        }
+        let received: usize = getters.into_iter().map(|getter| getter.join().unwrap()).sum();
+
+        assert_eq!(received, 30);
+        assert!(net_msg.get::<UpdatePos>().is_err());
+    }
+
+    #[test]
+    fn max_queues() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50132"))
+            .max_queues(5)
+            .start::<JSON>()
+            .unwrap();
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50133").unwrap();
+        for id in 1000u64..1050 {
+            let mut frame = id.to_be_bytes().to_vec();
+            frame.extend_from_slice(b"{}");
+            raw.send_to(&frame, "127.0.0.1:50132").unwrap();
+            assert!(net_msg.queue_depths().len() <= 5);
+        }
+        raw.send_to(&1000u64.to_be_bytes(), "127.0.0.1:50132").unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert_eq!(net_msg.queue_depths().len(), 5);
+        assert_eq!(net_msg.queue_depths().iter().map(|(_, depth)| depth).sum::<usize>(), 6);
+        assert_eq!(net_msg.drop_stats().too_many_queues, 45);
+    }
+
+    #[test]
+    fn sync() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50134"))
+            .start::<JSON>()
+            .unwrap();
+
+        for i in 0..20 {
+            net_msg.send(UpdatePos{x: i as f32, y: 0f32, z: 0f32}, String::from("127.0.0.1:50134")).unwrap();
+        }
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+        assert_eq!(net_msg.get_all::<UpdatePos>().unwrap().len(), 20);
+
+        let manual = Builder::init()
+            .socket(String::from("0.0.0.0:50135"))
+            .manual_poll(true)
+            .start::<JSON>()
+            .unwrap();
+        assert!(!manual.sync(time::Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn tag_in_body() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50136"))
+            .tag_in_body(String::from("type"))
+            .start::<JSON>()
+            .unwrap();
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50137").unwrap();
+        raw.set_read_timeout(Some(time::Duration::from_secs(1))).unwrap();
+        net_msg.send(RenameObj{name: String::from("Tagged")}, String::from("127.0.0.1:50137")).unwrap();
+        let mut buffer = [0; 100];
+        let (len, _) = raw.recv_from(&mut buffer).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&buffer[..len]).unwrap();
+        assert_eq!(body["type"], "RenameObj");
+        assert_eq!(body["name"], "Tagged");
+
+        raw.send_to(br#"{"type":"UpdatePos","x":1.0,"y":2.0,"z":3.0}"#, "127.0.0.1:50136").unwrap();
+        raw.send_to(br#"{"name":"Untagged"}"#, "127.0.0.1:50136").unwrap();
+        net_msg.send(RenameObj{name: String::from("Round trip")}, String::from("127.0.0.1:50136")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.y, 2f32);
+        assert_eq!(net_msg.get::<RenameObj>().unwrap().1.name, "Round trip");
+        assert!(net_msg.get::<RenameObj>().is_err());
+        assert_eq!(net_msg.drop_stats().malformed, 1);
+    }
+
+    struct StuckTransport;
+
+    impl crate::transport::Transport for StuckTransport {
+        fn send_to(&self, buf: &[u8], _addr: std::net::SocketAddr) -> Result<usize, std::io::Error> {
+            return Ok(buf.len());
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, std::net::SocketAddr), std::io::Error> {
+            thread::sleep(time::Duration::from_secs(5));
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        }
+
+        fn local_addr(&self) -> Result<std::net::SocketAddr, std::io::Error> {
+            return Ok("10.0.0.1:1".parse().unwrap());
+        }
+
+        fn set_read_timeout(&self, _timeout: Option<time::Duration>) -> Result<(), std::io::Error> {
+            return Ok(());
+        }
+
+        fn set_nonblocking(&self, _nonblocking: bool) -> Result<(), std::io::Error> {
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn shutdown_timeout() {
+        let (dropped, done) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let net_msg = Builder::init()
+                .socket(String::from("0.0.0.0:50138"))
+                .non_blocking(false)
+                .start::<JSON>()
+                .unwrap();
+            thread::sleep(time::Duration::from_millis(50));
+            drop(net_msg);
+            dropped.send(()).unwrap();
+        });
+        done.recv_timeout(time::Duration::from_secs(2)).unwrap();
+
+        let net_msg = Builder::init()
+            .transport(Box::new(StuckTransport))
+            .non_blocking(false)
+            .shutdown_timeout(time::Duration::from_millis(100))
+            .start::<JSON>()
+            .unwrap();
+        thread::sleep(time::Duration::from_millis(50));
+
+        let started = time::Instant::now();
+        drop(net_msg);
+        assert!(started.elapsed() < time::Duration::from_secs(1));
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn loopback_pair() {
+        let (client, server) = crate::testing::loopback_pair::<JSON>();
+        let client_addr = client.local_addrs().unwrap()[0];
+        let server_addr = server.local_addrs().unwrap()[0];
+        assert_eq!(client.socket().unwrap().peer_addr().unwrap(), server_addr);
+        assert_eq!(server.socket().unwrap().peer_addr().unwrap(), client_addr);
+
+        client.send(UpdatePos{x: 1f32, y: 2f32, z: 3f32}, server_addr).unwrap();
+        assert!(server.sync(time::Duration::from_secs(1)));
+        let (from, pos) = server.get::<UpdatePos>().unwrap();
+        assert_eq!(from, client_addr);
+        assert_eq!(pos.z, 3f32);
+
+        server.send(RenameObj{name: String::from("Reply")}, from).unwrap();
+        assert!(client.sync(time::Duration::from_secs(1)));
+        let (from, rename) = client.get::<RenameObj>().unwrap();
+        assert_eq!(from, server_addr);
+        assert_eq!(rename.name, "Reply");
+    }
+
+    #[test]
+    fn get_from() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50139"))
+            .start::<JSON>()
+            .unwrap();
+        let senders: Vec<_> = ["0.0.0.0:50140", "0.0.0.0:50141"].iter().map(|addr| Builder::init()
+            .socket(String::from(*addr))
+            .start::<JSON>()
+            .unwrap()).collect();
+
+        for i in 0..5 {
+            for (sender, offset) in senders.iter().zip([0f32, 100f32]) {
+                sender.send(UpdatePos{x: offset + i as f32, y: 0f32, z: 0f32}, String::from("127.0.0.1:50139")).unwrap();
+            }
+        }
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let first: std::net::SocketAddr = "127.0.0.1:50140".parse().unwrap();
+        let second: std::net::SocketAddr = "127.0.0.1:50141".parse().unwrap();
+        for i in 0..5 {
+            assert_eq!(net_msg.get_from::<UpdatePos>(second).unwrap().x, 100f32 + i as f32);
+            assert_eq!(net_msg.get_from::<UpdatePos>(first).unwrap().x, i as f32);
+        }
+        assert!(net_msg.get_from::<UpdatePos>(first).is_err());
+        assert!(net_msg.get::<UpdatePos>().is_err());
+    }
+
+    #[test]
+    fn idle_sleep() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50142"))
+            .idle_sleep(time::Duration::from_millis(50))
+            .start::<JSON>()
+            .unwrap();
+
+        //Two idle passes are needed, at least one of which sleeps in full
+        let started = time::Instant::now();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+        assert!(started.elapsed() >= time::Duration::from_millis(40));
+
+        let started = time::Instant::now();
+        net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50142")).unwrap();
+        while net_msg.get::<UpdatePos>().is_err() {
+            assert!(started.elapsed() < time::Duration::from_millis(500));
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        assert!(started.elapsed() < time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn send_into() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50143"))
+            .length_prefix(crate::manager::LengthPrefix::U16)
+            .checksum(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let mut scratch = Vec::with_capacity(256);
+        let buffer = scratch.as_ptr();
+        for i in 0..50 {
+            let pos = UpdatePos{x: i as f32, y: 0f32, z: 0f32};
+            net_msg.send_into(&pos, String::from("127.0.0.1:50143"), &mut scratch).unwrap();
+            assert_eq!(scratch, net_msg.encode(&pos).unwrap());
+        }
+        assert_eq!(scratch.as_ptr(), buffer);
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let xs: Vec<f32> = net_msg.get_all::<UpdatePos>().unwrap().iter().map(|(_, pos)| pos.x).collect();
+        assert_eq!(xs, (0..50).map(|i| i as f32).collect::<Vec<_>>());
+    }
+
+    struct PanicsOnDecode;
+
+    impl<'de> Deserialize<'de> for PanicsOnDecode {
+        fn deserialize<D>(_deserializer: D) -> Result<Self, D::Error> 
+            where D: serde::Deserializer<'de>
+        {
+            panic!("Deserialize implementation panicked");
+        }
+    }
+
+    #[test]
+    fn was_poisoned() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50144"))
+            .start::<JSON>()
+            .unwrap();
+        assert!(!net_msg.was_poisoned());
+
+        net_msg.forward(net_msg.get_id::<PanicsOnDecode>(), b"{}", String::from("127.0.0.1:50144")).unwrap();
+        net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50144")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| net_msg.get::<PanicsOnDecode>()));
+        assert!(panicked.is_err());
+        assert!(net_msg.was_poisoned());
+
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.x, 1f32);
+        assert!(net_msg.was_poisoned());
+
+        net_msg.clear_poison();
+        assert!(!net_msg.was_poisoned());
+    }
+
+    #[test]
+    fn protocol_version() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50145"))
+            .protocol_version(2)
+            .on_recv_error(|_| {})
+            .start::<JSON>()
+            .unwrap();
+        let old = Builder::init()
+            .socket(String::from("0.0.0.0:50146"))
+            .protocol_version(1)
+            .start::<JSON>()
+            .unwrap();
+
+        assert_eq!(net_msg.encode(&UpdatePos{x: 1f32, y: 1f32, z: 1f32}).unwrap()[0], 2);
+        old.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50145")).unwrap();
+        net_msg.send(UpdatePos{x: 2f32, y: 2f32, z: 2f32}, String::from("127.0.0.1:50145")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        assert_eq!(net_msg.get_all::<UpdatePos>().unwrap().len(), 1);
+        assert_eq!(net_msg.drop_stats().version, 1);
+
+        let mismatched = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = mismatched.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50147"))
+            .protocol_version(2)
+            .on_version_mismatch(move |addr, datagram| seen.lock().unwrap().push((addr, datagram)))
+            .start::<JSON>()
+            .unwrap();
+        old.send(RenameObj{name: String::from("Old")}, String::from("127.0.0.1:50147")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let mismatched = mismatched.lock().unwrap();
+        assert_eq!(mismatched.len(), 1);
+        assert_eq!(mismatched[0].0.port(), 50146);
+        assert_eq!(mismatched[0].1[0], 1);
+        assert!(net_msg.get::<RenameObj>().is_err());
+        assert_eq!(net_msg.drop_stats().version, 1);
+    }
+
+    #[test]
+    fn get_with_ttl() {
+        let net_msg = Builder::init()
+            .socket(String::from("127.0.0.1:50148"))
+            .recv_ttl(true)
+            .start::<JSON>()
+            .unwrap();
+        net_msg.socket().unwrap().set_ttl(17).unwrap();
+
+        net_msg.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50148")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let (_, ttl, pos) = net_msg.get_with_ttl::<UpdatePos>().unwrap();
+        assert_eq!(pos.x, 1f32);
+        if cfg!(target_os = "linux") {
+            //Loopback traffic crosses no router, so the TTL arrives as it was sent
+            assert_eq!(ttl, Some(17));
+        }
+        else {
+            assert_eq!(ttl, None);
+        }
+    }
+
+    #[test]
+    fn enum_dispatch_with_addr() {
+        use crate::manager::MessageRegistry;
+
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50149"))
+            .start::<JSON>()
+            .unwrap();
+
+        let mut registry = MessageRegistry::new();
+        registry.register(|pos: UpdatePos| format!("{}", pos.x));
+        registry.register_with_addr(|addr: std::net::SocketAddr, name: RenameObj| format!("{} from {}", name.name, addr.port()));
+        registry.register(|name: RenameObj| name.name);
+
+        net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50149")).unwrap();
+        net_msg.send(UpdatePos{x: 15f32, y: 15f32, z: 15f32}, String::from("127.0.0.1:50149")).unwrap();
+        net_msg.send(RenameObj{name: String::from("Bob")}, String::from("127.0.0.1:50149")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let mut order = vec![];
+        while let Some((_, msg)) = net_msg.get_enum(&registry) {
+            order.push(msg);
+        }
+        assert_eq!(order, vec!["Billy from 50149", "15", "Bob from 50149"]);
+    }
+
+    #[test]
+    fn framing_round_trip() {
+        use crate::framing::{Endian, WriteNum, ReadNum, WriteString, ReadString};
+        use std::io::ErrorKind;
+
+        let mut buf = Vec::new();
+        buf.write_num(0x0102u16, Endian::Big).unwrap();
+        buf.write_string("Billy", Endian::Little).unwrap();
+        buf.write_num(-7i64, Endian::Little).unwrap();
+        buf.write_string("", Endian::Big).unwrap();
+        buf.write_num(15f32, Endian::Big).unwrap();
+        assert_eq!(&buf[..2], &[1, 2]);
+
+        let mut rdr = buf.as_slice();
+        assert_eq!(rdr.read_num::<u16>(Endian::Big).unwrap(), 0x0102);
+        assert_eq!(rdr.read_string(Endian::Little).unwrap(), "Billy");
+        assert_eq!(rdr.read_num::<i64>(Endian::Little).unwrap(), -7);
+        assert_eq!(rdr.read_string(Endian::Big).unwrap(), "");
+        assert_eq!(rdr.read_num::<f32>(Endian::Big).unwrap(), 15f32);
+        assert_eq!(rdr.read_num::<u8>(Endian::Big).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let mut short = Vec::new();
+        short.write_num(10u32, Endian::Big).unwrap();
+        short.extend_from_slice(b"abc");
+        assert_eq!(short.as_slice().read_string(Endian::Big).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let mut invalid = Vec::new();
+        invalid.write_num(1u32, Endian::Big).unwrap();
+        invalid.push(0xff);
+        assert_eq!(invalid.as_slice().read_string(Endian::Big).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn detach_on_drop() {
+        let net_msg = Builder::init()
+            .transport(Box::new(StuckTransport))
+            .non_blocking(false)
+            .detach_on_drop(true)
+            .start::<JSON>()
+            .unwrap();
+        thread::sleep(time::Duration::from_millis(50));
+
+        let started = time::Instant::now();
+        drop(net_msg);
+        assert!(started.elapsed() < time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn set_type_max_size() {
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = errors.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50150"))
+            .on_recv_error(move |e| seen.lock().unwrap().push(e.to_string()))
+            .start::<JSON>()
+            .unwrap();
+        net_msg.set_type_max_size::<UpdatePos>(64);
+        net_msg.set_type_max_size::<RenameObj>(16);
+
+        net_msg.send(UpdatePos{x: 15f32, y: 15f32, z: 15f32}, String::from("127.0.0.1:50150")).unwrap();
+        net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50150")).unwrap();
+        net_msg.send(RenameObj{name: "Billy".repeat(100)}, String::from("127.0.0.1:50150")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        assert_eq!(net_msg.drop_stats().malformed, 1);
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains(&format!("exceeds the limit of 16 bytes for id {}", net_msg.get_id::<RenameObj>())));
+        assert!(net_msg.get::<UpdatePos>().is_ok());
+        assert_eq!(net_msg.get::<RenameObj>().unwrap().1.name, "Billy");
+        assert!(net_msg.get::<RenameObj>().is_err());
+    }
+
+    #[test]
+    fn get_all_counted() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50151"))
+            .use_ids(false)
+            .start::<JSON>()
+            .unwrap();
+
+        net_msg.send(UpdatePos{x: 15f32, y: 15f32, z: 15f32}, String::from("127.0.0.1:50151")).unwrap();
+        net_msg.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50151")).unwrap();
+        net_msg.send(UpdatePos{x: 1f32, y: 2f32, z: 3f32}, String::from("127.0.0.1:50151")).unwrap();
+        net_msg.send(RenameObj{name: String::from("Bob")}, String::from("127.0.0.1:50151")).unwrap();
+        net_msg.send(RenameObj{name: String::from("Joe")}, String::from("127.0.0.1:50151")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let (names, discarded) = net_msg.get_all_counted::<RenameObj>();
+        let names: Vec<String> = names.into_iter().map(|(_, rename)| rename.name).collect();
+        assert_eq!(names, vec!["Billy", "Bob", "Joe"]);
+        assert_eq!(discarded, 2);
+
+        let (names, discarded) = net_msg.get_all_counted::<RenameObj>();
+        assert!(names.is_empty());
+        assert_eq!(discarded, 0);
+    }
+
+    #[test]
+    fn ignore_send_errors() {
+        let attempts = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let net_msg = Builder::init()
+            .transport(Box::new(FlakyTransport{failures: 2, kind: std::io::ErrorKind::ConnectionRefused, attempts: attempts.clone()}))
+            .ignore_send_errors(true)
+            .start::<JSON>()
+            .unwrap();
+
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        assert!(net_msg.send_ref(&pos, String::from("127.0.0.1:39507")).is_ok());
+        assert!(net_msg.send_ref(&pos, String::from("127.0.0.1:39507")).is_ok());
+        assert!(net_msg.send_ref(&pos, String::from("127.0.0.1:39507")).is_ok());
+        assert_eq!(net_msg.dropped_sends(), 2);
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let net_msg = Builder::init()
+            .transport(Box::new(FlakyTransport{failures: 1, kind: std::io::ErrorKind::ConnectionRefused, attempts: std::sync::Arc::default()}))
+            .start::<JSON>()
+            .unwrap();
+        assert!(net_msg.send_ref(&pos, String::from("127.0.0.1:39507")).is_err());
+        assert_eq!(net_msg.dropped_sends(), 1);
+    }
+
+    impl crate::msg_id::MsgId for UpdatePos {
+        const ID: u64 = 1000;
+    }
+
+    impl crate::msg_id::MsgId for RenameObj {
+        const ID: u64 = 1001;
+    }
+
+    #[test]
+    fn msg_id() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50152"))
+            .start::<JSON>()
+            .unwrap();
+        net_msg.set_id::<UpdatePos>(2000).unwrap();
+
+        net_msg.send_typed(&UpdatePos{x: 15f32, y: 15f32, z: 15f32}, String::from("127.0.0.1:50152")).unwrap();
+        net_msg.send_typed(&RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50152")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        assert!(net_msg.get::<UpdatePos>().is_err());
+        assert_eq!(net_msg.queue_depths(), vec![(1000, 1), (1001, 1)]);
+        assert_eq!(net_msg.get_typed::<UpdatePos>().unwrap().1.x, 15f32);
+        assert_eq!(net_msg.get_typed::<RenameObj>().unwrap().1.name, "Billy");
+        assert!(net_msg.get_typed::<RenameObj>().is_err());
+    }
+
+    #[test]
+    fn msg_id_known() {
+        let unknown = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let seen = unknown.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50153"))
+            .on_unknown(move |_, _, _| { seen.fetch_add(1, std::sync::atomic::Ordering::SeqCst); })
+            .get_retains_on_error(true)
+            .start::<JSON>()
+            .unwrap();
+        assert!(net_msg.get_typed::<UpdatePos>().is_err());
+        assert!(net_msg.get_typed::<RenameObj>().is_err());
+
+        let sender = Builder::init()
+            .socket(String::from("0.0.0.0:50154"))
+            .start::<JSON>()
+            .unwrap();
+        sender.send_typed(&UpdatePos{x: 15f32, y: 15f32, z: 15f32}, String::from("127.0.0.1:50153")).unwrap();
+        sender.set_id::<UpdatePos>(1001).unwrap();
+        sender.send(UpdatePos{x: 1f32, y: 1f32, z: 1f32}, String::from("127.0.0.1:50153")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        assert_eq!(unknown.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert_eq!(net_msg.get_typed::<UpdatePos>().unwrap().1.x, 15f32);
+        assert!(net_msg.get_typed::<RenameObj>().is_err());
+        assert!(net_msg.queue_depths().contains(&(1001, 1)));
+    }
+
+    #[test]
+    fn tag_in_body_unknown() {
+        let unknown = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = unknown.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50157"))
+            .tag_in_body(String::from("type"))
+            .on_unknown(move |id, _, _| seen.lock().unwrap().push(id))
+            .start::<JSON>()
+            .unwrap();
+        net_msg.get_id::<UpdatePos>();
+
+        let raw = std::net::UdpSocket::bind("0.0.0.0:50158").unwrap();
+        raw.send_to(br#"{"type":"Spoofed1","name":"a"}"#, "127.0.0.1:50157").unwrap();
+        raw.send_to(br#"{"type":"Spoofed1","name":"b"}"#, "127.0.0.1:50157").unwrap();
+        raw.send_to(br#"{"type":"UpdatePos","x":1.0,"y":2.0,"z":3.0}"#, "127.0.0.1:50157").unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let unknown = unknown.lock().unwrap();
+        assert_eq!(unknown.len(), 2);
+        assert_eq!(unknown[0], net_msg.get_id_by_name("Spoofed1"));
+        assert_eq!(net_msg.get::<UpdatePos>().unwrap().1.y, 2f32);
+    }
+
+    /// Creates a self-signed certificate and its private key, both PEM encoded
+    #[cfg(feature = "dtls")]
+    fn self_signed(name: &str) -> (Vec<u8>, Vec<u8>) {
+        use openssl::{asn1::Asn1Time, bn::BigNum, ec::{EcGroup, EcKey}, hash::MessageDigest, nid::Nid, pkey::PKey};
+        use openssl::x509::{X509, X509NameBuilder};
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+        cert.set_subject_name(&subject).unwrap();
+        cert.set_issuer_name(&subject).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        return (cert.build().to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "dtls")]
+    fn dtls() {
+        use crate::dtls::DtlsConfig;
+
+        let (server_cert, server_key) = self_signed("server");
+        let (client_cert, client_key) = self_signed("client");
+        let (rogue_cert, rogue_key) = self_signed("rogue");
+
+        let config = |certificate: &[u8], private_key: &[u8], trusted: &[u8]| DtlsConfig {
+            certificate: certificate.to_vec(),
+            private_key: private_key.to_vec(),
+            trusted: trusted.to_vec(),
+            handshake_timeout: time::Duration::from_secs(1),
+            max_handshakes: 16
+        };
+
+        let server = Builder::init()
+            .socket(String::from("127.0.0.1:50159"))
+            .dtls(config(&server_cert, &server_key, &client_cert))
+            .start::<JSON>()
+            .unwrap();
+        let client = Builder::init()
+            .socket(String::from("127.0.0.1:50160"))
+            .dtls(config(&client_cert, &client_key, &server_cert))
+            .start::<JSON>()
+            .unwrap();
+        let rogue = Builder::init()
+            .socket(String::from("127.0.0.1:50161"))
+            .dtls(config(&rogue_cert, &rogue_key, &server_cert))
+            .start::<JSON>()
+            .unwrap();
+
+        client.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50159")).unwrap();
+        rogue.send(RenameObj{name: String::from("Mallory")}, String::from("127.0.0.1:50159")).unwrap();
+        //The handshake takes several passes of the receiving threads
+        let deadline = time::Instant::now() + time::Duration::from_secs(2);
+        let (from, rename) = loop {
+            match server.get::<RenameObj>() {
+                Ok(received) => break received,
+                Err(_) if time::Instant::now() < deadline => thread::sleep(time::Duration::from_millis(10)),
+                Err(e) => panic!("{}", e)
+            }
+        };
+        assert_eq!(rename.name, "Billy");
+        assert_eq!(from.port(), 50160);
+
+        server.send(UpdatePos{x: 15f32, y: 15f32, z: 15f32}, from).unwrap();
+        thread::sleep(time::Duration::from_millis(200));
+        assert_eq!(client.get::<UpdatePos>().unwrap().1.x, 15f32);
+
+        //The datagram arrived encrypted, and the rogue peer's was never accepted
+        let raw = std::net::UdpSocket::bind("127.0.0.1:50162").unwrap();
+        raw.send_to(br#"{"name":"Plain"}"#, "127.0.0.1:50159").unwrap();
+        thread::sleep(time::Duration::from_millis(200));
+        assert!(server.get::<RenameObj>().is_err());
+    }
+
+    #[test]
+    fn per_sender_queues() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50165"))
+            .per_sender_queues(true)
+            .start::<JSON>()
+            .unwrap();
+        let senders: Vec<_> = ["0.0.0.0:50166", "0.0.0.0:50167"].iter().map(|addr| Builder::init()
+            .socket(String::from(*addr))
+            .start::<JSON>()
+            .unwrap()).collect();
+
+        //Each datagram is synced before the next so their arrival order is known
+        for i in 0..4 {
+            for (sender, offset) in senders.iter().zip([0f32, 100f32]) {
+                sender.send(UpdatePos{x: offset + i as f32, y: 0f32, z: 0f32}, String::from("127.0.0.1:50165")).unwrap();
+                assert!(net_msg.sync(time::Duration::from_secs(1)));
+            }
+        }
+
+        let first: std::net::SocketAddr = "127.0.0.1:50166".parse().unwrap();
+        let second: std::net::SocketAddr = "127.0.0.1:50167".parse().unwrap();
+        for i in 0..2 {
+            assert_eq!(net_msg.get_from::<UpdatePos>(second).unwrap().x, 100f32 + i as f32);
+        }
+        assert_eq!(net_msg.peek_from::<UpdatePos>(second).unwrap().x, 102f32);
+
+        //get provides the oldest datagram across senders
+        let expected = [(first, 0f32), (first, 1f32), (first, 2f32), (second, 102f32), (first, 3f32), (second, 103f32)];
+        for (addr, x) in expected {
+            let (from, pos) = net_msg.get::<UpdatePos>().unwrap();
+            assert_eq!((from, pos.x), (addr, x));
+        }
+        assert!(net_msg.get_from::<UpdatePos>(first).is_err());
+        assert!(net_msg.get::<UpdatePos>().is_err());
+    }
+
+    #[test]
+    fn was_poisoned_by_subscriber() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50168"))
+            .start::<JSON>()
+            .unwrap();
+
+        //The listener thread panics while delivering to the subscriber, poisoning the subscribers
+        let _rx = net_msg.subscribe::<PanicsOnDecode>();
+        net_msg.forward(net_msg.get_id::<PanicsOnDecode>(), b"{}", String::from("127.0.0.1:50168")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let _rx = net_msg.subscribe::<UpdatePos>();
+        assert!(net_msg.was_poisoned());
+    }
+
+    #[test]
+    fn version_mismatch_checks_checksum() {
+        let mismatched = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = mismatched.clone();
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50169"))
+            .protocol_version(2)
+            .checksum(true)
+            .on_version_mismatch(move |_, datagram| seen.lock().unwrap().push(datagram))
+            .start::<JSON>()
+            .unwrap();
+        let old = Builder::init()
+            .socket(String::from("0.0.0.0:50170"))
+            .protocol_version(1)
+            .checksum(true)
+            .start::<JSON>()
+            .unwrap();
+
+        //A corrupted datagram is dropped for its checksum even though its first byte is not the version
+        let mut corrupted = old.encode(&RenameObj{name: String::from("Old")}).unwrap();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        old.socket().unwrap().send_to(&corrupted, "127.0.0.1:50169").unwrap();
+        old.send(RenameObj{name: String::from("Old")}, String::from("127.0.0.1:50169")).unwrap();
+        assert!(net_msg.sync(time::Duration::from_secs(1)));
+
+        let mismatched = mismatched.lock().unwrap();
+        assert_eq!(mismatched.len(), 1);
+        assert_eq!(mismatched[0][0], 1);
+        assert_eq!(net_msg.drop_stats().checksum, 1);
+        assert_eq!(net_msg.drop_stats().version, 1);
+    }
+
+    #[test]
+    fn recv_one_releases_socket_mode() {
+        let client = Builder::init()
+            .socket(String::from("0.0.0.0:50171"))
+            .manual_poll(true)
+            .start::<JSON>()
+            .unwrap();
+        let server = Builder::init()
+            .socket(String::from("0.0.0.0:50172"))
+            .start::<JSON>()
+            .unwrap();
+
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| client.recv_one::<UpdatePos>(time::Instant::now() + time::Duration::from_secs(2)));
+            thread::sleep(time::Duration::from_millis(100));
+
+            //The mode can be changed while recv_one waits, and is restored once it returns
+            let started = time::Instant::now();
+            client.set_read_timeout(Some(time::Duration::from_secs(1))).unwrap();
+            assert!(started.elapsed() < time::Duration::from_millis(500));
+
+            server.send(UpdatePos{x: 1f32, y: 2f32, z: 3f32}, String::from("127.0.0.1:50171")).unwrap();
+            assert_eq!(waiting.join().unwrap().unwrap().1.y, 2f32);
+        });
+        assert_eq!(client.socket().unwrap().read_timeout().unwrap(), Some(time::Duration::from_secs(1)));
+    }
+
+    #[test]
+    #[cfg(feature = "dtls")]
+    fn dtls_max_handshakes() {
+        use crate::dtls::{DtlsConfig, DtlsTransport};
+        use crate::transport::Transport;
+
+        let (server_cert, server_key) = self_signed("server");
+        let (client_cert, client_key) = self_signed("client");
+
+        let config = |certificate: &[u8], private_key: &[u8], trusted: &[u8], handshake_timeout: u64| DtlsConfig {
+            certificate: certificate.to_vec(),
+            private_key: private_key.to_vec(),
+            trusted: trusted.to_vec(),
+            handshake_timeout: time::Duration::from_secs(handshake_timeout),
+            max_handshakes: 1
+        };
+
+        let server = Builder::init()
+            .socket(String::from("127.0.0.1:50173"))
+            .dtls(config(&server_cert, &server_key, &client_cert, 1))
+            .start::<JSON>()
+            .unwrap();
+
+        //Starts a handshake with the server and never answers it
+        let stalled = std::net::UdpSocket::bind("127.0.0.1:50174").unwrap();
+        let stalled = DtlsTransport::new(stalled, &config(&client_cert, &client_key, &server_cert, 5)).unwrap();
+        stalled.send_to(b"stalled", "127.0.0.1:50173".parse().unwrap()).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+
+        let client = Builder::init()
+            .socket(String::from("127.0.0.1:50175"))
+            .dtls(config(&client_cert, &client_key, &server_cert, 5))
+            .start::<JSON>()
+            .unwrap();
+        client.send(RenameObj{name: String::from("Billy")}, String::from("127.0.0.1:50173")).unwrap();
+        thread::sleep(time::Duration::from_millis(500));
+        assert!(server.get::<RenameObj>().is_err());
+
+        //The client's retransmitted hello is accepted once the stalled handshake is abandoned
+        let deadline = time::Instant::now() + time::Duration::from_secs(5);
+        let (from, rename) = loop {
+            match server.get::<RenameObj>() {
+                Ok(received) => break received,
+                Err(_) if time::Instant::now() < deadline => thread::sleep(time::Duration::from_millis(10)),
+                Err(e) => panic!("{}", e)
+            }
+        };
+        assert_eq!(rename.name, "Billy");
+        assert_eq!(from.port(), 50175);
+    }
+
+    #[test]
+    fn send_builder_reserved_id() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50176"))
+            .start::<JSON>()
+            .unwrap();
+
+        let name = RenameObj{name: String::from("Bob")};
+        let e = net_msg.send_builder(name)
+            .id(3)
+            .to("127.0.0.1:50176")
+            .send()
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(net_msg.get_next_any().is_none());
+    }
+
+    #[test]
+    fn forward_reserved_id() {
+        let net_msg = Builder::init()
+            .socket(String::from("0.0.0.0:50177"))
+            .start::<JSON>()
+            .unwrap();
+
+        let payload = serde_json::to_vec(&RenameObj{name: String::from("Billy")}).unwrap();
+        let e = net_msg.forward(2, &payload, String::from("127.0.0.1:50177")).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+        thread::sleep(time::Duration::from_millis(100));
+
+        assert!(net_msg.get_next_any().is_none());
+    }
+}
+
+#[cfg(all(test, feature = "bincode"))]
+mod bincode_format {
+    use crate::prelude::*;
+    use serde::{Serialize, Deserialize};
+    use std::{thread, time};
+
+    #[derive(Serialize, Deserialize)]
+    struct UpdatePos {
+        pub x: f32,
+        pub y: f32,
+        pub z: f32
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RenameObj {
+        pub name: String
+    }
+
     #[test]
     fn bincode_serdes() {
         let net_msg = Builder::init()
@@ -65,6 +3109,25 @@ mod struct_creation {
 
         net_msg.get::<UpdatePos>().unwrap();
     }
+}
+
+#[cfg(all(test, feature = "yaml"))]
+mod yaml_format {
+    use crate::prelude::*;
+    use serde::{Serialize, Deserialize};
+    use std::{thread, time};
+
+    #[derive(Serialize, Deserialize)]
+    struct UpdatePos {
+        pub x: f32,
+        pub y: f32,
+        pub z: f32
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RenameObj {
+        pub name: String
+    }
 
     #[test]
     fn yaml_serdes() {
@@ -129,103 +3192,52 @@ mod struct_creation {
     }
 
     #[test]
-    fn no_ids_succeed() {
-        let net_msg = Builder::init()
-            .socket(String::from("0.0.0.0:50005"))
-            .use_ids(false)
-            .start::<JSON>()
-            .unwrap(); 
-
-        let name = RenameObj{name: String::from("Billy")};
-        net_msg.send(name, String::from("127.0.0.1:50005")).unwrap();
-
-        thread::sleep(time::Duration::from_millis(100));
-
-        net_msg.get::<RenameObj>().unwrap();
-    }
-
-    #[test]
-    #[should_panic]
-    fn no_ids_panic() {
+    fn remove_all() {
         let net_msg = Builder::init()
-            .socket(String::from("0.0.0.0:50006"))
-            .use_ids(false)
-            .start::<JSON>()
+            .socket(String::from("0.0.0.0:50009"))
+            .start::<YAML>()
             .unwrap(); 
 
         let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
-        net_msg.send(pos, String::from("127.0.0.1:50006")).unwrap();
-        let name = RenameObj{name: String::from("Billy")};
-        net_msg.send(name, String::from("127.0.0.1:50006")).unwrap();
-
-        thread::sleep(time::Duration::from_millis(100));
-
-        net_msg.get::<RenameObj>().unwrap();
-    }
-
-    #[test]
-    fn fail_des_keep_item() {
-        let net_msg = Builder::init()
-            .socket(String::from("0.0.0.0:50007"))
-            .use_ids(false)
-            .start::<JSON>()
-            .unwrap(); 
-
+        net_msg.send(pos, String::from("127.0.0.1:50009")).unwrap();
         let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
-        net_msg.send(pos, String::from("127.0.0.1:50007")).unwrap();
+        net_msg.send(pos, String::from("127.0.0.1:50009")).unwrap();
+        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
+        net_msg.send(pos, String::from("127.0.0.1:50009")).unwrap();
 
         thread::sleep(time::Duration::from_millis(100));
 
-        match net_msg.peek::<RenameObj>() {
-            Ok(_) => panic!("Should not have serialized correctly"),
-            Err(_) => {}
-        }
+        net_msg.remove_all::<UpdatePos>().unwrap();
 
-        net_msg.peek::<UpdatePos>().unwrap();
+        assert_eq!(net_msg.get_all::<UpdatePos>().unwrap().len(), 0);
     }
 
     #[test]
-    fn remove_front() {
-        let net_msg = Builder::init()
-            .socket(String::from("0.0.0.0:50008"))
-            .use_ids(false)
-            .start::<JSON>()
-            .unwrap(); 
+    fn forward() {
+        let relay = Builder::init()
+            .socket(String::from("0.0.0.0:50021"))
+            .preserve_global_order(true)
+            .start::<YAML>()
+            .unwrap();
+        let dest = Builder::init()
+            .socket(String::from("0.0.0.0:50022"))
+            .preserve_global_order(true)
+            .start::<YAML>()
+            .unwrap();
 
-        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
-        net_msg.send(pos, String::from("127.0.0.1:50008")).unwrap();
         let name = RenameObj{name: String::from("Billy")};
-        net_msg.send(name, String::from("127.0.0.1:50008")).unwrap();
+        relay.send(name, String::from("127.0.0.1:50021")).unwrap();
 
         thread::sleep(time::Duration::from_millis(100));
 
-        match net_msg.peek::<RenameObj>() {
-            Ok(_) => panic!("Should not have serialized correctly"),
-            Err(_) => {}
-        }
-        net_msg.remove_front::<RenameObj>().unwrap();
-        net_msg.peek::<RenameObj>().unwrap();
-    }
-
-    #[test]
-    fn remove_all() {
-        let net_msg = Builder::init()
-            .socket(String::from("0.0.0.0:50009"))
-            .start::<YAML>()
-            .unwrap(); 
-
-        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
-        net_msg.send(pos, String::from("127.0.0.1:50009")).unwrap();
-        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
-        net_msg.send(pos, String::from("127.0.0.1:50009")).unwrap();
-        let pos = UpdatePos{x: 15f32, y: 15f32, z: 15f32};
-        net_msg.send(pos, String::from("127.0.0.1:50009")).unwrap();
+        let (id, _, payload) = relay.get_next_any().unwrap();
+        relay.forward(id, &payload, String::from("127.0.0.1:50022")).unwrap();
 
         thread::sleep(time::Duration::from_millis(100));
 
-        net_msg.remove_all::<UpdatePos>().unwrap();
-
-        assert_eq!(net_msg.get_all::<UpdatePos>().unwrap().len(), 0);
+        let (forwarded_id, _, forwarded) = dest.get_next_any().unwrap();
+        assert_eq!(forwarded_id, id);
+        assert_eq!(forwarded, payload);
+        assert_eq!(dest.decode_payload::<RenameObj>(&forwarded).unwrap().name, "Billy");
     }
 }
-
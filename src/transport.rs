@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Implemented on anything able to carry datagrams for a UdpManager (see Builder::transport).
+/// 
+/// The manager uses UdpSocket by default. Other transports, such as InMemoryTransport, allow
+/// managers to be tested without the network.
+pub trait Transport: Send + Sync {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, std::io::Error>;
+
+    /// Receives a single datagram. Must return WouldBlock or TimedOut rather than wait forever
+    /// when non-blocking or a read timeout is set, so the background thread can stop.
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), std::io::Error>;
+
+    fn local_addr(&self) -> Result<SocketAddr, std::io::Error>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error>;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), std::io::Error>;
+
+    /// Provides the UdpSocket behind the transport, if there is one
+    fn as_udp_socket(&self) -> Option<&UdpSocket> {
+        return None;
+    }
+
+    /// Receives a single datagram like recv_from, along with the TTL (or IPv6 hop limit) it arrived
+    /// with when the transport can tell. The default receives with recv_from and provides None.
+    fn recv_from_ttl(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr, Option<u8>), std::io::Error> {
+        let (len, addr) = self.recv_from(buf)?;
+        return Ok((len, addr, None));
+    }
+
+    /// Receives up to one datagram per buffer, returning the length and sender of each. Waits like
+    /// recv_from for the first datagram only. The default receives a single datagram with recv_from.
+    fn recv_batch(&self, bufs: &mut [Vec<u8>]) -> Result<Vec<(usize, SocketAddr)>, std::io::Error> {
+        return Ok(vec![self.recv_from(&mut bufs[0])?]);
+    }
+}
+
+impl Transport for UdpSocket {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, std::io::Error> {
+        return UdpSocket::send_to(self, buf, addr);
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), std::io::Error> {
+        return UdpSocket::recv_from(self, buf);
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        return UdpSocket::local_addr(self);
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        return UdpSocket::set_read_timeout(self, timeout);
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), std::io::Error> {
+        return UdpSocket::set_nonblocking(self, nonblocking);
+    }
+
+    fn as_udp_socket(&self) -> Option<&UdpSocket> {
+        return Some(self);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn recv_from_ttl(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr, Option<u8>), std::io::Error> {
+        return recvmsg_ttl(self, buf);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn recv_batch(&self, bufs: &mut [Vec<u8>]) -> Result<Vec<(usize, SocketAddr)>, std::io::Error> {
+        return recvmmsg(self, bufs);
+    }
+}
+
+/// Asks the kernel to attach the TTL (or IPv6 hop limit) to every datagram received on the socket.
+#[cfg(target_os = "linux")]
+pub(crate) fn enable_recv_ttl(udp: &UdpSocket) -> Result<(), std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name) = match udp.local_addr()? {
+        SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_RECVTTL),
+        SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT)
+    };
+    let enable: libc::c_int = 1;
+    let result = unsafe {
+        libc::setsockopt(udp.as_raw_fd(), level, name, &enable as *const libc::c_int as *const libc::c_void, 
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t)
+    };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+/// The TTL is not captured on other platforms, so received datagrams report None.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn enable_recv_ttl(_udp: &UdpSocket) -> Result<(), std::io::Error> {
+    return Ok(());
+}
+
+/// Receives a single datagram with recvmsg, reading its TTL from the control message attached by 
+/// enable_recv_ttl.
+#[cfg(target_os = "linux")]
+fn recvmsg_ttl(udp: &UdpSocket, buf: &mut [u8]) -> Result<(usize, SocketAddr, Option<u8>), std::io::Error> {
+    use std::convert::TryFrom;
+    use std::os::unix::io::AsRawFd;
+
+    let mut iovec = libc::iovec { iov_base: buf.as_mut_ptr() as *mut libc::c_void, iov_len: buf.len() };
+    let mut name: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    //Aligned like the control message headers, with room for a few of them
+    let mut control = [0u64; 16];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = &mut name as *mut libc::sockaddr_storage as *mut libc::c_void;
+    msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iovec;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    let len = unsafe { libc::recvmsg(udp.as_raw_fd(), &mut msg, 0) };
+    if len < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let addr = unsafe { socket2::SockAddr::new(name, msg.msg_namelen) }.as_socket()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "Received a datagram from a non-IP address"))?;
+
+    let mut ttl = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let (level, kind) = ((*cmsg).cmsg_level, (*cmsg).cmsg_type);
+            if (level == libc::IPPROTO_IP && kind == libc::IP_TTL) || (level == libc::IPPROTO_IPV6 && kind == libc::IPV6_HOPLIMIT) {
+                let value = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                ttl = u8::try_from(value).ok();
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    return Ok((len as usize, addr, ttl));
+}
+
+/// Receives several datagrams with a single recvmmsg system call.
+#[cfg(target_os = "linux")]
+fn recvmmsg(udp: &UdpSocket, bufs: &mut [Vec<u8>]) -> Result<Vec<(usize, SocketAddr)>, std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut iovecs: Vec<libc::iovec> = bufs.iter_mut()
+        .map(|buf| libc::iovec { iov_base: buf.as_mut_ptr() as *mut libc::c_void, iov_len: buf.len() })
+        .collect();
+    let mut names: Vec<libc::sockaddr_storage> = (0..bufs.len())
+        .map(|_| unsafe { std::mem::zeroed() })
+        .collect();
+    let mut msgs: Vec<libc::mmsghdr> = iovecs.iter_mut().zip(names.iter_mut())
+        .map(|(iovec, name)| {
+            let mut msg: libc::mmsghdr = unsafe { std::mem::zeroed() };
+            msg.msg_hdr.msg_name = name as *mut libc::sockaddr_storage as *mut libc::c_void;
+            msg.msg_hdr.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            msg.msg_hdr.msg_iov = iovec;
+            msg.msg_hdr.msg_iovlen = 1;
+            return msg;
+        })
+        .collect();
+
+    //MSG_WAITFORONE returns once a datagram has arrived instead of waiting to fill every buffer
+    let received = unsafe {
+        libc::recvmmsg(udp.as_raw_fd(), msgs.as_mut_ptr(), msgs.len() as libc::c_uint, libc::MSG_WAITFORONE, std::ptr::null_mut())
+    };
+    if received < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut datagrams = Vec::with_capacity(received as usize);
+    for (msg, name) in msgs.iter().zip(names.iter()).take(received as usize) {
+        let addr = unsafe { socket2::SockAddr::new(*name, msg.msg_hdr.msg_namelen) }.as_socket()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "Received a datagram from a non-IP address"))?;
+        datagrams.push((msg.msg_len as usize, addr));
+    }
+    return Ok(datagrams);
+}
+
+type Inbox = mpsc::Sender<(Vec<u8>, SocketAddr)>;
+
+/// Routes datagrams between InMemoryTransports bound to it, keyed by fake addresses.
+#[derive(Clone, Default)]
+pub struct InMemoryNetwork {
+    inboxes: Arc<Mutex<HashMap<SocketAddr, Inbox>>>
+}
+
+impl InMemoryNetwork {
+    pub fn new() -> InMemoryNetwork {
+        return InMemoryNetwork::default();
+    }
+
+    /// Creates a transport reachable at the address. Binding an address twice replaces the 
+    /// previous transport.
+    /// 
+    /// # Panics
+    /// 
+    /// This will panic if the lock becomes poisioned.
+    pub fn bind(&self, addr: SocketAddr) -> InMemoryTransport {
+        let (inbox, received) = mpsc::channel();
+        self.inboxes.lock().unwrap().insert(addr, inbox);
+
+        return InMemoryTransport {
+            addr,
+            network: self.clone(),
+            received: Mutex::from(received),
+            nonblocking: Mutex::from(true),
+            read_timeout: Mutex::from(None)
+        };
+    }
+}
+
+/// Transport that delivers datagrams through memory instead of the network. Created with 
+/// InMemoryNetwork::bind.
+/// 
+/// Like UDP, datagrams sent to an address nobody is bound to are silently lost.
+pub struct InMemoryTransport {
+    addr: SocketAddr,
+    network: InMemoryNetwork,
+    received: Mutex<mpsc::Receiver<(Vec<u8>, SocketAddr)>>,
+    nonblocking: Mutex<bool>,
+    read_timeout: Mutex<Option<Duration>>
+}
+
+impl Transport for InMemoryTransport {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, std::io::Error> {
+        if let Some(inbox) = self.network.inboxes.lock().unwrap().get(&addr) {
+            let _ = inbox.send((buf.to_vec(), self.addr));
+        }
+        return Ok(buf.len());
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), std::io::Error> {
+        let received = self.received.lock().unwrap();
+
+        let datagram = if *self.nonblocking.lock().unwrap() {
+            received.try_recv().map_err(|_| std::io::Error::from(ErrorKind::WouldBlock))
+        } 
+        else {
+            match *self.read_timeout.lock().unwrap() {
+                Some(timeout) => received.recv_timeout(timeout).map_err(|_| std::io::Error::from(ErrorKind::TimedOut)),
+                None => received.recv().map_err(|_| std::io::Error::from(ErrorKind::NotConnected))
+            }
+        };
+
+        //Like UDP, the part of a datagram that does not fit in the buffer is discarded
+        let (datagram, addr) = datagram?;
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+        return Ok((len, addr));
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        return Ok(self.addr);
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "cannot set a 0 duration timeout"));
+        }
+        *self.read_timeout.lock().unwrap() = timeout;
+        return Ok(());
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), std::io::Error> {
+        *self.nonblocking.lock().unwrap() = nonblocking;
+        return Ok(());
+    }
+}
+
+/// Stands in for the sockets of a manager after shutdown, failing every operation.
+pub(crate) struct Closed;
+
+impl Transport for Closed {
+    fn send_to(&self, _buf: &[u8], _addr: SocketAddr) -> Result<usize, std::io::Error> {
+        return Err(std::io::Error::new(ErrorKind::NotConnected, "The manager has been shut down"));
+    }
+
+    fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, SocketAddr), std::io::Error> {
+        return Err(std::io::Error::new(ErrorKind::NotConnected, "The manager has been shut down"));
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        return Err(std::io::Error::new(ErrorKind::NotConnected, "The manager has been shut down"));
+    }
+
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        return Ok(());
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> Result<(), std::io::Error> {
+        return Ok(());
+    }
+}
@@ -6,12 +6,6 @@ pub struct ThreadSafe<T> {
 }
 
 impl <T>ThreadSafe<T> {
-    pub fn lock(&self)->Result<std::sync::MutexGuard<'_, T>,
-    std::sync::PoisonError<std::sync::MutexGuard<'_, T>>>
-    {
-        return self.obj.lock()
-    }
-
     pub fn clone(&self)->ThreadSafe<T> {
         return ThreadSafe{obj: self.obj.clone()}
     }
@@ -19,5 +13,9 @@ impl <T>ThreadSafe<T> {
     pub fn from(obj: T)->ThreadSafe<T> {
         return ThreadSafe{obj: Arc::from(Mutex::from(obj))}
     }
+
+    pub fn mutex(&self)->&Mutex<T> {
+        return &self.obj
+    }
 }
 